@@ -0,0 +1,42 @@
+//! Backs the cost figures in the `ChunkMesh::rebuild` docs. The GPU upload isn't included, so
+//! these only measure building the mesh data. Run with `cargo bench --bench meshing`.
+
+use cgmath::{Vector2, Vector3};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use wgpu_voxel_game::block::Block;
+use wgpu_voxel_game::chunk::{Chunk, ChunkMeshData, Direction, MAX_LIGHT, MIN_Y};
+use wgpu_voxel_game::registry::TextureTable;
+
+/// A chunk whose lower half is solid stone
+fn half_full_chunk() -> Chunk {
+    let mut chunk = Chunk::new(Vector2::new(0, 0));
+    chunk.fill_region(Vector3::new(0, MIN_Y, 0), Vector3::new(15, -1, 15), Block::new_stone());
+    chunk
+}
+
+fn meshing(c: &mut Criterion) {
+    let chunk = half_full_chunk();
+    let neighbors = [Some(&chunk); 4];
+    let textures = TextureTable::default();
+
+    c.bench_function("rebuild a half full chunk", |b| {
+        b.iter(|| {
+            let mut data = ChunkMeshData::from_chunk(black_box(&chunk), neighbors, &textures);
+            data.compact();
+            data
+        })
+    });
+
+    let mut data = ChunkMeshData::from_chunk(&chunk, neighbors, &textures);
+    let (position, stone) = (Vector3::new(8, 0, 8), Block::new_stone());
+
+    c.bench_function("add and remove a single face", |b| {
+        b.iter(|| {
+            data.add_face(black_box(position), &Direction::TOP, &stone, MAX_LIGHT, &textures);
+            data.remove_face(black_box(position), &Direction::TOP);
+        })
+    });
+}
+
+criterion_group!(benches, meshing);
+criterion_main!(benches);
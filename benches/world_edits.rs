@@ -0,0 +1,51 @@
+//! Checks that the cost of `World::set_block` doesn't grow with the number of loaded chunks. The
+//! world needs a device for its meshes, so this does nothing without a GPU adapter. Run with
+//! `cargo bench --bench world_edits`.
+
+use std::path::Path;
+
+use cgmath::{Vector2, Vector3};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use wgpu_voxel_game::block::Block;
+use wgpu_voxel_game::chunk::TEXTURE_SIZE;
+use wgpu_voxel_game::registry::TextureTable;
+use wgpu_voxel_game::renderer::Renderer;
+use wgpu_voxel_game::texture::TextureArray;
+use wgpu_voxel_game::uniforms::ChunkUniforms;
+use wgpu_voxel_game::world::World;
+
+fn set_block(c: &mut Criterion) {
+    let renderer = match Renderer::new_headless(64, 64, wgpu::TextureFormat::Rgba8UnormSrgb) {
+        Ok(renderer) => renderer,
+        Err(e) => {
+            eprintln!("Skipping the world edit benchmarks, there's no adapter to run them on: {}", e);
+            return;
+        }
+    };
+    let (device, queue) = (&renderer.device, &renderer.queue);
+
+    let mut group = c.benchmark_group("set_block");
+    for side in [1, 5, 10] {
+        let locations = (0..side)
+            .flat_map(|x| (0..side).map(move |z| Vector2::new(x, z)))
+            .collect::<Vec<_>>();
+
+        let texture = TextureArray::new(Path::new("sprite_atlas.png"), TEXTURE_SIZE as u32, device, queue);
+        let mut world = World::new(TextureTable::default(), ChunkUniforms::new(texture, locations.len() as u32, device));
+        world.generate_chunks_parallel(&locations, device, queue, |_, position| {
+            if position.y < 0 { Block::new_stone() } else { Block::new_air() }
+        });
+
+        let (location, position) = (Vector2::new(0, 0), Vector3::new(8, 0, 8));
+        group.bench_with_input(BenchmarkId::new("chunks", locations.len()), &locations.len(), |b, _| {
+            b.iter(|| {
+                world.set_block(location, position, Block::new_stone());
+                world.set_block(location, position, Block::new_air());
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, set_block);
+criterion_main!(benches);
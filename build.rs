@@ -8,10 +8,11 @@ fn main() -> Result<()> {
     println!("cargo:rerun-if-changed=res/*");
 
     let out_dir = env::var("OUT_DIR")?;
+    // Start from a clean copy, so files removed from res/ don't linger
+    let _ = std::fs::remove_dir_all(std::path::Path::new(&out_dir).join("res"));
     let mut copy_options = CopyOptions::new();
     copy_options.overwrite = true;
-    let mut paths_to_copy = Vec::new();
-    paths_to_copy.push("res/");
+    let paths_to_copy = vec!["res/"];
     copy_items(&paths_to_copy, out_dir, &copy_options)?;
 
     Ok(())
@@ -157,6 +157,18 @@ impl ToTokens for ImplBlock {
     }
 }
 
+/// Turns a variant name like `OakLog` into `oak_log` for its constructor
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
 pub fn expand_trait_enum(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as TraitEnum);
 
@@ -188,7 +200,7 @@ pub fn expand_trait_enum(input: TokenStream) -> TokenStream {
     let mut struct_construct_pattern = Vec::new();
     // Get fields from struct
     for (data, name) in struct_data.iter().zip(struct_name.clone()) {
-        struct_construct_name.push(format_ident!("new_{}", name.to_string().to_lowercase()));
+        struct_construct_name.push(format_ident!("new_{}", to_snake_case(&name.to_string())));
         let mut idents = Vec::new();
         let mut types = Vec::new();
         match data {
@@ -1,22 +1,30 @@
-#![allow(dead_code)]
-use std::ops::{Deref, DerefMut, Div, Mul};
+use std::fmt::Write;
+use std::ops::{Deref, DerefMut};
 
-use cgmath::{ElementWise, Vector2};
+use cgmath::{InnerSpace, Vector2, Vector3};
+use hashbrown::HashMap;
 
-use crate::chunk;
+use crate::chunk::{self, Direction};
+use crate::registry::TextureTable;
+use crate::tags::BlockTags;
+use crate::texture;
+use crate::world::{Aabb, WorldAccess};
 use macros::trait_enum;
+use serde::{Deserialize, Serialize};
 
-pub struct TexCoordConfig {
-    pub front: Vector2<f32>,
-    pub back: Vector2<f32>,
-    pub top: Vector2<f32>,
-    pub bottom: Vector2<f32>,
-    pub left: Vector2<f32>,
-    pub right: Vector2<f32>,
+/// The texture array layer used for each face of a block
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextureLayers {
+    pub front: u32,
+    pub back: u32,
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
 }
 
-impl TexCoordConfig {
-    pub fn all_same(value: Vector2<f32>) -> Self {
+impl TextureLayers {
+    pub fn all_same(value: u32) -> Self {
         Self {
             front: value,
             back: value,
@@ -27,7 +35,7 @@ impl TexCoordConfig {
         }
     }
 
-    pub fn top_bottom_sides(top: Vector2<f32>, bottom: Vector2<f32>, sides: Vector2<f32>) -> Self {
+    pub fn top_bottom_sides(top: u32, bottom: u32, sides: u32) -> Self {
         Self {
             front: sides,
             back: sides,
@@ -38,77 +46,1235 @@ impl TexCoordConfig {
         }
     }
 
+    /// Takes each face's layer from the map, or `None` if a face is missing
+    pub fn from_faces(faces: &HashMap<Direction, u32>) -> Option<Self> {
+        Some(Self {
+            front: *faces.get(&Direction::FRONT)?,
+            back: *faces.get(&Direction::BACK)?,
+            top: *faces.get(&Direction::TOP)?,
+            bottom: *faces.get(&Direction::BOTTOM)?,
+            left: *faces.get(&Direction::LEFT)?,
+            right: *faces.get(&Direction::RIGHT)?,
+        })
+    }
+
     pub fn zero() -> Self {
+        Self::all_same(0)
+    }
+
+    /// The layer holding the tile at pixel `(x, y)` of the old sprite atlas
+    pub fn atlas(x: u32, y: u32) -> u32 {
+        texture::atlas_layer(x, y, chunk::TEXTURE_SIZE as u32, chunk::ATLAS_SIZE as u32)
+    }
+
+    /// The layer holding the tile in column `x`, row `y` of the atlas, so definitions don't
+    /// depend on `TEXTURE_SIZE`. Panics if the cell is outside of the atlas.
+    pub fn cell((x, y): (u32, u32)) -> u32 {
+        let (tile_size, atlas_size) = (chunk::TEXTURE_SIZE as u32, chunk::ATLAS_SIZE as u32);
+        let tiles = atlas_size / tile_size;
+        assert!(x < tiles && y < tiles, "atlas cell ({}, {}) is outside of the {}x{} tile atlas", x, y, tiles, tiles);
+
+        TextureLayers::atlas(x * tile_size, y * tile_size)
+    }
+
+    /// Like `top_bottom_sides`, with each face given as an atlas cell
+    pub fn from_indices(top: (u32, u32), bottom: (u32, u32), sides: (u32, u32)) -> Self {
+        Self::top_bottom_sides(Self::cell(top), Self::cell(bottom), Self::cell(sides))
+    }
+
+    /// Every face uses the same atlas cell
+    pub fn all_index(cell: (u32, u32)) -> Self {
+        Self::all_same(Self::cell(cell))
+    }
+
+    pub fn get(&self, face: &Direction) -> u32 {
+        match face {
+            Direction::FRONT => self.front,
+            Direction::BACK => self.back,
+            Direction::TOP => self.top,
+            Direction::BOTTOM => self.bottom,
+            Direction::LEFT => self.left,
+            Direction::RIGHT => self.right,
+        }
+    }
+}
+
+/// A texture that cycles through `frames` tiles, each one right below the one before it in the
+/// atlas, showing each for `ticks_per_frame` ticks of `TextureAnimation::TICK` seconds. The
+/// layer a face is given is the first frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextureAnimation {
+    pub frames: u32,
+    pub ticks_per_frame: u32,
+}
+
+impl TextureAnimation {
+    /// How long a tick lasts, in seconds
+    pub const TICK: f32 = 0.1;
+    /// Both values are packed into 4 bits of every vertex
+    pub const MAX_FRAMES: u32 = 16;
+    pub const MAX_TICKS_PER_FRAME: u32 = 16;
+
+    /// A texture that doesn't move
+    pub const STILL: TextureAnimation = TextureAnimation { frames: 1, ticks_per_frame: 1 };
+}
+
+impl Default for TextureAnimation {
+    fn default() -> Self {
+        TextureAnimation::STILL
+    }
+}
+
+/// What a block sounds like when it's walked on, broken or placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundCategory {
+    Grass,
+    Stone,
+    Wood,
+    Sand,
+    Glass,
+    /// Makes no sound, like air
+    None,
+}
+
+/// A color a block's faces can be multiplied with. Vertices only store the tint's index, the
+/// shader looks the color up in `Tint::PALETTE`, which fits up to `Tint::MAX_COUNT` of them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Tint {
+    /// White, which leaves the texture as it is
+    #[default]
+    None,
+    /// The green grass tops are tinted with. It's applied to linear colors, so it brings the grey
+    /// texture back to the green it was painted as.
+    Grass,
+}
+
+impl Tint {
+    pub const ALL: [Tint; 2] = [Tint::None, Tint::Grass];
+    /// How many tints fit in the bits a vertex has for them
+    pub const MAX_COUNT: usize = 128;
+    /// The color of every tint, in index order. The shader's tint palette has the same length.
+    pub const PALETTE: [[f32; 4]; 2] = [[1.0; 4], [0.25, 0.892, 0.051, 1.0]];
+
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+
+    pub fn from_index(index: u32) -> Option<Tint> {
+        Tint::ALL.get(index as usize).copied()
+    }
+}
+
+/// The axis a block like a log runs along
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Axis {
+    X,
+    #[default]
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+
+    /// The axis a face points along, which is the way a block placed against it should run
+    pub fn of_face(face: &Direction) -> Self {
+        match face {
+            Direction::LEFT | Direction::RIGHT => Axis::X,
+            Direction::TOP | Direction::BOTTOM => Axis::Y,
+            Direction::FRONT | Direction::BACK => Axis::Z,
+        }
+    }
+
+    /// The axis the texture's v coordinate runs along on a cube face. Textures are upright on the
+    /// sides, and run from back to front on the top and bottom.
+    fn of_texture_v(face: &Direction) -> Self {
+        match face {
+            Direction::TOP | Direction::BOTTOM => Axis::Z,
+            _ => Axis::Y,
+        }
+    }
+}
+
+/// How a block's geometry is built
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderKind {
+    /// One face per side of the cell, hidden by neighbors that occlude it
+    Cube,
+    /// Two quads crossing diagonally through the cell, like plants. Both sides of each quad are
+    /// drawn, using the side face slots, and there's nothing on the top or bottom.
+    Cross,
+    /// No geometry at all
+    None,
+}
+
+/// The four corners of a single face of a block, relative to the block's center, along with the
+/// texture coordinates for each corner and the texture layer to sample.
+pub struct FaceGeometry {
+    pub positions: [Vector3<f32>; 4],
+    pub tex_coords: [Vector2<f32>; 4],
+    pub layer: u32,
+}
+
+impl FaceGeometry {
+    /// The face of a full cube. The texture is upright when looking at the face from outside the
+    /// block, `FaceUv` is applied on top of that when the face is meshed.
+    pub fn cube(face: &Direction, layers: &TextureLayers) -> Self {
         Self {
-            front: Vector2::new(0.0, 0.0),
-            back: Vector2::new(0.0, 0.0),
-            top: Vector2::new(0.0, 0.0),
-            bottom: Vector2::new(0.0, 0.0),
-            left: Vector2::new(0.0, 0.0),
-            right: Vector2::new(0.0, 0.0),
+            positions: face.cube_verts(),
+            tex_coords: [
+                Vector2::new(0.0, 1.0),
+                Vector2::new(1.0, 1.0),
+                Vector2::new(1.0, 0.0),
+                Vector2::new(0.0, 0.0),
+            ],
+            layer: layers.get(face),
         }
     }
 
-    pub fn to_vec(&self) -> Vec<Vector2<f32>> {
-        fn transform(origin: Vector2<f32>, coord: Vector2<f32>) -> Vector2<f32> {
-            origin
-                .add_element_wise(coord.mul(chunk::TEXTURE_SIZE as f32))
-                .div(chunk::ATLAS_SIZE as f32)
+    /// One side of one of the quads of a cross shaped block, all using the front texture. Front
+    /// and back are the two sides of the quad from the back left to the front right corner, left
+    /// and right the sides of the other one. Cross blocks have no top or bottom faces.
+    pub fn cross(face: &Direction, layers: &TextureLayers) -> Self {
+        let positions = match face {
+            Direction::FRONT => [
+                Vector3::new(-0.5, -0.5, -0.5),
+                Vector3::new(0.5, -0.5, 0.5),
+                Vector3::new(0.5, 0.5, 0.5),
+                Vector3::new(-0.5, 0.5, -0.5),
+            ],
+            Direction::BACK => [
+                Vector3::new(0.5, -0.5, 0.5),
+                Vector3::new(-0.5, -0.5, -0.5),
+                Vector3::new(-0.5, 0.5, -0.5),
+                Vector3::new(0.5, 0.5, 0.5),
+            ],
+            Direction::LEFT => [
+                Vector3::new(-0.5, -0.5, 0.5),
+                Vector3::new(0.5, -0.5, -0.5),
+                Vector3::new(0.5, 0.5, -0.5),
+                Vector3::new(-0.5, 0.5, 0.5),
+            ],
+            Direction::RIGHT => [
+                Vector3::new(0.5, -0.5, -0.5),
+                Vector3::new(-0.5, -0.5, 0.5),
+                Vector3::new(-0.5, 0.5, 0.5),
+                Vector3::new(0.5, 0.5, -0.5),
+            ],
+            Direction::TOP | Direction::BOTTOM => panic!("cross shaped blocks have no {:?} face", face),
+        };
+
+        Self {
+            positions,
+            layer: layers.front,
+            ..FaceGeometry::cube(face, layers)
         }
+    }
 
-        let faces = [
-            self.front,
-            self.back,
-            self.top,
-            self.bottom,
-            self.left,
-            self.right,
-        ];
+    /// Squashes the face into the bottom half of the block. Side faces keep the bottom half of
+    /// their texture instead of stretching the whole thing.
+    pub fn bottom_half(mut self, face: &Direction) -> Self {
+        let (v_min, v_max) = self.tex_coords.iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), t| (min.min(t.y), max.max(t.y)));
 
-        faces
-            .iter()
-            .enumerate()
-            .map(|(i, face)| {
-                let mut result = [
-                    transform(*face, Vector2::new(0.0, 1.0)),
-                    transform(*face, Vector2::new(1.0, 1.0)),
-                    transform(*face, Vector2::new(1.0, 0.0)),
-                    transform(*face, Vector2::new(0.0, 0.0)),
-                ];
-
-                if i % 2 == 0 {
-                    result.swap(0, 1);
-                    result.swap(2, 3);
+        for (position, tex_coord) in self.positions.iter_mut().zip(self.tex_coords.iter_mut()) {
+            if position.y > 0.0 {
+                position.y = 0.0;
+
+                if *face != Direction::TOP {
+                    tex_coord.y = (v_min + v_max) / 2.0;
                 }
+            }
+        }
 
-                result
-            })
-            .flatten()
-            .collect::<Vec<_>>()
+        self
+    }
+}
+
+/// The shape of a block's geometry: what each of its faces looks like and which sides of the
+/// cell they fill. Chunk meshes keep one slot per face and `Direction`, so a shape still has at
+/// most six faces, one per side. Shapes needing more quads than that, like stairs, don't fit yet.
+pub trait BlockShape: Sync {
+    /// The geometry of the given face, relative to the block's center
+    fn face_geometry(&self, face: &Direction, layers: &TextureLayers) -> FaceGeometry;
+
+    /// Whether the face fills the whole side of the cell, so an opaque block with this shape
+    /// hides the neighbor's face against it
+    fn covers(&self, face: &Direction) -> bool;
+}
+
+/// A full cube, see `FaceGeometry::cube`
+pub struct CubeShape;
+
+impl BlockShape for CubeShape {
+    fn face_geometry(&self, face: &Direction, layers: &TextureLayers) -> FaceGeometry {
+        FaceGeometry::cube(face, layers)
+    }
+
+    fn covers(&self, _face: &Direction) -> bool {
+        true
+    }
+}
+
+/// Two quads crossing diagonally through the cell, see `FaceGeometry::cross`
+pub struct CrossShape;
+
+impl BlockShape for CrossShape {
+    fn face_geometry(&self, face: &Direction, layers: &TextureLayers) -> FaceGeometry {
+        FaceGeometry::cross(face, layers)
+    }
+
+    fn covers(&self, _face: &Direction) -> bool {
+        false
+    }
+}
+
+/// The bottom half of a cube, see `FaceGeometry::bottom_half`
+pub struct SlabShape;
+
+impl BlockShape for SlabShape {
+    fn face_geometry(&self, face: &Direction, layers: &TextureLayers) -> FaceGeometry {
+        FaceGeometry::cube(face, layers).bottom_half(face)
+    }
+
+    fn covers(&self, face: &Direction) -> bool {
+        *face == Direction::BOTTOM
+    }
+}
+
+/// How a texture is oriented on a face. Flips are applied before the rotation.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FaceUv {
+    /// Number of clockwise quarter turns
+    pub rotate: u8,
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+impl FaceUv {
+    /// The orientation blocks use unless they say otherwise. Side faces are upright, and the top
+    /// is mirrored to keep the orientation it's always had.
+    pub fn standard(face: &Direction) -> Self {
+        Self {
+            flip_x: *face == Direction::TOP,
+            ..Default::default()
+        }
+    }
+
+    /// Applies the orientation to the texture coordinates of a face's corners, given in the
+    /// counter-clockwise order of `Direction::cube_verts`.
+    pub fn apply(&self, tex_coords: [Vector2<f32>; 4]) -> [Vector2<f32>; 4] {
+        let mut tex_coords = tex_coords.map(|t| Vector2::new(
+            if self.flip_x { 1.0 - t.x } else { t.x },
+            if self.flip_y { 1.0 - t.y } else { t.y },
+        ));
+
+        // Moving each coordinate to the previous corner turns the texture clockwise
+        tex_coords.rotate_left(self.rotate as usize % 4);
+
+        tex_coords
     }
 }
 
 pub trait BlockData {
-    fn texture_coordinates(&self) -> TexCoordConfig;
+    /// The name shown to players, like `Oak Log`
+    fn name(&self) -> &'static str;
+
+    fn texture_layers(&self) -> TextureLayers;
+
+    /// How the block's textures are animated, on every face. Defaults to not at all.
+    fn texture_animation(&self) -> TextureAnimation {
+        TextureAnimation::STILL
+    }
+
+    /// How the block's geometry is built. Defaults to a cube.
+    fn render_kind(&self) -> RenderKind {
+        RenderKind::Cube
+    }
+
+    /// The shape of the block's geometry. Defaults to a full cube, or a cross for cross shaped
+    /// blocks.
+    fn shape(&self) -> &'static dyn BlockShape {
+        match self.render_kind() {
+            RenderKind::Cross => &CrossShape,
+            _ => &CubeShape,
+        }
+    }
+
+    /// The geometry used for the given face when it's visible. Defaults to the shape's face.
+    fn face_geometry(&self, face: &Direction) -> FaceGeometry {
+        self.shape().face_geometry(face, &self.texture_layers())
+    }
+
+    /// How the texture is oriented on the given face
+    fn face_uv(&self, face: &Direction) -> FaceUv {
+        FaceUv::standard(face)
+    }
+
+    /// What the block sounds like. Defaults to stone.
+    fn sound_category(&self) -> SoundCategory {
+        SoundCategory::Stone
+    }
+
+    /// How many seconds of mining it takes to break the block. Defaults to 1.
+    fn hardness(&self) -> f32 {
+        1.0
+    }
+
+    /// Whether the blocks behind this one can be seen through it. Defaults to false.
+    fn is_transparent(&self) -> bool {
+        false
+    }
+
+    /// Whether the block has collision and can be targeted. Defaults to true.
+    fn is_solid(&self) -> bool {
+        true
+    }
+
+    /// The part of the cell the block collides with, relative to the block's center. Defaults to
+    /// the whole cell for solid blocks and nothing for the rest.
+    fn collision_box(&self) -> Option<Aabb> {
+        self.is_solid().then(Aabb::block)
+    }
+
+    /// Whether the block completely covers the given side of its cell, hiding the neighboring
+    /// block's face against it. Defaults to the sides the shape covers of opaque cubes.
+    fn occludes(&self, face: &Direction) -> bool {
+        self.render_kind() == RenderKind::Cube && !self.is_transparent() && self.shape().covers(face)
+    }
+
+    /// Whether faces against a neighbor of the same kind are hidden even though it doesn't
+    /// occlude them. Blocks that fill their cell without occluding, like water, turn this on so a
+    /// cluster of them doesn't show every face inside it. Blocks like leaves leave it off to keep
+    /// the faces between them.
+    fn merges_with_same(&self) -> bool {
+        false
+    }
+
+    /// The tint the face's texture is multiplied with. Defaults to none, which leaves the
+    /// texture as it is.
+    fn tint(&self, _face: &Direction) -> Tint {
+        Tint::None
+    }
+
+    /// How much light the block gives off, from 0 to `chunk::MAX_LIGHT`. Defaults to none.
+    /// There's no light propagation yet, so for now this only lights up the block's own faces.
+    fn light_emission(&self) -> u8 {
+        0
+    }
+
+    /// Called after the block at `changed_pos`, one of the six next to this one at `own_pos`,
+    /// was changed with `World::set_block`. Positions are in world space. Defaults to doing
+    /// nothing.
+    fn on_neighbor_changed(&self, _own_pos: Vector3<i32>, _changed_pos: Vector3<i32>, _world: &mut WorldAccess) {}
+
+    /// Whether the block does anything in `on_random_tick`, so the world can skip the rest.
+    /// Defaults to false.
+    fn wants_random_tick(&self) -> bool {
+        false
+    }
+
+    /// Called every now and then for blocks that want random ticks, see `World::random_tick`.
+    /// `pos` is in world space. Defaults to doing nothing.
+    fn on_random_tick(&self, _pos: Vector3<i32>, _world: &mut WorldAccess) {}
 }
 
+// Derives are forwarded onto every block's struct as well, so blocks serialize by variant name
+// along with their state, like `Grass((snowy: true))` in RON
 trait_enum! {
-    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
     pub enum Block: BlockData {
         Air: {
-            fn texture_coordinates(&self) -> TexCoordConfig {
-                TexCoordConfig::zero()
+            fn name(&self) -> &'static str {
+                "Air"
+            }
+
+            fn render_kind(&self) -> RenderKind {
+                RenderKind::None
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                TextureLayers::zero()
+            }
+
+            fn sound_category(&self) -> SoundCategory {
+                SoundCategory::None
+            }
+
+            fn is_transparent(&self) -> bool {
+                true
+            }
+
+            fn is_solid(&self) -> bool {
+                false
             }
         },
-        Grass: {
-            fn texture_coordinates(&self) -> TexCoordConfig {
-                TexCoordConfig::top_bottom_sides(Vector2::new(0.0, 0.0), Vector2::new(32.0, 0.0), Vector2::new(16.0, 0.0))
+        /// Snowy grass is covered in snow on top, and has some of it hanging over the sides
+        Grass { pub snowy: bool }: {
+            fn name(&self) -> &'static str {
+                if self.snowy {
+                    "Snowy Grass"
+                } else {
+                    "Grass"
+                }
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                if self.snowy {
+                    TextureLayers::from_indices((10, 0), (2, 0), (11, 0))
+                } else {
+                    TextureLayers::from_indices((0, 0), (2, 0), (1, 0))
+                }
+            }
+
+            fn hardness(&self) -> f32 {
+                0.6
+            }
+
+            fn sound_category(&self) -> SoundCategory {
+                SoundCategory::Grass
+            }
+
+            // The top texture is grey so it can be tinted, the sides have their green baked in
+            // and stay as they are. There are no biomes yet, so every grass block is the same
+            // green.
+            fn tint(&self, face: &Direction) -> Tint {
+                if *face == Direction::TOP && !self.snowy {
+                    Tint::Grass
+                } else {
+                    Tint::None
+                }
+            }
+
+            fn wants_random_tick(&self) -> bool {
+                true
+            }
+
+            // Spreads to the dirt around it, up to a block higher or lower, as long as the dirt
+            // isn't covered
+            fn on_random_tick(&self, pos: Vector3<i32>, world: &mut WorldAccess) {
+                for face in [Direction::FRONT, Direction::BACK, Direction::LEFT, Direction::RIGHT] {
+                    for dy in -1..=1 {
+                        let target = pos + face.to_vec3() + Vector3::new(0, dy, 0);
+                        let uncovered = matches!(world.get_block(target + Vector3::unit_y()), Some(Block::Air(_)));
+
+                        if uncovered && matches!(world.get_block(target), Some(Block::Dirt(_))) {
+                            world.set_block(target, Block::new_grass(false));
+                        }
+                    }
+                }
             }
         },
         Stone: {
-            fn texture_coordinates(&self) -> TexCoordConfig {
-                TexCoordConfig::all_same(Vector2::new(48.0, 0.0))
+            fn name(&self) -> &'static str {
+                "Stone"
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                TextureLayers::all_index((3, 0))
             }
+
+            fn hardness(&self) -> f32 {
+                1.5
+            }
+        },
+        Slab: {
+            fn name(&self) -> &'static str {
+                "Slab"
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                TextureLayers::all_index((3, 0))
+            }
+
+            fn hardness(&self) -> f32 {
+                1.5
+            }
+
+            fn shape(&self) -> &'static dyn BlockShape {
+                &SlabShape
+            }
+
+            // The top half of the cell is empty
+            fn is_transparent(&self) -> bool {
+                true
+            }
+
+            // Still hides what's below it, since the bottom is solid
+            fn occludes(&self, face: &Direction) -> bool {
+                self.shape().covers(face)
+            }
+
+            fn collision_box(&self) -> Option<Aabb> {
+                let mut collision_box = Aabb::block();
+                collision_box.max.y = 0.0;
+                Some(collision_box)
+            }
+        },
+        Dirt: {
+            fn name(&self) -> &'static str {
+                "Dirt"
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                TextureLayers::all_index((2, 0))
+            }
+
+            fn hardness(&self) -> f32 {
+                0.5
+            }
+
+            fn sound_category(&self) -> SoundCategory {
+                SoundCategory::Grass
+            }
+        },
+        Sand: {
+            fn name(&self) -> &'static str {
+                "Sand"
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                TextureLayers::all_index((4, 0))
+            }
+
+            fn hardness(&self) -> f32 {
+                0.5
+            }
+
+            fn sound_category(&self) -> SoundCategory {
+                SoundCategory::Sand
+            }
+
+            // Falls one block at a time, each move updating the block it lands on top of, until
+            // it's resting on something
+            fn on_neighbor_changed(&self, own_pos: Vector3<i32>, _changed_pos: Vector3<i32>, world: &mut WorldAccess) {
+                let below = own_pos - Vector3::unit_y();
+
+                if matches!(world.get_block(below), Some(Block::Air(_))) {
+                    world.set_block(below, Block::Sand(*self));
+                    world.set_block(own_pos, Block::new_air());
+                }
+            }
+        },
+        Cobblestone: {
+            fn name(&self) -> &'static str {
+                "Cobblestone"
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                TextureLayers::all_index((5, 0))
+            }
+
+            fn hardness(&self) -> f32 {
+                2.0
+            }
+        },
+        /// The rings are on the two faces at the ends of the axis, with bark around the rest
+        OakLog { pub axis: Axis }: {
+            fn name(&self) -> &'static str {
+                "Oak Log"
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                let (rings, bark) = (TextureLayers::cell((7, 0)), TextureLayers::cell((6, 0)));
+                let layer = |face: Direction| if Axis::of_face(&face) == self.axis { rings } else { bark };
+
+                TextureLayers {
+                    front: layer(Direction::FRONT),
+                    back: layer(Direction::BACK),
+                    top: layer(Direction::TOP),
+                    bottom: layer(Direction::BOTTOM),
+                    left: layer(Direction::LEFT),
+                    right: layer(Direction::RIGHT),
+                }
+            }
+
+            fn hardness(&self) -> f32 {
+                2.0
+            }
+
+            fn sound_category(&self) -> SoundCategory {
+                SoundCategory::Wood
+            }
+
+            // The bark's grain runs up the texture, so turn it wherever that isn't along the log
+            fn face_uv(&self, face: &Direction) -> FaceUv {
+                let along_log = Axis::of_face(face) == self.axis || Axis::of_texture_v(face) == self.axis;
+
+                FaceUv {
+                    rotate: if along_log { 0 } else { 1 },
+                    ..FaceUv::standard(face)
+                }
+            }
+        },
+        OakLeaves: {
+            fn name(&self) -> &'static str {
+                "Oak Leaves"
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                TextureLayers::all_index((8, 0))
+            }
+
+            fn hardness(&self) -> f32 {
+                0.2
+            }
+
+            fn sound_category(&self) -> SoundCategory {
+                SoundCategory::Grass
+            }
+
+            // The texture has holes, so everything behind the leaves stays visible, including
+            // other leaves
+            fn is_transparent(&self) -> bool {
+                true
+            }
+        },
+        Water: {
+            fn name(&self) -> &'static str {
+                "Water"
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                TextureLayers::all_index((9, 0))
+            }
+
+            // Ripples between the tile and the one below it
+            fn texture_animation(&self) -> TextureAnimation {
+                TextureAnimation { frames: 2, ticks_per_frame: 8 }
+            }
+
+            fn sound_category(&self) -> SoundCategory {
+                SoundCategory::None
+            }
+
+            fn is_transparent(&self) -> bool {
+                true
+            }
+
+            fn is_solid(&self) -> bool {
+                false
+            }
+
+            fn merges_with_same(&self) -> bool {
+                true
+            }
+        },
+        Glowstone: {
+            fn name(&self) -> &'static str {
+                "Glowstone"
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                TextureLayers::all_index((12, 0))
+            }
+
+            fn hardness(&self) -> f32 {
+                0.3
+            }
+
+            fn sound_category(&self) -> SoundCategory {
+                SoundCategory::Glass
+            }
+
+            fn light_emission(&self) -> u8 {
+                chunk::MAX_LIGHT
+            }
+        },
+        Magma: {
+            fn name(&self) -> &'static str {
+                "Magma"
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                TextureLayers::all_index((13, 0))
+            }
+
+            fn light_emission(&self) -> u8 {
+                10
+            }
+        },
+        TallGrass: {
+            fn name(&self) -> &'static str {
+                "Tall Grass"
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                TextureLayers::all_index((14, 0))
+            }
+
+            fn hardness(&self) -> f32 {
+                0.0
+            }
+
+            fn sound_category(&self) -> SoundCategory {
+                SoundCategory::Grass
+            }
+
+            fn render_kind(&self) -> RenderKind {
+                RenderKind::Cross
+            }
+
+            fn is_transparent(&self) -> bool {
+                true
+            }
+
+            fn is_solid(&self) -> bool {
+                false
+            }
+        },
+        Flower: {
+            fn name(&self) -> &'static str {
+                "Flower"
+            }
+
+            fn texture_layers(&self) -> TextureLayers {
+                TextureLayers::all_index((0, 1))
+            }
+
+            fn hardness(&self) -> f32 {
+                0.0
+            }
+
+            fn sound_category(&self) -> SoundCategory {
+                SoundCategory::Grass
+            }
+
+            fn render_kind(&self) -> RenderKind {
+                RenderKind::Cross
+            }
+
+            fn is_transparent(&self) -> bool {
+                true
+            }
+
+            fn is_solid(&self) -> bool {
+                false
+            }
+        }
+    }
+}
+
+/// The stable id of every block, used when blocks are saved or sent somewhere. Ids are assigned
+/// by hand rather than by variant order, so reordering the variants doesn't change them. Never
+/// reuse the id of a removed block. Ids only name the kind of block, blocks loaded by id get the
+/// state listed here. The keys match the block definition files in `res/blocks`.
+const BLOCK_IDS: [(u16, &str, Block); 14] = [
+    (0, "air", Block::Air(Air)),
+    (1, "grass", Block::Grass(Grass { snowy: false })),
+    (2, "stone", Block::Stone(Stone)),
+    (3, "slab", Block::Slab(Slab)),
+    (4, "dirt", Block::Dirt(Dirt)),
+    (5, "sand", Block::Sand(Sand)),
+    (6, "cobblestone", Block::Cobblestone(Cobblestone)),
+    (7, "oak_log", Block::OakLog(OakLog { axis: Axis::Y })),
+    (8, "oak_leaves", Block::OakLeaves(OakLeaves)),
+    (9, "water", Block::Water(Water)),
+    (10, "glowstone", Block::Glowstone(Glowstone)),
+    (11, "magma", Block::Magma(Magma)),
+    (12, "tall_grass", Block::TallGrass(TallGrass)),
+    (14, "flower", Block::Flower(Flower)),
+];
+
+/// Ids of blocks that were removed, which stay taken. 13 was a block that only flashed between
+/// two frames to check texture animations, water shows those off now.
+pub const RETIRED_BLOCK_IDS: [u16; 1] = [13];
+
+// Two blocks sharing an id, or a block reusing a retired one, would load as the wrong block
+const _: () = {
+    let mut i = 0;
+    while i < BLOCK_IDS.len() {
+        let mut j = i + 1;
+        while j < BLOCK_IDS.len() {
+            assert!(BLOCK_IDS[i].0 != BLOCK_IDS[j].0, "two blocks share an id");
+            j += 1;
+        }
+        let mut k = 0;
+        while k < RETIRED_BLOCK_IDS.len() {
+            assert!(BLOCK_IDS[i].0 != RETIRED_BLOCK_IDS[k], "a block uses a retired id");
+            k += 1;
+        }
+        i += 1;
+    }
+};
+
+impl Block {
+    /// The block's stable id, see `BLOCK_IDS`
+    pub fn id(&self) -> u16 {
+        self.entry().0
+    }
+
+    /// The name of the block's definition file, like `oak_log`
+    pub fn key(&self) -> &'static str {
+        self.entry().1
+    }
+
+    fn entry(&self) -> &'static (u16, &'static str, Block) {
+        BLOCK_IDS
+            .iter()
+            .find(|(_, _, block)| std::mem::discriminant(block) == std::mem::discriminant(self))
+            .unwrap_or_else(|| panic!("{:?} has no id", self))
+    }
+
+    /// The block with the given stable id, if there is one
+    pub fn from_id(id: u16) -> Option<Block> {
+        BLOCK_IDS.iter().find(|(block_id, _, _)| *block_id == id).map(|(_, _, block)| *block)
+    }
+
+    /// The per-instance state of the block packed into a byte, saved next to its id. Blocks
+    /// without state always have 0.
+    pub fn state(&self) -> u8 {
+        match self {
+            Block::Grass(grass) => grass.snowy as u8,
+            Block::OakLog(log) => log.axis as u8,
+            _ => 0,
         }
     }
+
+    /// The block with the given id and state (see `state`), or `None` if either is invalid
+    pub fn from_id_and_state(id: u16, state: u8) -> Option<Block> {
+        match Block::from_id(id)? {
+            Block::Grass(_) if state <= 1 => Some(Block::new_grass(state == 1)),
+            Block::OakLog(_) => Axis::ALL.get(state as usize).map(|axis| Block::new_oak_log(*axis)),
+            block if state == 0 => Some(block),
+            _ => None,
+        }
+    }
+
+    /// The block as it should be when placed against `face` of another block, so logs run along
+    /// the axis of the face they were placed on
+    pub fn placed_against(self, face: &Direction) -> Block {
+        match self {
+            Block::OakLog(_) => Block::new_oak_log(Axis::of_face(face)),
+            block => block,
+        }
+    }
+
+    /// What breaking the block yields. Most blocks drop themselves, in their default state for
+    /// blocks like logs whose state only depends on how they were placed.
+    pub fn drops(&self) -> Vec<Block> {
+        match self {
+            Block::Air(_) | Block::Water(_) | Block::OakLeaves(_) | Block::TallGrass(_) => Vec::new(),
+            Block::Grass(_) => vec![Block::new_dirt()],
+            Block::Stone(_) => vec![Block::new_cobblestone()],
+            Block::OakLog(_) => vec![Block::new_oak_log(Axis::default())],
+            block => vec![*block],
+        }
+    }
+
+    /// Whether the block has the tag, in the tags installed with `BlockTags::install`
+    pub fn has_tag(&self, tag: &str) -> bool {
+        BlockTags::installed().has(self, tag)
+    }
+
+    /// Looks a block up by its display name or its key, ignoring case, so both `Oak Log` and
+    /// `oak_log` work
+    pub fn by_name(name: &str) -> Option<Block> {
+        Block::all().find(|block| block.name().eq_ignore_ascii_case(name) || block.key().eq_ignore_ascii_case(name))
+    }
+
+    /// Every built-in block, in id order
+    pub fn all() -> impl Iterator<Item = Block> {
+        BLOCK_IDS.iter().map(|(_, _, block)| *block)
+    }
+
+    /// Whether the block has geometry in the face's slot next to `neighbor`, which is `None` when
+    /// it isn't loaded. Cubes lose faces to neighbors that hide them, crosses always fill the side
+    /// slots, and blocks without geometry have nothing to show.
+    pub fn face_visible(&self, face: &Direction, neighbor: Option<&Block>) -> bool {
+        match self.render_kind() {
+            RenderKind::Cube => !neighbor.is_some_and(|neighbor| self.face_hidden_by(face, neighbor)),
+            RenderKind::Cross => !matches!(face, Direction::TOP | Direction::BOTTOM),
+            RenderKind::None => false,
+        }
+    }
+
+    /// Whether the block's face is hidden by the neighbor on that side. Only faces lying on the
+    /// side of the cell can be, see `face_on_cell_side`.
+    pub fn face_hidden_by(&self, face: &Direction, neighbor: &Block) -> bool {
+        let covered = neighbor.occludes(&face.get_opposite()) || (neighbor == self && self.merges_with_same());
+        covered && self.face_on_cell_side(face)
+    }
+
+    /// Whether the face's geometry lies flat against the side of the cell it's facing. Faces set
+    /// into the cell, like the top of a slab, leave a gap a neighbor can be seen through.
+    pub fn face_on_cell_side(&self, face: &Direction) -> bool {
+        let normal = face.to_vec3().cast::<f32>().unwrap();
+        self.face_geometry(face).positions.iter().all(|position| position.dot(normal) >= 0.5)
+    }
+
+    /// The texture layer and animation the face is meshed with, from the block definition files
+    /// when `textures` has them. Only call this for faces with geometry, see `face_visible`.
+    pub fn face_texture(&self, face: &Direction, textures: &TextureTable) -> (u32, TextureAnimation) {
+        match textures.get(self) {
+            Some((layers, animation)) => {
+                let layer = match self.render_kind() {
+                    RenderKind::Cross => layers.front,
+                    _ => layers.get(face),
+                };
+                (layer, *animation)
+            }
+            None => (self.face_geometry(face).layer, self.texture_animation()),
+        }
+    }
+}
+
+/// A table of the atlas cell every face of every built-in block samples, in pixels and in
+/// normalized atlas coordinates, for tracking down faces showing the wrong texture. Animated
+/// faces cover every frame. Faces running past the edge of the atlas are flagged, and so are
+/// cells that one block uses as a different animation frame than another, since sharing a tile
+/// on purpose always lines the frames up.
+pub fn dump_uv_table(textures: &TextureTable) -> String {
+    let (atlas_size, tile_size) = (chunk::ATLAS_SIZE as u32, chunk::TEXTURE_SIZE as u32);
+    let columns = atlas_size / tile_size;
+
+    let faces = Block::all()
+        .flat_map(|block| {
+            Direction::ALL
+                .into_iter()
+                .filter(move |face| block.face_visible(face, None))
+                .map(move |face| {
+                    let (layer, animation) = block.face_texture(&face, textures);
+                    (block, face, layer, animation)
+                })
+        })
+        .collect::<Vec<_>>();
+
+    // Which block uses each layer, and as which frame
+    let mut users: HashMap<u32, Vec<(Block, u32)>> = HashMap::new();
+    for (block, _, layer, animation) in &faces {
+        for frame in 0..animation.frames {
+            let users = users.entry(layer + frame * columns).or_default();
+            if !users.contains(&(*block, frame)) {
+                users.push((*block, frame));
+            }
+        }
+    }
+
+    let mut table = format!(
+        "{:<16} {:<7} {:>5}  {:<9} {:<22} {:<36} problems\n",
+        "block", "face", "layer", "cell", "pixels", "uv"
+    );
+
+    for (block, face, layer, animation) in &faces {
+        let (x, y) = (layer % columns * tile_size, layer / columns * tile_size);
+        let (right, bottom) = (x + tile_size, y + animation.frames * tile_size);
+        let uv = |pixels: u32| pixels as f32 / atlas_size as f32;
+
+        let mut problems = Vec::new();
+        if bottom > atlas_size {
+            problems.push("outside of the atlas".to_string());
+        }
+        for frame in 0..animation.frames {
+            for (other, other_frame) in &users[&(layer + frame * columns)] {
+                if other != block && *other_frame != frame {
+                    problems.push(format!("frame {} is frame {} of {}", frame, other_frame, other.key()));
+                }
+            }
+        }
+
+        let row = format!(
+            "{:<16} {:<7} {:>5}  {:<9} {:<22} {:<36} {}",
+            block.key(),
+            format!("{:?}", face).to_lowercase(),
+            layer,
+            format!("({}, {})", layer % columns, layer / columns),
+            format!("({}, {})-({}, {})", x, y, right, bottom),
+            format!("({:.4}, {:.4})-({:.4}, {:.4})", uv(x), uv(y), uv(right), uv(bottom)),
+            problems.join(", "),
+        );
+        let _ = writeln!(table, "{}", row.trim_end());
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{Chunk, ChunkMeshData};
+
+    #[test]
+    fn ids_round_trip_through_from_id() {
+        for block in Block::all() {
+            assert_eq!(Block::from_id(block.id()), Some(block));
+            assert_eq!(Block::from_id_and_state(block.id(), block.state()), Some(block));
+        }
+
+        let snowy = Block::new_grass(true);
+        assert_eq!(Block::from_id_and_state(snowy.id(), snowy.state()), Some(snowy));
+        assert_eq!(Block::from_id(15), None);
+        for id in RETIRED_BLOCK_IDS {
+            assert_eq!(Block::from_id(id), None);
+        }
+        assert_eq!(Block::from_id_and_state(Block::new_stone().id(), 1), None);
+    }
+
+    #[test]
+    fn ids_dont_depend_on_the_variant_order() {
+        // Saved worlds store these, so they must never change
+        assert_eq!(Block::new_air().id(), 0);
+        assert_eq!(Block::new_grass(false).id(), 1);
+        assert_eq!(Block::new_stone().id(), 2);
+        assert_eq!(Block::new_water().id(), 9);
+    }
+
+    #[test]
+    fn every_block_round_trips_through_json_and_bincode() {
+        let blocks = Block::all()
+            .chain([Block::new_grass(true)])
+            .chain(Axis::ALL.map(Block::new_oak_log))
+            .collect::<Vec<_>>();
+
+        for block in blocks {
+            let json = serde_json::to_string(&block).unwrap();
+            assert_eq!(serde_json::from_str::<Block>(&json).unwrap(), block, "{}", json);
+
+            let bytes = bincode::serialize(&block).unwrap();
+            assert_eq!(bincode::deserialize::<Block>(&bytes).unwrap(), block);
+        }
+
+        let layers = TextureLayers::top_bottom_sides(1, 2, 3);
+        assert_eq!(serde_json::from_str::<TextureLayers>(&serde_json::to_string(&layers).unwrap()).unwrap(), layers);
+        assert_eq!(bincode::deserialize::<TextureLayers>(&bincode::serialize(&layers).unwrap()).unwrap(), layers);
+    }
+
+    #[test]
+    fn an_unknown_block_name_is_an_error() {
+        let error = serde_json::from_str::<Block>("\"Bedrock\"").unwrap_err();
+        assert!(error.to_string().contains("unknown variant"), "{}", error);
+    }
+
+    #[test]
+    fn every_name_round_trips_through_by_name() {
+        for block in Block::all() {
+            assert_eq!(Block::by_name(block.name()), Some(block));
+            assert_eq!(Block::by_name(block.key()), Some(block));
+            assert_eq!(Block::by_name(&block.name().to_uppercase()), Some(block));
+        }
+
+        assert_eq!(Block::by_name("oak log"), Some(Block::new_oak_log(Axis::Y)));
+        assert_eq!(Block::by_name("bedrock"), None);
+    }
+
+    #[test]
+    fn blocks_drop_what_breaking_them_yields() {
+        assert_eq!(Block::new_grass(false).drops(), vec![Block::new_dirt()]);
+        assert_eq!(Block::new_grass(true).drops(), vec![Block::new_dirt()]);
+        assert_eq!(Block::new_stone().drops(), vec![Block::new_cobblestone()]);
+        assert_eq!(Block::new_oak_log(Axis::X).drops(), vec![Block::new_oak_log(Axis::Y)]);
+        assert_eq!(Block::new_sand().drops(), vec![Block::new_sand()]);
+        assert!(Block::new_air().drops().is_empty());
+    }
+
+    #[test]
+    fn air_makes_no_sound() {
+        assert_eq!(Block::new_air().sound_category(), SoundCategory::None);
+        assert_eq!(Block::new_grass(false).sound_category(), SoundCategory::Grass);
+        assert_eq!(Block::new_stone().sound_category(), SoundCategory::Stone);
+    }
+
+    #[test]
+    fn a_slab_top_sits_half_way_up_and_doesnt_hide_the_block_above() {
+        let (slab, stone) = (Block::new_slab(), Block::new_stone());
+
+        // The cell spans -0.5 to 0.5, the slab's top is half a block above its bottom
+        assert!(slab.face_geometry(&Direction::TOP).positions.iter().all(|position| position.y == 0.0));
+        assert!(slab.face_visible(&Direction::TOP, Some(&stone)));
+        assert!(stone.face_visible(&Direction::BOTTOM, Some(&slab)));
+        assert!(!stone.face_visible(&Direction::TOP, Some(&slab)));
+
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+        chunk.set_block(Vector3::new(4, 0, 4), slab).unwrap();
+        chunk.set_block(Vector3::new(4, 1, 4), stone).unwrap();
+        let mesh = ChunkMeshData::from_chunk(&chunk, [None; 4], &TextureTable::default());
+
+        assert_eq!(mesh.face_count(), 12);
+        let tops = mesh.vertices.iter().map(|vertex| vertex.unpack()).filter(|vertex| vertex.face == Direction::TOP);
+        let heights = tops.map(|vertex| vertex.position.y).collect::<Vec<_>>();
+        assert_eq!(heights, [0.0, 0.0, 0.0, 0.0, 1.5, 1.5, 1.5, 1.5]);
+    }
+
+    #[test]
+    fn a_flower_doesnt_hide_the_grass_next_to_it() {
+        let (grass, flower) = (Block::new_grass(false), Block::new_flower());
+
+        for face in Direction::ALL {
+            assert!(grass.face_visible(&face, Some(&flower)), "{:?}", face);
+        }
+
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+        chunk.set_block(Vector3::new(4, 0, 4), grass).unwrap();
+        let alone = ChunkMeshData::from_chunk(&chunk, [None; 4], &TextureTable::default()).face_count();
+        chunk.set_block(Vector3::new(5, 0, 4), flower).unwrap();
+        chunk.set_block(Vector3::new(4, 1, 4), flower).unwrap();
+        let mesh = ChunkMeshData::from_chunk(&chunk, [None; 4], &TextureTable::default());
+
+        // Both flowers add their crossed quads, and every grass face stays
+        assert_eq!(alone, 6);
+        assert_eq!(mesh.face_count(), 6 + 2 * 4);
+    }
+
+    #[test]
+    fn water_merges_with_water_but_leaves_keep_their_faces() {
+        let (water, stone, leaves) = (Block::new_water(), Block::new_stone(), Block::new_oak_leaves());
+        let face = &Direction::RIGHT;
+
+        // Stone hides water, water doesn't hide stone
+        assert!(!water.face_visible(face, Some(&stone)));
+        assert!(stone.face_visible(face, Some(&water)));
+
+        // Faces inside a body of water are merged away, but not against other see through blocks
+        assert!(!water.face_visible(face, Some(&water)));
+        assert!(water.face_visible(face, Some(&leaves)));
+
+        // Leaves don't merge, so the leaves behind them show through the holes
+        assert!(leaves.face_visible(face, Some(&leaves)));
+    }
+
+    #[test]
+    fn every_tint_has_a_palette_color_and_fits_in_a_vertex() {
+        assert_eq!(Tint::PALETTE.len(), Tint::ALL.len());
+        assert!(Tint::ALL.len() <= Tint::MAX_COUNT);
+        for (index, tint) in Tint::ALL.into_iter().enumerate() {
+            assert_eq!(tint.index(), index as u32);
+            assert_eq!(Tint::from_index(index as u32), Some(tint));
+        }
+    }
+
+    #[test]
+    fn a_log_shows_rings_on_its_ends_and_bark_everywhere_else() {
+        let textures = TextureTable::default();
+        let layer = |block: Block, face: &Direction| block.face_texture(face, &textures).0;
+        let rings = layer(Block::new_oak_log(Axis::Y), &Direction::TOP);
+        let bark = layer(Block::new_oak_log(Axis::Y), &Direction::FRONT);
+        assert_ne!(rings, bark);
+
+        for axis in Axis::ALL {
+            let log = Block::new_oak_log(axis);
+            let (ends, sides): (Vec<_>, Vec<_>) = Direction::ALL.iter().partition(|face| Axis::of_face(face) == axis);
+
+            assert_eq!((ends.len(), sides.len()), (2, 4));
+            assert!(ends.iter().all(|face| layer(log, face) == rings), "{:?} log", axis);
+            assert!(sides.iter().all(|face| layer(log, face) == bark), "{:?} log", axis);
+        }
+    }
+
+    #[test]
+    fn shapes_cover_the_sides_they_fill() {
+        for face in Direction::ALL {
+            assert!(CubeShape.covers(&face));
+            assert!(!CrossShape.covers(&face));
+            assert_eq!(SlabShape.covers(&face), face == Direction::BOTTOM);
+
+            assert!(Block::new_stone().occludes(&face));
+            assert_eq!(Block::new_slab().occludes(&face), face == Direction::BOTTOM);
+            assert!(!Block::new_flower().occludes(&face));
+        }
+
+        // Blocks get their faces from their shape unless they override it
+        let layers = Block::new_slab().texture_layers();
+        let slab_top = SlabShape.face_geometry(&Direction::TOP, &layers);
+        assert_eq!(Block::new_slab().face_geometry(&Direction::TOP).positions, slab_top.positions);
+    }
 }
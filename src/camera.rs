@@ -1,9 +1,13 @@
 use std::f32::consts::FRAC_PI_2;
 
 use cgmath::*;
+use serde::{Deserialize, Serialize};
 use winit::dpi::PhysicalPosition;
 use winit::event::*;
 
+use crate::input::{Action, ActionMap};
+use crate::world::{Aabb, World};
+
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
@@ -14,6 +18,25 @@ pub const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
 
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
 
+/// How far above the ground the camera is kept while walking
+const EYE_HEIGHT: f32 = 1.6;
+const WALK_SPEED: f32 = 5.0;
+const GRAVITY: f32 = 30.0;
+const JUMP_SPEED: f32 = 9.0;
+/// The player's body while walking, a box this tall and twice this wide
+const PLAYER_HEIGHT: f32 = 1.8;
+const PLAYER_RADIUS: f32 = 0.3;
+
+/// The player's body with their eyes at `eye`, in render space
+pub fn player_body(eye: Point3<f32>) -> Aabb {
+    let feet = eye - Vector3::unit_y() * EYE_HEIGHT;
+
+    Aabb::new(
+        feet - Vector3::new(PLAYER_RADIUS, 0.0, PLAYER_RADIUS),
+        feet + Vector3::new(PLAYER_RADIUS, PLAYER_HEIGHT, PLAYER_RADIUS),
+    )
+}
+
 #[derive(Debug)]
 pub struct Camera {
     pub position: Point3<f32>,
@@ -34,18 +57,166 @@ impl Camera {
         }
     }
 
+    /// The normalized direction the camera is looking in
+    pub fn forward(&self) -> Vector3<f32> {
+        Vector3::new(self.yaw.0.cos(), self.pitch.0.sin(), self.yaw.0.sin()).normalize()
+    }
+
     pub fn calc_matrix(&self) -> Matrix4<f32> {
         Matrix4::look_to_rh(
             self.position,
-            Vector3::new(self.yaw.0.cos(), self.pitch.0.sin(), self.yaw.0.sin()).normalize(),
+            self.forward(),
             Vector3::unit_y(),
         )
     }
+
+    /// The camera and projection as they're stored in save files
+    pub fn state(&self, projection: &Projection) -> CameraState {
+        CameraState {
+            position: self.position.into(),
+            yaw: self.yaw.0,
+            pitch: self.pitch.0,
+            projection: match projection.mode {
+                ProjectionMode::Perspective { fovy } => ProjectionState::Perspective { fovy: fovy.0 },
+                ProjectionMode::Orthographic { height } => ProjectionState::Orthographic { height },
+            },
+        }
+    }
+}
+
+/// A third person camera circling `focus` at `distance`, for looking at builds from the outside.
+/// Mouse motion turns it around the focus and scrolling moves it closer or further away.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrbitCamera {
+    pub focus: Point3<f32>,
+    distance: f32,
+    /// The direction the camera looks at the focus from, like `Camera`'s
+    yaw: Rad<f32>,
+    pitch: Rad<f32>,
+}
+
+impl OrbitCamera {
+    pub const MIN_DISTANCE: f32 = 2.0;
+    pub const MAX_DISTANCE: f32 = 64.0;
+    /// How much a line of scrolling changes the distance, as a fraction of it, so zooming feels
+    /// the same up close and far away
+    const ZOOM_PER_LINE: f32 = 0.1;
+    const RADIANS_PER_PIXEL: f32 = 0.005;
+
+    /// An orbit camera `distance` from `focus`, clamped between `MIN_DISTANCE` and
+    /// `MAX_DISTANCE`, looking at it in the direction given by `yaw` and `pitch`. The pitch is
+    /// clamped like the free camera's.
+    pub fn new<Y: Into<Rad<f32>>, P: Into<Rad<f32>>>(focus: Point3<f32>, distance: f32, yaw: Y, pitch: P) -> Self {
+        Self {
+            focus,
+            distance: distance.clamp(OrbitCamera::MIN_DISTANCE, OrbitCamera::MAX_DISTANCE),
+            yaw: yaw.into(),
+            pitch: Rad(pitch.into().0.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2)),
+        }
+    }
+
+    /// An orbit camera looking at `focus` from where `camera` is
+    pub fn looking_from(camera: &Camera, focus: Point3<f32>) -> Self {
+        OrbitCamera::new(focus, camera.position.distance(focus), camera.yaw, camera.pitch)
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    pub fn set_distance(&mut self, distance: f32) {
+        self.distance = distance.clamp(OrbitCamera::MIN_DISTANCE, OrbitCamera::MAX_DISTANCE);
+    }
+
+    /// Turns the camera around the focus, keeping it from going over the top or under the bottom
+    pub fn rotate(&mut self, yaw: Rad<f32>, pitch: Rad<f32>) {
+        self.yaw += yaw;
+        self.pitch = Rad((self.pitch + pitch).0.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2));
+    }
+
+    pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
+        self.rotate(
+            Rad(mouse_dx as f32 * OrbitCamera::RADIANS_PER_PIXEL),
+            Rad(-mouse_dy as f32 * OrbitCamera::RADIANS_PER_PIXEL),
+        );
+    }
+
+    /// Scrolling up moves closer to the focus, down further away
+    pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, lines) => *lines,
+            // Like `CameraController::process_scroll`, a line is about 100 pixels
+            MouseScrollDelta::PixelDelta(PhysicalPosition { y, .. }) => *y as f32 / 100.0,
+        };
+
+        self.set_distance(self.distance * (1.0 - lines * OrbitCamera::ZOOM_PER_LINE));
+    }
+
+    /// Where the camera is relative to the focus
+    pub fn offset(&self) -> Vector3<f32> {
+        -self.to_camera().forward() * self.distance
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        self.focus + self.offset()
+    }
+
+    /// A camera at the eye looking at the focus, to render with `CameraUniform::update_view_proj`
+    /// like any other
+    pub fn to_camera(&self) -> Camera {
+        let mut camera = Camera::new(self.focus, self.yaw, self.pitch);
+        camera.position -= camera.forward() * self.distance;
+
+        camera
+    }
+}
+
+/// How the view volume is shaped, like `ProjectionMode`, as stored in save files
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ProjectionState {
+    /// The vertical field of view in radians
+    Perspective { fovy: f32 },
+    Orthographic { height: f32 },
+}
+
+/// Where the camera is and how it's looking, as stored in save files. The position is in render
+/// space, so it only means something along with the world's origin. The aspect ratio comes from
+/// the window and the far plane from the render distance, so neither is saved.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraState {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub projection: ProjectionState,
+}
+
+impl CameraState {
+    /// Moves the camera and changes the projection back to how they were saved
+    pub fn restore(&self, camera: &mut Camera, projection: &mut Projection) {
+        camera.position = self.position.into();
+        camera.yaw = Rad(self.yaw);
+        camera.pitch = Rad(self.pitch);
+
+        projection.mode = match self.projection {
+            ProjectionState::Perspective { fovy } => ProjectionMode::Perspective { fovy: Rad(fovy) },
+            ProjectionState::Orthographic { height } => ProjectionMode::Orthographic { height },
+        };
+    }
+}
+
+/// How the view volume is shaped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProjectionMode {
+    /// Things get smaller with distance, `fovy` is the vertical field of view
+    Perspective { fovy: Rad<f32> },
+    /// Things keep their size no matter how far away they are, `height` is how many world units
+    /// fit vertically on screen. Useful for map screenshots and isometric views.
+    Orthographic { height: f32 },
 }
 
 pub struct Projection {
     aspect: f32,
-    fovy: Rad<f32>,
+    mode: ProjectionMode,
     znear: f32,
     zfar: f32,
 }
@@ -54,21 +225,162 @@ impl Projection {
     pub fn new<F: Into<Rad<f32>>>(width: u32, height: u32, fovy: F, znear: f32, zfar: f32) -> Self {
         Self {
             aspect: width as f32 / height as f32,
-            fovy: fovy.into(),
+            mode: ProjectionMode::Perspective { fovy: fovy.into() },
             znear,
             zfar,
         }
     }
 
+    /// An orthographic projection showing `width` by `height` world units. Resizing keeps the
+    /// height and adjusts the width to the new aspect ratio.
+    pub fn orthographic(width: f32, height: f32, znear: f32, zfar: f32) -> Self {
+        Self {
+            aspect: width / height,
+            mode: ProjectionMode::Orthographic { height },
+            znear,
+            zfar,
+        }
+    }
+
+    pub fn mode(&self) -> ProjectionMode {
+        self.mode
+    }
+
+    /// The vertical field of view, `None` for an orthographic projection
+    pub fn fovy(&self) -> Option<Rad<f32>> {
+        match self.mode {
+            ProjectionMode::Perspective { fovy } => Some(fovy),
+            ProjectionMode::Orthographic { .. } => None,
+        }
+    }
+
+    /// Changes the vertical field of view, kept between 1 and 179 degrees so the matrix stays
+    /// valid. Does nothing to an orthographic projection.
+    pub fn set_fovy<F: Into<Rad<f32>>>(&mut self, fovy: F) {
+        if let ProjectionMode::Perspective { fovy: current } = &mut self.mode {
+            *current = Rad::from(Deg(Deg::from(fovy.into()).0.clamp(1.0, 179.0)));
+        }
+    }
+
+    /// Distance to the far plane, nothing past it is drawn
+    pub fn far(&self) -> f32 {
+        self.zfar
+    }
+
+    pub fn set_far(&mut self, far: f32) {
+        self.zfar = far;
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
         self.aspect = width as f32 / height as f32;
     }
 
     pub fn calc_matrix(&self) -> Matrix4<f32> {
-        OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar)
+        let projection = match self.mode {
+            ProjectionMode::Perspective { fovy } => perspective(fovy, self.aspect, self.znear, self.zfar),
+            ProjectionMode::Orthographic { height } => {
+                let (half_width, half_height) = (height * self.aspect / 2.0, height / 2.0);
+                ortho(-half_width, half_width, -half_height, half_height, self.znear, self.zfar)
+            }
+        };
+
+        OPENGL_TO_WGPU_MATRIX * projection
+    }
+}
+
+/// The field of view the player picked, along with how far the zoom key narrows it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraSettings {
+    fovy: Deg<f32>,
+}
+
+impl CameraSettings {
+    pub const MIN_FOVY: Deg<f32> = Deg(30.0);
+    pub const MAX_FOVY: Deg<f32> = Deg(110.0);
+    /// How much the field of view changes with every press of the keys
+    pub const FOVY_STEP: Deg<f32> = Deg(5.0);
+    /// The field of view while zooming, as a fraction of the normal one
+    pub const ZOOM: f32 = 0.25;
+
+    /// Clamps the vertical field of view `fovy` between `MIN_FOVY` and `MAX_FOVY`
+    pub fn new<F: Into<Deg<f32>>>(fovy: F) -> Self {
+        Self {
+            fovy: Deg(fovy.into().0.clamp(CameraSettings::MIN_FOVY.0, CameraSettings::MAX_FOVY.0)),
+        }
+    }
+
+    pub fn fovy(&self) -> Deg<f32> {
+        self.fovy
+    }
+
+    /// The field of view while the zoom key is held
+    pub fn zoomed_fovy(&self) -> Deg<f32> {
+        self.fovy * CameraSettings::ZOOM
+    }
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self::new(Deg(45.0))
     }
 }
 
+/// The six planes bounding the camera's view volume, used to skip drawing things that are off
+/// screen. Each plane is stored as `(normal, distance)` with the normal pointing inwards.
+#[derive(Debug)]
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the planes from a view-projection matrix (Gribb & Hartmann). Expects wgpu's 0 to 1
+    /// clip space depth, i.e. a projection that includes `OPENGL_TO_WGPU_MATRIX`.
+    pub fn from_matrix(view_proj: Matrix4<f32>) -> Self {
+        let row = |i: usize| Vector4::new(view_proj.x[i], view_proj.y[i], view_proj.z[i], view_proj.w[i]);
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+        let planes = [
+            r3 + r0, // left
+            r3 - r0, // right
+            r3 + r1, // bottom
+            r3 - r1, // top
+            r2,      // near
+            r3 - r2, // far
+        ]
+        .map(|plane| plane / plane.truncate().magnitude());
+
+        Self { planes }
+    }
+
+    /// The left, right, bottom, top, near and far planes, as `(normal, distance)`
+    pub fn planes(&self) -> &[Vector4<f32>; 6] {
+        &self.planes
+    }
+
+    /// Returns false only if the box is entirely outside one of the planes
+    pub fn intersects_aabb(&self, min: Vector3<f32>, max: Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner of the box furthest along the plane's normal
+            let corner = Vector3::new(
+                if plane.x >= 0.0 { max.x } else { min.x },
+                if plane.y >= 0.0 { max.y } else { min.y },
+                if plane.z >= 0.0 { max.z } else { min.z },
+            );
+
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+/// How the controller moves the camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerMode {
+    /// Moves freely in every direction, ignoring blocks
+    Fly,
+    /// Stays on the ground and falls under gravity, up jumps
+    Walk,
+}
+
 #[derive(Debug)]
 pub struct CameraController {
     amount_left: f32,
@@ -82,6 +394,10 @@ pub struct CameraController {
     scroll: f32,
     speed: f32,
     sensitivity: f32,
+    pub mode: ControllerMode,
+    /// Vertical speed while walking, positive is up
+    vertical_velocity: f32,
+    on_ground: bool,
 }
 
 impl CameraController {
@@ -98,41 +414,30 @@ impl CameraController {
             scroll: 0.0,
             speed,
             sensitivity,
+            mode: ControllerMode::Fly,
+            vertical_velocity: 0.0,
+            on_ground: false,
         }
     }
 
-    pub fn process_keyboard(&mut self, key: VirtualKeyCode, state: ElementState) -> bool {
-        let amount = if state == ElementState::Pressed {
-            1.0
-        } else {
-            0.0
-        };
-        match key {
-            VirtualKeyCode::W | VirtualKeyCode::Up => {
-                self.amount_forward = amount;
-                true
-            }
-            VirtualKeyCode::S | VirtualKeyCode::Down => {
-                self.amount_backward = amount;
-                true
-            }
-            VirtualKeyCode::A | VirtualKeyCode::Left => {
-                self.amount_left = amount;
-                true
-            }
-            VirtualKeyCode::D | VirtualKeyCode::Right => {
-                self.amount_right = amount;
-                true
-            }
-            VirtualKeyCode::Space => {
-                self.amount_up = amount;
-                true
-            }
-            VirtualKeyCode::LShift => {
-                self.amount_down = amount;
-                true
-            }
-            _ => false,
+    /// Reads the movement actions that are currently held
+    pub fn process_actions(&mut self, action_map: &ActionMap) {
+        let amount = |action| if action_map.pressed(action) { 1.0 } else { 0.0 };
+
+        self.amount_forward = amount(Action::MoveForward);
+        self.amount_backward = amount(Action::MoveBackward);
+        self.amount_left = amount(Action::MoveLeft);
+        self.amount_right = amount(Action::MoveRight);
+        self.amount_up = amount(Action::MoveUp);
+        self.amount_down = amount(Action::MoveDown);
+
+        if action_map.just_pressed(Action::ToggleWalk) {
+            self.mode = match self.mode {
+                ControllerMode::Fly => ControllerMode::Walk,
+                ControllerMode::Walk => ControllerMode::Fly,
+            };
+            self.vertical_velocity = 0.0;
+            self.on_ground = false;
         }
     }
 
@@ -149,28 +454,27 @@ impl CameraController {
         };
     }
 
-    pub fn update_camera(&mut self, camera: &mut Camera, dt: f32) {
+    /// Moves and turns the camera. `world` is only used while walking, to find the ground.
+    pub fn update_camera(&mut self, camera: &mut Camera, world: &World, dt: f32) {
         // let dt = dt.as_secs_f32();
 
         // Move forward/backward and left/right
         let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
         let forward = Vector3::new(yaw_cos, 0.0, yaw_sin).normalize();
         let right = Vector3::new(-yaw_sin, 0.0, yaw_cos).normalize();
-        camera.position += forward * (self.amount_forward - self.amount_backward) * self.speed * dt;
-        camera.position += right * (self.amount_right - self.amount_left) * self.speed * dt;
-
-        // Move in/out (aka. "zoom")
-        // Note: this isn't actual zoom. The camera's position
-        // changes when zooming. I've added this to make it easier
-        // to get closer to an object you want to focus on.
-        let (pitch_sin, pitch_cos) = camera.pitch.0.sin_cos();
-        let scrollward =
-            Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin).normalize();
-        camera.position += scrollward * self.scroll * self.speed * self.sensitivity * dt;
-        self.scroll = 0.0;
+        let speed = match self.mode {
+            ControllerMode::Fly => self.speed,
+            ControllerMode::Walk => WALK_SPEED,
+        };
+        let horizontal = (forward * (self.amount_forward - self.amount_backward)
+            + right * (self.amount_right - self.amount_left)) * speed * dt;
 
-        // Move up/down. Since we don't use roll, we can just modify the y coordinate directly.
-        camera.position.y += (self.amount_up - self.amount_down) * self.speed * dt;
+        if self.mode == ControllerMode::Walk {
+            self.walk(camera, world, horizontal, dt);
+        } else {
+            camera.position += horizontal;
+            self.fly(camera, dt);
+        }
 
         // Rotate
         camera.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
@@ -189,4 +493,187 @@ impl CameraController {
             camera.pitch = Rad(SAFE_FRAC_PI_2);
         }
     }
+
+    /// Moves the player's body through the world, falling under gravity and jumping when up is
+    /// held on the ground. The camera sits `EYE_HEIGHT` above the bottom of the body.
+    fn walk(&mut self, camera: &mut Camera, world: &World, horizontal: Vector3<f32>, dt: f32) {
+        self.scroll = 0.0;
+
+        if self.on_ground && self.amount_up > 0.0 {
+            self.vertical_velocity = JUMP_SPEED;
+        }
+        self.vertical_velocity -= GRAVITY * dt;
+
+        let body = player_body(camera.position);
+        let velocity = Vector3::new(horizontal.x, self.vertical_velocity * dt, horizontal.z);
+        let collision = world.resolve_collision(body, velocity);
+
+        camera.position += collision.displacement;
+
+        self.on_ground = collision.blocked[1] && self.vertical_velocity < 0.0;
+        if collision.blocked[1] {
+            self.vertical_velocity = 0.0;
+        }
+    }
+
+    fn fly(&mut self, camera: &mut Camera, dt: f32) {
+        let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
+
+        // Move in/out (aka. "zoom")
+        // Note: this isn't actual zoom. The camera's position
+        // changes when zooming. I've added this to make it easier
+        // to get closer to an object you want to focus on.
+        let (pitch_sin, pitch_cos) = camera.pitch.0.sin_cos();
+        let scrollward =
+            Vector3::new(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin).normalize();
+        camera.position += scrollward * self.scroll * self.speed * self.sensitivity * dt;
+        self.scroll = 0.0;
+
+        // Move up/down. Since we don't use roll, we can just modify the y coordinate directly.
+        camera.position.y += (self.amount_up - self.amount_down) * self.speed * dt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The frustum of a camera at the origin looking down +x with a 90 degree field of view
+    fn frustum() -> Frustum {
+        let camera = Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0));
+        let projection = Projection::new(100, 100, Deg(90.0), 0.1, 100.0);
+
+        Frustum::from_matrix(projection.calc_matrix() * camera.calc_matrix())
+    }
+
+    #[test]
+    fn frustum_keeps_boxes_in_view() {
+        let frustum = frustum();
+
+        assert!(frustum.intersects_aabb(Vector3::new(9.0, -1.0, -1.0), Vector3::new(11.0, 1.0, 1.0)));
+        // Only a corner of this one pokes into the view
+        assert!(frustum.intersects_aabb(Vector3::new(9.0, -1.0, 9.0), Vector3::new(11.0, 1.0, 11.0)));
+        // Boxes containing the camera are never culled
+        assert!(frustum.intersects_aabb(Vector3::new(-1.0, -1.0, -1.0), Vector3::new(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn frustum_culls_boxes_outside_each_plane() {
+        let frustum = frustum();
+        let outside = [
+            // Behind the camera
+            (Vector3::new(-11.0, -1.0, -1.0), Vector3::new(-9.0, 1.0, 1.0)),
+            // Past the far plane
+            (Vector3::new(101.0, -1.0, -1.0), Vector3::new(103.0, 1.0, 1.0)),
+            // Off to either side
+            (Vector3::new(9.0, -1.0, 12.0), Vector3::new(11.0, 1.0, 14.0)),
+            (Vector3::new(9.0, -1.0, -14.0), Vector3::new(11.0, 1.0, -12.0)),
+            // Above and below
+            (Vector3::new(9.0, 12.0, -1.0), Vector3::new(11.0, 14.0, 1.0)),
+            (Vector3::new(9.0, -14.0, -1.0), Vector3::new(11.0, -12.0, 1.0)),
+        ];
+
+        for (min, max) in outside {
+            assert!(!frustum.intersects_aabb(min, max), "{:?} to {:?} wasn't culled", min, max);
+        }
+    }
+
+    #[test]
+    fn the_field_of_view_is_clamped_to_a_valid_range() {
+        let mut projection = Projection::new(100, 100, Deg(60.0), 0.1, 100.0);
+        let degrees = |projection: &Projection| Deg::from(projection.fovy().unwrap()).0;
+
+        projection.set_fovy(Deg(75.0));
+        assert!((degrees(&projection) - 75.0).abs() < 1e-4);
+        projection.set_fovy(Deg(0.0));
+        assert!((degrees(&projection) - 1.0).abs() < 1e-4);
+        projection.set_fovy(Deg(400.0));
+        assert!((degrees(&projection) - 179.0).abs() < 1e-4);
+
+        let mut orthographic = Projection::orthographic(40.0, 30.0, 0.1, 100.0);
+        orthographic.set_fovy(Deg(75.0));
+        assert_eq!(orthographic.fovy(), None);
+    }
+
+    #[test]
+    fn scrolling_zooms_the_orbit_camera_within_its_limits() {
+        let mut orbit = OrbitCamera::new(Point3::new(0.0, 0.0, 0.0), 10.0, Deg(0.0), Deg(0.0));
+
+        orbit.process_scroll(&MouseScrollDelta::LineDelta(0.0, 1.0));
+        assert!((orbit.distance() - 9.0).abs() < 1e-4);
+        orbit.process_scroll(&MouseScrollDelta::LineDelta(0.0, -1.0));
+        assert!((orbit.distance() - 9.9).abs() < 1e-4);
+
+        for _ in 0..100 {
+            orbit.process_scroll(&MouseScrollDelta::LineDelta(0.0, 1.0));
+        }
+        assert_eq!(orbit.distance(), OrbitCamera::MIN_DISTANCE);
+        for _ in 0..100 {
+            orbit.process_scroll(&MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, -100.0)));
+        }
+        assert_eq!(orbit.distance(), OrbitCamera::MAX_DISTANCE);
+    }
+
+    #[test]
+    fn the_orbit_camera_sits_behind_the_focus_and_looks_at_it() {
+        let focus = Point3::new(1.0, 2.0, 3.0);
+
+        // Looking down +x puts the eye on the -x side of the focus
+        let orbit = OrbitCamera::new(focus, 10.0, Deg(0.0), Deg(0.0));
+        assert!((orbit.eye() - Point3::new(-9.0, 2.0, 3.0)).magnitude() < 1e-4);
+
+        let orbit = OrbitCamera::new(focus, 10.0, Deg(90.0), Deg(-30.0));
+        let camera = orbit.to_camera();
+        assert!(((orbit.eye() - focus).magnitude() - 10.0).abs() < 1e-4);
+        assert!((camera.position - orbit.eye()).magnitude() < 1e-4);
+        assert!((camera.position + camera.forward() * 10.0 - focus).magnitude() < 1e-4);
+        // Looking down at the focus means being above it
+        assert!(orbit.eye().y > focus.y);
+    }
+
+    #[test]
+    fn orthographic_projection_ignores_distance_unlike_perspective() {
+        // In view space, 5 units in front of the camera, which looks down -z
+        let point = Vector4::new(2.0, 1.0, -5.0, 1.0);
+        let to_ndc = |projection: &Projection| {
+            let clip = projection.calc_matrix() * point;
+            clip.truncate() / clip.w
+        };
+        let close = |a: Vector3<f32>, b: Vector3<f32>| (a - b).magnitude() < 1e-4;
+
+        // With a 90 degree field of view the view is 10 units wide at that distance
+        let perspective = to_ndc(&Projection::new(100, 100, Deg(90.0), 0.1, 100.0));
+        let depth = 100.0 / 99.9 * (1.0 - 0.1 / 5.0);
+        assert!(close(perspective, Vector3::new(0.4, 0.2, depth)), "{:?}", perspective);
+
+        // A 20 unit tall orthographic view is 20 units wide at every distance, and depth is linear
+        let orthographic = to_ndc(&Projection::orthographic(20.0, 20.0, 0.1, 100.0));
+        assert!(close(orthographic, Vector3::new(0.2, 0.1, 4.9 / 99.9)), "{:?}", orthographic);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn walking_falls_onto_the_floor_and_stays_there() {
+        let renderer = crate::renderer::tests::headless();
+        let location = Vector2::new(0, 0);
+        let mut world = crate::world::tests::air_world(&renderer, &[location]);
+        for x in 4..=6 {
+            for z in 4..=6 {
+                world.set_block(location, Vector3::new(x, 0, z), crate::block::Block::new_stone());
+            }
+        }
+
+        let mut camera = Camera::new((5.0, 6.0, 5.0), Deg(0.0), Deg(0.0));
+        let mut controller = CameraController::new(4.0, 0.4);
+        controller.mode = ControllerMode::Walk;
+
+        for _ in 0..120 {
+            controller.update_camera(&mut camera, &world, 1.0 / 60.0);
+        }
+
+        // The floor's top is at 0.5, and the eyes sit EYE_HEIGHT above the feet
+        assert!((camera.position.y - (0.5 + EYE_HEIGHT)).abs() < 1e-3, "{:?}", camera.position);
+        assert!(controller.on_ground);
+        assert_eq!((camera.position.x, camera.position.z), (5.0, 5.0));
+    }
 }
@@ -1,14 +1,19 @@
+use std::fmt;
 use std::ops::Deref;
 use std::rc::Rc;
 
 use bytemuck::{Pod, Zeroable};
-use cgmath::{Vector2, Vector3, Zero};
+use cgmath::{Matrix4, Vector2, Vector3, Vector4};
 use encase::ShaderType;
+use hashbrown::HashMap;
 use ndarray::Array3;
 use wgpu::{BindGroup, DynamicOffset, RenderPass};
 use wgpu::util::DeviceExt;
 
 use crate::{block, renderer};
+use crate::block::{TextureAnimation, Tint};
+use crate::palette::BlockStorage;
+use crate::registry::TextureTable;
 
 /*
        (-1, 1, -1) /-------------------| (1, 1, -1)
@@ -25,7 +30,7 @@ use crate::{block, renderer};
 (-1, -1, 1) |-------------------| (1, -1, 1)
    */
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 /// An enum for the different faces of a cube to allow for easy toggling
 pub enum Direction {
     FRONT, // 0, 0, 1
@@ -37,6 +42,15 @@ pub enum Direction {
 }
 
 impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::FRONT,
+        Direction::BACK,
+        Direction::TOP,
+        Direction::BOTTOM,
+        Direction::LEFT,
+        Direction::RIGHT,
+    ];
+
     /// Returns the vertices that make up the face in a cube.
     pub fn cube_verts(&self) -> [Vector3<f32>; 4] {
         match self {
@@ -80,17 +94,6 @@ impl Direction {
     }
 
     /// Returns the indices that make up the face in a cube.
-    pub fn cube_indices(&self) -> [u32; 6] {
-        match self {
-            Direction::FRONT => [0, 1, 2, 2, 3, 0],
-            Direction::BACK => [4, 5, 6, 6, 7, 4],
-            Direction::TOP => [8, 9, 10, 10, 11, 8],
-            Direction::BOTTOM => [12, 13, 14, 14, 15, 12],
-            Direction::LEFT => [16, 17, 18, 18, 19, 16],
-            Direction::RIGHT => [20, 21, 22, 22, 23, 20],
-        }
-    }
-
     /// Returns the normal vector of the face.
     pub fn to_vec3(&self) -> Vector3<i32> {
         match self {
@@ -130,21 +133,121 @@ pub trait Vertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
 }
 
+/// A chunk vertex in a readable form. Meshes store `ChunkVertex`, which packs the same data into
+/// 8 bytes, so this is mostly useful for building and inspecting vertices.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnpackedChunkVertex {
+    /// Position relative to the chunk, which must lie on the half-block grid
+    pub position: Vector3<f32>,
+    /// Coordinates within the texture layer, each 0, 0.5 or 1
+    pub tex_coord: Vector2<f32>,
+    pub layer: u32,
+    pub animation: TextureAnimation,
+    pub face: Direction,
+    /// Light level of the block the face is exposed to, from 0 to `MAX_LIGHT`
+    pub light: u8,
+    /// Multiplied with the texture, see `BlockData::tint`
+    pub tint: Tint,
+}
+
 // Perhaps a more apt name would be BlockVertex but it's fine
+/// A packed chunk vertex, decoded by `vs_main`. Positions and texture coordinates are stored in
+/// half-block steps.
+///
+/// `data[0]`: x (6 bits), z (6 bits), y - `MIN_Y` + 0.5 (10 bits), face index (3 bits), tint
+/// index (7 bits)
+/// `data[1]`: u (2 bits), v (2 bits), texture layer (16 bits), light (4 bits), animation frames - 1
+/// (4 bits), animation ticks per frame - 1 (4 bits)
 #[repr(C)]
-#[derive(Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct ChunkVertex {
-    pub position: Vector3<f32>,
-    pub tex_coord: Vector2<f32>,
+    data: [u32; 2],
 }
 
 unsafe impl Pod for ChunkVertex {}
 
 unsafe impl Zeroable for ChunkVertex {}
 
+impl ChunkVertex {
+    /// Packed y values start at the bottom of the chunk, `model_matrix` moves them back down
+    const Y_OFFSET: f32 = 0.5 - MIN_Y as f32;
+
+    pub fn new(
+        position: Vector3<f32>,
+        tex_coord: Vector2<f32>,
+        layer: u32,
+        animation: TextureAnimation,
+        face: &Direction,
+        light: u8,
+        tint: Tint,
+    ) -> Self {
+        let half_steps = |value: f32| (value * 2.0).round() as u32;
+
+        let x = half_steps(position.x + 0.5);
+        let y = half_steps(position.y + Self::Y_OFFSET);
+        let z = half_steps(position.z + 0.5);
+
+        let vertex = Self {
+            data: [
+                x | z << 6 | y << 12 | face.index() << 22 | tint.index() << 25,
+                half_steps(tex_coord.x)
+                    | half_steps(tex_coord.y) << 2
+                    | layer << 4
+                    | (light as u32) << 20
+                    | (animation.frames - 1) << 24
+                    | (animation.ticks_per_frame - 1) << 28,
+            ],
+        };
+
+        debug_assert_eq!(
+            vertex.unpack(),
+            UnpackedChunkVertex { position, tex_coord, layer, animation, face: face.clone(), light, tint },
+            "vertex doesn't fit the packed format"
+        );
+
+        vertex
+    }
+
+    pub fn unpack(&self) -> UnpackedChunkVertex {
+        let bits = |word: usize, shift: u32, len: u32| (self.data[word] >> shift) & ((1 << len) - 1);
+        let from_half_steps = |value: u32| value as f32 / 2.0;
+
+        UnpackedChunkVertex {
+            position: Vector3::new(
+                from_half_steps(bits(0, 0, 6)) - 0.5,
+                from_half_steps(bits(0, 12, 10)) - Self::Y_OFFSET,
+                from_half_steps(bits(0, 6, 6)) - 0.5,
+            ),
+            tex_coord: Vector2::new(from_half_steps(bits(1, 0, 2)), from_half_steps(bits(1, 2, 2))),
+            layer: bits(1, 4, 16),
+            animation: TextureAnimation {
+                frames: bits(1, 24, 4) + 1,
+                ticks_per_frame: bits(1, 28, 4) + 1,
+            },
+            face: Direction::ALL[bits(0, 22, 3) as usize].clone(),
+            light: bits(1, 20, 4) as u8,
+            tint: Tint::from_index(bits(0, 25, 7)).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<UnpackedChunkVertex> for ChunkVertex {
+    fn from(vertex: UnpackedChunkVertex) -> Self {
+        ChunkVertex::new(
+            vertex.position,
+            vertex.tex_coord,
+            vertex.layer,
+            vertex.animation,
+            &vertex.face,
+            vertex.light,
+            vertex.tint,
+        )
+    }
+}
+
 impl Vertex for ChunkVertex {
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        static ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+        static ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Uint32x2];
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<ChunkVertex>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
@@ -156,13 +259,20 @@ impl Vertex for ChunkVertex {
 #[repr(C)]
 #[derive(ShaderType, Debug, Copy, Clone)]
 pub struct ChunkUniform {
-    pub chunk_offset: Vector3<f32>,
+    /// Transforms the chunk's local vertex positions into world space
+    pub model: Matrix4<f32>,
+    /// Color multiplied into every fragment of the chunk (e.g. for biome tinting)
+    pub tint: Vector4<f32>,
+    /// Opacity of the whole chunk, used to fade in newly loaded chunks
+    pub fade: f32,
 }
 
 impl ChunkUniform {
-    pub fn new(chunk_offset: Vector3<f32>) -> Self {
+    pub fn new(model: Matrix4<f32>, tint: Vector4<f32>, fade: f32) -> Self {
         Self {
-            chunk_offset,
+            model,
+            tint,
+            fade,
         }
     }
 }
@@ -171,114 +281,456 @@ unsafe impl Pod for ChunkUniform {}
 unsafe impl Zeroable for ChunkUniform {}
 
 pub const ATLAS_SIZE: usize = 256;
+/// The brightest light level a block can have. Light levels fit in 4 bits.
+pub const MAX_LIGHT: u8 = 15;
 pub const TEXTURE_SIZE: usize = 16;
 
+/// Size information about a mesh. The byte counts only cover the faces that are actually in use,
+/// while `gpu_bytes` is what's allocated for the mesh's buffers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MeshStats {
+    pub face_count: usize,
+    pub vertex_bytes: usize,
+    pub index_bytes: usize,
+    pub gpu_bytes: usize,
+}
+
+impl std::ops::Add for MeshStats {
+    type Output = MeshStats;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        MeshStats {
+            face_count: self.face_count + rhs.face_count,
+            vertex_bytes: self.vertex_bytes + rhs.vertex_bytes,
+            index_bytes: self.index_bytes + rhs.index_bytes,
+            gpu_bytes: self.gpu_bytes + rhs.gpu_bytes,
+        }
+    }
+}
+
+impl std::iter::Sum for MeshStats {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(MeshStats::default(), |a, b| a + b)
+    }
+}
+
+/// The indices of a face's two triangles, relative to its first vertex
+const QUAD_INDICES: [u32; 6] = [0, 1, 2, 2, 3, 0];
+
+/// The CPU side of a chunk mesh. It holds no GPU resources, so it can be built on a worker thread
+/// and handed to the main thread once it's finished.
+///
+/// Faces are stored in the order they were added rather than by block position. Removing one
+/// moves the last face into its place, so there are never any gaps to draw or compact away, and
+/// faces are found again through their slot.
+#[derive(Clone)]
+pub struct ChunkMeshData {
+    /// Four vertices for every face, packed without gaps
+    pub vertices: Vec<ChunkVertex>,
+    /// Six indices for every face, the same two triangles for each face's vertices
+    pub indices: Vec<u32>,
+    /// Which face in the packed data each face slot (see `ChunkMesh::face_slot`) holds
+    faces: HashMap<u32, u32>,
+    /// The slot of every packed face, so the last face can be moved into the gap left behind
+    /// when one is removed
+    slots: Vec<u32>,
+}
+
+// The mesher thread and `World::generate_chunks_parallel` rely on chunks and mesh data moving
+// between threads, so keep GPU handles like the `Rc`s in `ChunkMesh` out of them
+const _: fn() = || {
+    fn assert_thread_safe<T: Send + Sync>() {}
+    assert_thread_safe::<Chunk>();
+    assert_thread_safe::<ChunkMeshData>();
+};
+
+impl Default for ChunkMeshData {
+    fn default() -> Self {
+        ChunkMeshData::new()
+    }
+}
+
+impl ChunkMeshData {
+    pub fn new() -> Self {
+        Self {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            faces: HashMap::new(),
+            slots: Vec::new(),
+        }
+    }
+
+    pub fn stats(&self) -> MeshStats {
+        MeshStats {
+            face_count: self.face_count(),
+            vertex_bytes: std::mem::size_of_val(self.vertices.as_slice()),
+            index_bytes: std::mem::size_of_val(self.indices.as_slice()),
+            gpu_bytes: std::mem::size_of_val(self.vertices.as_slice()) + std::mem::size_of_val(self.indices.as_slice()),
+        }
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// How many indices have to be drawn to cover every face in the mesh. The faces are packed,
+    /// so that's all of them.
+    pub fn index_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// How many bytes the vectors have allocated past the faces in use
+    pub fn reclaimable_bytes(&self) -> usize {
+        (self.vertices.capacity() - self.vertices.len()) * std::mem::size_of::<ChunkVertex>()
+            + (self.indices.capacity() - self.indices.len()) * std::mem::size_of::<u32>()
+    }
+
+    /// Frees the memory allocated past the faces in use and returns how many bytes that was.
+    /// `add_face` grows the mesh again if needed.
+    pub fn compact(&mut self) -> usize {
+        let reclaimed = self.reclaimable_bytes();
+
+        self.vertices.shrink_to_fit();
+        self.indices.shrink_to_fit();
+        self.slots.shrink_to_fit();
+
+        reclaimed
+    }
+
+    /// Builds the mesh for every visible face in the chunk from scratch. `neighbors` are the
+    /// loaded chunks on the FRONT, BACK, LEFT and RIGHT sides (in that order), and are used to
+    /// cull faces on the chunk's border.
+    pub fn from_chunk(chunk: &Chunk, neighbors: [Option<&Chunk>; 4], textures: &TextureTable) -> Self {
+        let mut data = Self::new();
+
+        for (position, block) in chunk.iter_blocks() {
+            for face in Direction::ALL.iter() {
+                let v = face.to_vec3() + position;
+
+                let (neighbor_chunk, neighbor_position) = if chunk.get_block(v).is_some() {
+                    (Some(chunk), v)
+                } else {
+                    let neighbor_chunk = match face {
+                        Direction::FRONT => neighbors[0],
+                        Direction::BACK => neighbors[1],
+                        Direction::LEFT => neighbors[2],
+                        Direction::RIGHT => neighbors[3],
+                        Direction::TOP | Direction::BOTTOM => None,
+                    };
+
+                    (neighbor_chunk, Vector3::new(
+                        v.x.rem_euclid(CHUNK_WIDTH as i32),
+                        v.y,
+                        v.z.rem_euclid(CHUNK_DEPTH as i32),
+                    ))
+                };
+
+                let neighbor = neighbor_chunk.and_then(|neighbor_chunk| neighbor_chunk.get_block(neighbor_position));
+
+                if block.face_visible(face, neighbor) {
+                    let light = neighbor_chunk
+                        .and_then(|neighbor_chunk| neighbor_chunk.get_light(neighbor_position))
+                        .unwrap_or(MAX_LIGHT);
+
+                    data.add_face(position, face, block, light, textures)
+                }
+            }
+        }
+
+        data
+    }
+
+    /// Adds the block's face to the mesh. `light` is the light level of the block the face is
+    /// exposed to. Blocks that give off light are never darker than their own emission.
+    pub fn add_face(
+        &mut self,
+        block_position: Vector3<i32>,
+        face: &Direction,
+        block: &block::Block,
+        light: u8,
+        textures: &TextureTable,
+    ) {
+        let vertices = {
+            let position = block_position.cast::<f32>().unwrap();
+            let geometry = block.deref().face_geometry(face);
+            let (layer, animation) = block.face_texture(face, textures);
+            let tex_coords = block.deref().face_uv(face).apply(geometry.tex_coords);
+            let light = light.max(block.light_emission()).min(MAX_LIGHT);
+            let tint = block.tint(face);
+
+            geometry.positions
+                .iter()
+                .zip(tex_coords.iter())
+                .map(|(p, t)| {
+                    ChunkVertex::new(*p + position, *t, layer, animation, face, light, tint)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let slot = ChunkMesh::face_slot(block_position, face);
+
+        // New faces go on the end, faces that are already in the mesh are replaced in place
+        let index = match self.faces.get(&slot) {
+            Some(index) => *index as usize,
+            None => {
+                let index = self.slots.len();
+                self.faces.insert(slot, index as u32);
+                self.slots.push(slot);
+                self.vertices.extend([ChunkVertex::zeroed(); 4]);
+                self.indices.extend(QUAD_INDICES.map(|i| i + 4 * index as u32));
+
+                index
+            }
+        };
+
+        self.vertices[index * 4..index * 4 + 4].copy_from_slice(&vertices);
+    }
+
+    /// Removes the face from the mesh if it's there. The last face is moved into its place, so
+    /// the faces stay packed.
+    pub fn remove_face(&mut self, position: Vector3<i32>, face: &Direction) {
+        let index = match self.faces.remove(&ChunkMesh::face_slot(position, face)) {
+            Some(index) => index as usize,
+            None => return,
+        };
+
+        let last = self.slots.len() - 1;
+        if index != last {
+            let moved = self.slots[last];
+            self.slots[index] = moved;
+            self.faces.insert(moved, index as u32);
+            self.vertices.copy_within(last * 4..last * 4 + 4, index * 4);
+        }
+
+        // Every face's indices follow the same pattern, so the last face's can just be dropped
+        self.slots.pop();
+        self.vertices.truncate(last * 4);
+        self.indices.truncate(last * 6);
+    }
+}
+
+/// Index data converted to the format it's uploaded in. Meshes with few enough vertices use
+/// 16 bit indices, which halves the size of their index buffer.
+pub enum MeshIndices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl MeshIndices {
+    /// The smallest index format that can address `vertex_count` vertices
+    pub fn format_for(vertex_count: usize) -> wgpu::IndexFormat {
+        if vertex_count <= u16::MAX as usize + 1 {
+            wgpu::IndexFormat::Uint16
+        } else {
+            wgpu::IndexFormat::Uint32
+        }
+    }
+
+    pub fn new(indices: &[u32], format: wgpu::IndexFormat) -> Self {
+        match format {
+            wgpu::IndexFormat::Uint16 => MeshIndices::U16(indices.iter().map(|i| *i as u16).collect()),
+            wgpu::IndexFormat::Uint32 => MeshIndices::U32(indices.to_vec()),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            MeshIndices::U16(indices) => bytemuck::cast_slice(indices),
+            MeshIndices::U32(indices) => bytemuck::cast_slice(indices),
+        }
+    }
+}
+
+/// The GPU side of a chunk mesh. The buffers live on the main thread, while the `ChunkMeshData`
+/// they're filled from can be built anywhere.
 #[derive(Clone)]
 pub struct ChunkMesh {
     vertex_buffer: Rc<wgpu::Buffer>,
     index_buffer: Rc<wgpu::Buffer>,
+    index_format: wgpu::IndexFormat,
+    /// How many vertices and indices the buffers were created to hold
+    buffer_lengths: (usize, usize),
     num_elements: u32,
+    /// Whether `data` changed since it was last uploaded
+    dirty: bool,
     pub uniform_offset: DynamicOffset,
-    pub vertices: Vec<ChunkVertex>,
-    pub indices: Vec<u32>,
+    pub data: ChunkMeshData,
 }
 
 impl ChunkMesh {
     pub fn new(uniform_offset: DynamicOffset, device: &wgpu::Device) -> Self {
-        let vertices = vec![
-            ChunkVertex { position: Vector3::zero(), tex_coord: Vector2::zero() }; 24 * CHUNK_SIZE
-        ];
+        let data = ChunkMeshData::new();
 
-        let indices = vec![0u32; 36 * CHUNK_SIZE];
+        let index_format = MeshIndices::format_for(data.vertices.len());
+        let (vertex_buffer, index_buffer) = ChunkMesh::create_buffers(&data, index_format, device);
+
+        ChunkMesh {
+            vertex_buffer: Rc::new(vertex_buffer),
+            index_buffer: Rc::new(index_buffer),
+            index_format,
+            buffer_lengths: (data.vertices.len(), data.indices.len()),
+            num_elements: data.index_count() as u32,
+            dirty: false,
+            uniform_offset,
+            data,
+        }
+    }
 
+    fn create_buffers(data: &ChunkMeshData, index_format: wgpu::IndexFormat, device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(&vertices),
+            contents: bytemuck::cast_slice(&data.vertices),
             usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         });
 
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::cast_slice(&indices),
+            contents: MeshIndices::new(&data.indices, index_format).as_bytes(),
             usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
         });
 
-        ChunkMesh {
-            vertex_buffer: Rc::new(vertex_buffer),
-            index_buffer: Rc::new(index_buffer),
-            num_elements: indices.len() as u32,
-            uniform_offset,
-            vertices,
-            indices,
-        }
+        (vertex_buffer, index_buffer)
     }
 
-    pub fn flatten_3d(v: (i32, i32, i32)) -> u64 {
-        // CHUNK_HEIGHT >> 1 is added to the y position to allow for y values of -127 to 128
-        let (x, y, z) = v;
-        (x + CHUNK_WIDTH as i32 * (y + (CHUNK_HEIGHT >> 1) as i32 + CHUNK_HEIGHT as i32 * z)) as u64
-    }
+    /// Replaces the mesh data with a finished `ChunkMeshData` and uploads it like `sync`
+    pub fn upload(&mut self, data: ChunkMeshData, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.data = data;
+        self.num_elements = self.data.index_count() as u32;
 
-    pub fn buffer_write(&self, queue: &wgpu::Queue) {
-        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
-        queue.write_buffer(&self.index_buffer, 0, bytemuck::cast_slice(&self.indices));
+        self.sync(device, queue);
     }
 
-    pub fn get_buf_offset(chunk_position: Vector3<i32>, face: &Direction) -> (u64, u64) {
-        let flattened = ChunkMesh::flatten_3d(chunk_position.into());
+    /// Uploads the mesh data after it was replaced or patched in place with `add_face` and
+    /// `remove_face`, whether or not it's dirty. The buffers are only recreated if the data grew
+    /// past them or needs a different index format, otherwise the data is written to the start of
+    /// the old ones and whatever is left after it is never drawn. `compact` shrinks them again.
+    pub fn sync(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let index_format = MeshIndices::format_for(self.data.vertices.len());
+        let (vertex_len, index_len) = self.buffer_lengths;
 
-        let v_off = flattened * 24
-            + face.index() as u64 * 4;
+        if self.data.vertices.len() > vertex_len || self.data.indices.len() > index_len || index_format != self.index_format {
+            self.recreate_buffers(index_format, device);
+        } else {
+            self.buffer_write(queue);
+        }
 
-        let i_off = flattened * 36
-            + face.index() as u64 * 6;
+        self.dirty = false;
+    }
 
-        (v_off, i_off)
+    /// Whether the mesh data changed since it was last uploaded, see `World::update_buffers`
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
     }
 
-    pub fn add_face(
+    /// How many indices a draw of the mesh uses
+    pub fn num_elements(&self) -> u32 {
+        self.num_elements
+    }
+
+    fn recreate_buffers(&mut self, index_format: wgpu::IndexFormat, device: &wgpu::Device) {
+        let (vertex_buffer, index_buffer) = ChunkMesh::create_buffers(&self.data, index_format, device);
+        self.vertex_buffer = Rc::new(vertex_buffer);
+        self.index_buffer = Rc::new(index_buffer);
+        self.index_format = index_format;
+        self.buffer_lengths = (self.data.vertices.len(), self.data.indices.len());
+        // The new buffers start out with the data in them
+        self.dirty = false;
+    }
+
+    /// Throws away the mesh and builds it again from the chunk's blocks, using the neighbors
+    /// (front, back, left, right) to cull faces on the border. This is the simple and always
+    /// correct path, at a cost: building the mesh of a chunk half full of stone takes about 4ms
+    /// in a release build, where patching a single face like `World::set_block` does takes well
+    /// under a microsecond (see `benches/meshing.rs`). Prefer `World::queue_remesh` to do this off
+    /// the main thread.
+    pub fn rebuild(
         &mut self,
-        block_position: Vector3<i32>,
-        face: &Direction,
-        block: &block::Block,
+        chunk: &Chunk,
+        neighbors: [Option<&Chunk>; 4],
+        textures: &TextureTable,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
     ) {
-        let flattened = ChunkMesh::flatten_3d(block_position.into());
+        let mut data = ChunkMeshData::from_chunk(chunk, neighbors, textures);
+        data.compact();
 
-        let vertices = {
-            let position = block_position.cast::<f32>().unwrap();
+        self.upload(data, device, queue);
+    }
 
-            face.cube_verts()
-                .iter()
-                .zip(
-                    &block.deref().texture_coordinates().to_vec()
-                        [(face.index() * 4) as usize..(face.index() * 4 + 4) as usize],
-                )
-                .map(|(p, t)| {
-                    ChunkVertex {
-                        position: *p + position,
-                        tex_coord: *t,
-                    }
-                })
-                .collect::<Vec<_>>()
+    pub fn stats(&self) -> MeshStats {
+        MeshStats {
+            gpu_bytes: self.buffer_bytes(self.buffer_lengths),
+            ..self.data.stats()
+        }
+    }
+
+    /// How many bytes buffers holding the given number of vertices and indices take up
+    fn buffer_bytes(&self, (vertex_len, index_len): (usize, usize)) -> usize {
+        let index_size = match self.index_format {
+            wgpu::IndexFormat::Uint16 => std::mem::size_of::<u16>(),
+            wgpu::IndexFormat::Uint32 => std::mem::size_of::<u32>(),
         };
 
-        let indices = face.cube_indices().map(|i| i + 24 * flattened as u32);
+        vertex_len * std::mem::size_of::<ChunkVertex>() + index_len * index_size
+    }
+
+    /// How many bytes the buffers and the mesh data have allocated past the faces in use. The
+    /// buffers only grow when faces are added with `sync`, so they can be larger than needed
+    /// after faces are removed.
+    pub fn reclaimable_bytes(&self) -> usize {
+        self.buffer_bytes(self.buffer_lengths) - self.buffer_bytes((self.data.vertices.len(), self.data.indices.len()))
+            + self.data.reclaimable_bytes()
+    }
 
-        let (v_off, i_off) = ChunkMesh::get_buf_offset(block_position, &face);
+    /// Frees the memory allocated past the faces in use, see `ChunkMeshData::compact`, and
+    /// shrinks the buffers to fit the mesh. Returns how many bytes were freed.
+    pub fn compact(&mut self, device: &wgpu::Device) -> usize {
+        let reclaimed = self.reclaimable_bytes();
 
-        self.vertices.splice(v_off as usize..(v_off as usize + vertices.len()), vertices);
-        self.indices.splice(i_off as usize..(i_off as usize + indices.len()), indices);
+        self.data.compact();
+        if self.buffer_lengths != (self.data.vertices.len(), self.data.indices.len()) {
+            let index_format = MeshIndices::format_for(self.data.vertices.len());
+            self.recreate_buffers(index_format, device);
+        }
+
+        reclaimed
     }
 
-    pub fn remove_face(&mut self, position: Vector3<i32>, face: &Direction) {
-        let (v_off, i_off) = ChunkMesh::get_buf_offset(position, &face);
+    pub fn flatten_3d(v: (i32, i32, i32)) -> u64 {
+        let (x, y, z) = v;
+        (x as usize + CHUNK_WIDTH * (Chunk::local_y(y) + CHUNK_HEIGHT * z as usize)) as u64
+    }
 
-        self.vertices.splice(
-            v_off as usize..(v_off as usize + 4),
-            vec![ChunkVertex { position: Vector3::zero(), tex_coord: Vector2::zero() }; 4]
-        );
+    pub fn buffer_write(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.data.vertices));
+        queue.write_buffer(&self.index_buffer, 0, MeshIndices::new(&self.data.indices, self.index_format).as_bytes());
+    }
 
-        self.indices.splice(i_off as usize..(i_off as usize + 6), vec![0u32; 6]);
+    /// Identifies one face of one block in the chunk, for finding it in the packed mesh data
+    pub fn face_slot(chunk_position: Vector3<i32>, face: &Direction) -> u32 {
+        ChunkMesh::flatten_3d(chunk_position.into()) as u32 * 6 + face.index()
+    }
+
+    pub fn add_face(
+        &mut self,
+        block_position: Vector3<i32>,
+        face: &Direction,
+        block: &block::Block,
+        light: u8,
+        textures: &TextureTable,
+    ) {
+        self.data.add_face(block_position, face, block, light, textures);
+        self.num_elements = self.data.index_count() as u32;
+        self.dirty = true;
+    }
+
+    pub fn remove_face(&mut self, position: Vector3<i32>, face: &Direction) {
+        self.data.remove_face(position, face);
+
+        // Faces that weren't there don't need an upload
+        let num_elements = self.data.index_count() as u32;
+        self.dirty |= num_elements != self.num_elements;
+        self.num_elements = num_elements;
     }
 }
 
@@ -288,49 +740,621 @@ pub const CHUNK_DEPTH: usize = 16;
 pub const CHUNK_DIMS: (usize, usize, usize) = (CHUNK_WIDTH, CHUNK_HEIGHT, CHUNK_DEPTH);
 pub const CHUNK_SIZE: usize = CHUNK_WIDTH * CHUNK_HEIGHT * CHUNK_DEPTH;
 
+/// The lowest and highest local y positions. Local positions are centered vertically on the
+/// chunk, `Chunk::local_y` and `Chunk::world_y` convert between them and array indices.
+pub const MIN_Y: i32 = -((CHUNK_HEIGHT >> 1) as i32);
+pub const MAX_Y: i32 = MIN_Y + CHUNK_HEIGHT as i32 - 1;
+
+// `ChunkVertex` packs y into 10 bits of half-block steps
+const _: () = assert!(CHUNK_HEIGHT < 512, "CHUNK_HEIGHT doesn't fit in a packed vertex");
+
+// `ChunkMesh::face_slot` numbers every face of every block with a `u32`
+const _: () = assert!(CHUNK_SIZE * 6 <= u32::MAX as usize, "too many blocks in a chunk to number their faces");
+
+/// A local block position that's outside of the chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkBoundsError {
+    pub position: Vector3<i32>,
+}
+
+impl fmt::Display for ChunkBoundsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "block position {:?} is outside of the chunk", self.position)
+    }
+}
+
+impl std::error::Error for ChunkBoundsError {}
+
 #[derive(Clone)]
 pub struct Chunk {
-    pub blocks: Array3<block::Block>,
+    blocks: BlockStorage,
+    /// Light level of every block, from 0 to `MAX_LIGHT`
+    pub light: Array3<u8>,
     pub world_offset: Vector2<i32>,
 }
 
 impl Chunk {
     pub fn new(world_offset: Vector2<i32>) -> Self {
-        let blocks =
-            Array3::<block::Block>::from_shape_fn(CHUNK_DIMS, |_| block::Block::Air(block::Air));
+        let blocks = BlockStorage::from_fn(|_, _, _| block::Block::Air(block::Air));
 
         Self {
             blocks,
+            light: Array3::from_elem(CHUNK_DIMS, MAX_LIGHT),
             world_offset,
         }
     }
 
-    pub fn set_block(&mut self, position: Vector3<i32>, block: block::Block) {
+    /// Creates a chunk by evaluating `f` for every local position in it. Positions use the same
+    /// convention as `get_block`/`set_block`, so y ranges from `MIN_Y` to `MAX_Y`.
+    pub fn generate(world_offset: Vector2<i32>, f: impl Fn(Vector3<i32>) -> block::Block) -> Self {
+        let blocks = BlockStorage::from_fn(|x, y, z| {
+            f(Vector3::new(x as i32, Chunk::world_y(y), z as i32))
+        });
 
-        self.blocks[[
+        Self {
+            blocks,
+            light: Array3::from_elem(CHUNK_DIMS, MAX_LIGHT),
+            world_offset,
+        }
+    }
+
+    /// Iterates over every non-air block in the chunk along with its local position, using the
+    /// same convention as `get_block`/`set_block`.
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (Vector3<i32>, &block::Block)> {
+        self.blocks
+            .indexed_iter_except(block::Block::Air(block::Air))
+            .filter(|(_, block)| !matches!(block, block::Block::Air(_)))
+            .map(|((x, y, z), block)| {
+                (Vector3::new(x as i32, Chunk::world_y(y), z as i32), block)
+            })
+    }
+
+    /// Iterates over every block in the chunk, air included, in the order of
+    /// `ChunkMesh::flatten_3d`: x first, then y from `MIN_Y` up, then z. Positions use the same
+    /// convention as `get_block`/`set_block`.
+    pub fn iter_all_blocks(&self) -> impl Iterator<Item = (Vector3<i32>, &block::Block)> {
+        (0..CHUNK_DEPTH as i32)
+            .flat_map(|z| (MIN_Y..=MAX_Y).flat_map(move |y| (0..CHUNK_WIDTH as i32).map(move |x| Vector3::new(x, y, z))))
+            .map(|position| (position, self.get_block(position).unwrap()))
+    }
+
+    /// The mutable counterpart of `iter_all_blocks`. Blocks are stored in a palette, so there's
+    /// no `&mut Block` to hand out, instead `f` returns the block to replace the one at the
+    /// position with, if any. Returns how many blocks changed.
+    pub fn update_blocks(&mut self, mut f: impl FnMut(Vector3<i32>, &block::Block) -> Option<block::Block>) -> usize {
+        let mut changed = 0;
+
+        for z in 0..CHUNK_DEPTH {
+            for y in 0..CHUNK_HEIGHT {
+                for x in 0..CHUNK_WIDTH {
+                    let position = Vector3::new(x as i32, Chunk::world_y(y), z as i32);
+                    let replacement = match self.blocks.get(x, y, z) {
+                        Some(block) => f(position, block),
+                        None => None,
+                    };
+
+                    if let Some(block) = replacement {
+                        if self.blocks.set(x, y, z, block) != block {
+                            changed += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Converts a local y position to an index into the chunk's block and light arrays. Positions
+    /// below `MIN_Y` wrap around to huge indices, which the arrays reject as out of bounds.
+    pub const fn local_y(y: i32) -> usize {
+        y.wrapping_sub(MIN_Y) as usize
+    }
+
+    /// Converts an index into the chunk's block and light arrays back to a local y position
+    pub const fn world_y(index: usize) -> i32 {
+        index as i32 + MIN_Y
+    }
+
+    /// Converts a local position in the chunk to world space
+    pub fn to_world(&self, position: Vector3<i32>) -> Vector3<i32> {
+        position + Vector3::new(
+            self.world_offset.x * CHUNK_WIDTH as i32,
+            0,
+            self.world_offset.y * CHUNK_DEPTH as i32,
+        )
+    }
+
+    /// Where the chunk's local origin ends up when rendering with the world's origin moved to the
+    /// chunk at `origin` (see `World::rebase_origin`). Computed in integers so it stays exact no
+    /// matter how far the chunk is from spawn.
+    pub fn render_position(&self, origin: Vector2<i32>) -> Vector3<f32> {
+        let origin = Vector3::new(origin.x * CHUNK_WIDTH as i32, 0, origin.y * CHUNK_DEPTH as i32);
+
+        (self.to_world(Vector3::new(0, 0, 0)) - origin).cast::<f32>().unwrap()
+    }
+
+    /// The transform placing the chunk's mesh in the world, relative to the `origin` chunk. Packed
+    /// vertices count y up from the bottom of the chunk, so this also moves them down by `MIN_Y`.
+    pub fn model_matrix(&self, origin: Vector2<i32>) -> Matrix4<f32> {
+        Matrix4::from_translation(self.render_position(origin) + Vector3::new(0.0, MIN_Y as f32, 0.0))
+    }
+
+    /// The bounding box of everything that can be drawn in the chunk, relative to the `origin` chunk
+    pub fn bounds(&self, origin: Vector2<i32>) -> (Vector3<f32>, Vector3<f32>) {
+        let corner = self.render_position(origin) + Vector3::new(0.0, MIN_Y as f32, 0.0);
+
+        // Blocks are centered on their position, so the chunk starts half a block early
+        let min = corner - Vector3::new(0.5, 0.5, 0.5);
+        let max = min + Vector3::new(CHUNK_WIDTH as f32, CHUNK_HEIGHT as f32, CHUNK_DEPTH as f32);
+
+        (min, max)
+    }
+
+    /// Whether a local position is inside the chunk, which is x and z from 0 to 15 and y from
+    /// `MIN_Y` to `MAX_Y`
+    pub fn contains(position: Vector3<i32>) -> bool {
+        (0..CHUNK_WIDTH as i32).contains(&position.x)
+            && (MIN_Y..=MAX_Y).contains(&position.y)
+            && (0..CHUNK_DEPTH as i32).contains(&position.z)
+    }
+
+    /// Places a block and returns the one it replaced
+    pub fn set_block(&mut self, position: Vector3<i32>, block: block::Block) -> Result<block::Block, ChunkBoundsError> {
+        if !Chunk::contains(position) {
+            return Err(ChunkBoundsError { position });
+        }
+
+        Ok(self.blocks.set(
             position.x as usize,
-            (position.y + (CHUNK_HEIGHT >> 1) as i32) as usize,
+            Chunk::local_y(position.y),
             position.z as usize,
-        ]] = block;
+            block,
+        ))
+    }
+
+    /// Sets every block in the box between `min` and `max`, inclusive, clamping the box to the
+    /// chunk. Returns how many blocks changed. The mesh isn't touched, see `World::fill_region`.
+    pub fn fill_region(&mut self, min: Vector3<i32>, max: Vector3<i32>, block: block::Block) -> usize {
+        let min = Vector3::new(min.x.max(0), min.y.max(MIN_Y), min.z.max(0));
+        let max = Vector3::new(
+            max.x.min(CHUNK_WIDTH as i32 - 1),
+            max.y.min(MAX_Y),
+            max.z.min(CHUNK_DEPTH as i32 - 1),
+        );
+
+        let mut changed = 0;
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    if self.blocks.set(x as usize, Chunk::local_y(y), z as usize, block) != block {
+                        changed += 1;
+                    }
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Replaces every block in the chunk
+    pub fn fill(&mut self, block: block::Block) {
+        self.blocks = BlockStorage::from_fn(|_, _, _| block);
     }
 
-    pub fn get_block(&self, mut position: Vector3<i32>) -> Option<&block::Block> {
-        // let mut position: Option<Vector3<usize>> = position.cast();
-        position.y = position.y + (CHUNK_HEIGHT >> 1) as i32;
-        self.blocks.get((
+    pub fn get_block(&self, position: Vector3<i32>) -> Option<&block::Block> {
+        self.blocks.get(
             position.x as usize,
-            position.y as usize,
+            Chunk::local_y(position.y),
             position.z as usize,
-        ))
+        )
+    }
+
+    /// Roughly how many bytes the chunk's blocks take up in memory
+    pub fn block_memory(&self) -> usize {
+        self.blocks.heap_size()
+    }
+
+    /// Sets the light level of a block, clamped to `MAX_LIGHT`. The mesh only picks it up once
+    /// the faces around the block are rebuilt.
+    pub fn set_light(&mut self, position: Vector3<i32>, value: u8) -> Result<(), ChunkBoundsError> {
+        if !Chunk::contains(position) {
+            return Err(ChunkBoundsError { position });
+        }
+
+        self.light[[
+            position.x as usize,
+            Chunk::local_y(position.y),
+            position.z as usize,
+        ]] = value.min(MAX_LIGHT);
+
+        Ok(())
+    }
+
+    pub fn get_light(&self, position: Vector3<i32>) -> Option<u8> {
+        self.light.get((
+            position.x as usize,
+            Chunk::local_y(position.y),
+            position.z as usize,
+        )).copied()
     }
 }
 
 impl renderer::Draw for ChunkMesh {
     fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera_bind_group: &'a BindGroup, uniforms: &'a BindGroup) {
+        // Compacted meshes without any faces have empty buffers, which can't be bound
+        if self.num_elements == 0 {
+            return;
+        }
+
         render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.set_index_buffer(self.index_buffer.slice(..), self.index_format);
         render_pass.set_bind_group(0, camera_bind_group, &[]);
         render_pass.set_bind_group(1, uniforms, &[self.uniform_offset]);
         render_pass.draw_indexed(0..self.num_elements, 0, 0..1);
     }
 }
+
+impl ChunkMesh {
+    /// Like `Draw::draw`, but takes the draw's arguments from a `DrawIndexedIndirect` in `indirect`
+    /// at `offset`, which is how chunks culled by `GpuCuller` are drawn
+    pub fn draw_indirect<'a>(
+        &'a self,
+        render_pass: &mut RenderPass<'a>,
+        camera_bind_group: &'a BindGroup,
+        uniforms: &'a BindGroup,
+        indirect: &'a wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+    ) {
+        if self.num_elements == 0 {
+            return;
+        }
+
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), self.index_format);
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, uniforms, &[self.uniform_offset]);
+        render_pass.draw_indexed_indirect(indirect, offset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+
+    /// How many faces a chunk gets when meshed with the given LEFT and RIGHT neighbors
+    fn face_count(chunk: &Chunk, left: Option<&Chunk>, right: Option<&Chunk>) -> usize {
+        ChunkMeshData::from_chunk(chunk, [None, None, left, right], &TextureTable::default()).face_count()
+    }
+
+    #[test]
+    fn a_block_inside_a_chunk_hides_the_face_it_shares() {
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+
+        chunk.set_block(Vector3::new(4, 0, 4), Block::new_stone()).unwrap();
+        assert_eq!(face_count(&chunk, None, None), 6);
+
+        chunk.set_block(Vector3::new(5, 0, 4), Block::new_stone()).unwrap();
+        assert_eq!(face_count(&chunk, None, None), 10);
+        assert_eq!(chunk.get_block(Vector3::new(5, 0, 4)), Some(&Block::new_stone()));
+    }
+
+    #[test]
+    fn a_border_block_is_culled_by_a_solid_neighbor() {
+        let mut left = Chunk::new(Vector2::new(0, 0));
+        let mut right = Chunk::new(Vector2::new(1, 0));
+
+        left.set_block(Vector3::new(15, 0, 0), Block::new_stone()).unwrap();
+        assert_eq!(face_count(&left, None, Some(&right)), 6);
+        assert_eq!(face_count(&right, Some(&left), None), 0);
+
+        right.set_block(Vector3::new(0, 0, 0), Block::new_stone()).unwrap();
+        assert_eq!(face_count(&left, None, Some(&right)), 5);
+        assert_eq!(face_count(&right, Some(&left), None), 5);
+    }
+
+    #[test]
+    fn a_border_block_without_a_neighbor_keeps_the_border_face() {
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+
+        chunk.set_block(Vector3::new(15, 0, 0), Block::new_stone()).unwrap();
+        assert_eq!(face_count(&chunk, None, None), 6);
+
+        // Positions past the border belong to the neighbor, they don't wrap around
+        assert!(chunk.set_block(Vector3::new(16, 0, 0), Block::new_stone()).is_err());
+        assert_eq!(face_count(&chunk, None, None), 6);
+    }
+
+    #[test]
+    fn removing_a_border_block_brings_back_both_border_faces() {
+        let mut left = Chunk::new(Vector2::new(0, 0));
+        let mut right = Chunk::new(Vector2::new(1, 0));
+
+        right.set_block(Vector3::new(0, 0, 0), Block::new_stone()).unwrap();
+        left.set_block(Vector3::new(15, 0, 0), Block::new_stone()).unwrap();
+        assert_eq!(face_count(&left, None, Some(&right)), 5);
+        assert_eq!(face_count(&right, Some(&left), None), 5);
+
+        left.set_block(Vector3::new(15, 0, 0), Block::new_air()).unwrap();
+        assert_eq!(face_count(&left, None, Some(&right)), 0);
+        assert_eq!(face_count(&right, Some(&left), None), 6);
+    }
+
+    #[test]
+    fn mesh_stats_count_the_faces_in_use() {
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+        chunk.set_block(Vector3::new(4, 0, 4), Block::new_stone()).unwrap();
+        let mut data = ChunkMeshData::from_chunk(&chunk, [None; 4], &TextureTable::default());
+
+        let stats = data.stats();
+        assert_eq!(stats.face_count, 6);
+        assert_eq!(stats.vertex_bytes, 6 * 4 * std::mem::size_of::<ChunkVertex>());
+        assert_eq!(stats.index_bytes, 6 * 6 * std::mem::size_of::<u32>());
+        assert_eq!(stats.gpu_bytes, stats.vertex_bytes + stats.index_bytes);
+
+        // Removed faces stop counting right away, the packed data shrinks with them
+        data.remove_face(Vector3::new(4, 0, 4), &Direction::TOP);
+        assert_eq!(data.stats().face_count, 5);
+        assert_eq!(data.stats().vertex_bytes, 5 * 4 * std::mem::size_of::<ChunkVertex>());
+    }
+
+    #[test]
+    fn mesh_stats_add_up() {
+        let stats = MeshStats {
+            face_count: 1,
+            vertex_bytes: 2,
+            index_bytes: 3,
+            gpu_bytes: 4,
+        };
+
+        assert_eq!(stats + stats, MeshStats { face_count: 2, vertex_bytes: 4, index_bytes: 6, gpu_bytes: 8 });
+        assert_eq!([stats; 3].into_iter().sum::<MeshStats>().gpu_bytes, 12);
+        assert_eq!(std::iter::empty().sum::<MeshStats>(), MeshStats::default());
+    }
+
+    #[test]
+    fn index_format_switches_to_u32_past_u16_vertices() {
+        assert_eq!(MeshIndices::format_for(0), wgpu::IndexFormat::Uint16);
+        assert_eq!(MeshIndices::format_for(u16::MAX as usize + 1), wgpu::IndexFormat::Uint16);
+        assert_eq!(MeshIndices::format_for(u16::MAX as usize + 2), wgpu::IndexFormat::Uint32);
+    }
+
+    #[test]
+    fn mesh_indices_keep_their_values_in_either_format() {
+        let indices = [0, 1, 2, 2, 3, 0, u16::MAX as u32];
+
+        let u16_indices = MeshIndices::new(&indices, wgpu::IndexFormat::Uint16);
+        assert_eq!(u16_indices.as_bytes(), bytemuck::cast_slice::<u16, u8>(&indices.map(|i| i as u16)));
+
+        let u32_indices = MeshIndices::new(&indices, wgpu::IndexFormat::Uint32);
+        assert_eq!(u32_indices.as_bytes(), bytemuck::cast_slice::<u32, u8>(&indices));
+        assert_eq!(u32_indices.as_bytes().len(), 2 * u16_indices.as_bytes().len());
+    }
+
+    #[test]
+    fn chunk_vertices_unpack_to_what_they_were_built_from() {
+        let corners = [
+            (Vector3::new(-0.5, MIN_Y as f32 - 0.5, -0.5), Vector2::new(0.0, 0.0)),
+            (Vector3::new(15.5, MAX_Y as f32 + 0.5, 15.5), Vector2::new(1.0, 1.0)),
+            // Inset faces like slabs sit on the half-block grid
+            (Vector3::new(3.0, 0.0, 7.5), Vector2::new(0.5, 0.5)),
+        ];
+        let animations = [
+            TextureAnimation { frames: 1, ticks_per_frame: 1 },
+            TextureAnimation { frames: TextureAnimation::MAX_FRAMES, ticks_per_frame: TextureAnimation::MAX_TICKS_PER_FRAME },
+        ];
+
+        for (position, tex_coord) in corners {
+            for animation in animations {
+                for face in Direction::ALL {
+                    let unpacked = UnpackedChunkVertex {
+                        position,
+                        tex_coord,
+                        layer: u16::MAX as u32,
+                        animation,
+                        face,
+                        light: MAX_LIGHT,
+                        tint: Tint::Grass,
+                    };
+
+                    assert_eq!(ChunkVertex::from(unpacked.clone()).unpack(), unpacked);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_vertices_stay_packed() {
+        assert_eq!(std::mem::size_of::<ChunkVertex>(), 8);
+    }
+
+    #[test]
+    fn set_light_rejects_positions_outside_the_chunk() {
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+
+        for position in [Vector3::new(16, 0, 0), Vector3::new(-1, 0, 0), Vector3::new(0, MAX_Y + 1, 0), Vector3::new(0, MIN_Y - 1, 0)] {
+            assert_eq!(chunk.set_light(position, 4), Err(ChunkBoundsError { position }));
+            assert_eq!(chunk.get_light(position), None);
+        }
+
+        assert_eq!(chunk.set_light(Vector3::new(15, MAX_Y, 15), 255), Ok(()));
+        assert_eq!(chunk.get_light(Vector3::new(15, MAX_Y, 15)), Some(MAX_LIGHT));
+    }
+
+    #[test]
+    fn chunk_uniform_matches_the_std140_layout() {
+        let model = Matrix4::from_translation(Vector3::new(16.0, -128.0, 32.0));
+        let uniform = ChunkUniform::new(model, Vector4::new(1.0, 0.4, 0.4, 1.0), 0.5);
+
+        let mut buffer = encase::UniformBuffer::new(Vec::new());
+        buffer.write(&uniform).unwrap();
+        let bytes = buffer.into_inner();
+        let floats = bytes.chunks_exact(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect::<Vec<_>>();
+
+        // The matrix takes the first 64 bytes column by column, the translation in the last one
+        let model: &[f32; 16] = model.as_ref();
+        assert_eq!(&floats[..16], model);
+        assert_eq!(&floats[16..20], &[1.0, 0.4, 0.4, 1.0]);
+        assert_eq!(floats[20], 0.5);
+        // The struct is padded to its 16 byte alignment
+        assert_eq!(ChunkUniform::min_size().get(), 96);
+        assert_eq!(bytes.len(), 96);
+    }
+
+    #[test]
+    fn a_filled_cube_only_keeps_its_outer_faces() {
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+
+        let changed = chunk.fill_region(Vector3::new(2, 0, 2), Vector3::new(5, 3, 5), Block::new_stone());
+        assert_eq!(changed, 64);
+        assert_eq!(face_count(&chunk, None, None), 6 * 16);
+
+        // Filling it again changes nothing
+        assert_eq!(chunk.fill_region(Vector3::new(2, 0, 2), Vector3::new(5, 3, 5), Block::new_stone()), 0);
+    }
+
+    #[test]
+    fn a_fill_across_a_border_is_clamped_and_culled_on_both_sides() {
+        let mut left = Chunk::new(Vector2::new(0, 0));
+        let mut right = Chunk::new(Vector2::new(1, 0));
+
+        // x 14 to 17 in world space, which is 14 and 15 of the left chunk and 0 and 1 of the right
+        assert_eq!(left.fill_region(Vector3::new(14, 0, 0), Vector3::new(17, 3, 3), Block::new_stone()), 32);
+        assert_eq!(right.fill_region(Vector3::new(-2, 0, 0), Vector3::new(1, 3, 3), Block::new_stone()), 32);
+
+        let faces = face_count(&left, None, Some(&right)) + face_count(&right, Some(&left), None);
+        assert_eq!(faces, 6 * 16);
+    }
+
+    #[test]
+    fn a_rebuilt_mesh_has_the_hand_counted_faces() {
+        // An L of three blocks shares two pairs of faces, so 3 * 6 - 2 * 2 faces are left
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+        chunk.set_block(Vector3::new(4, 0, 4), Block::new_stone()).unwrap();
+        chunk.set_block(Vector3::new(5, 0, 4), Block::new_stone()).unwrap();
+        chunk.set_block(Vector3::new(4, 1, 4), Block::new_stone()).unwrap();
+        assert_eq!(face_count(&chunk, None, None), 14);
+
+        // With solid neighbors all around, only the top and bottom of a half full chunk are left
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+        chunk.fill_region(Vector3::new(0, MIN_Y, 0), Vector3::new(15, -1, 15), Block::new_stone());
+        let mesh = ChunkMeshData::from_chunk(&chunk, [Some(&chunk); 4], &TextureTable::default());
+        assert_eq!(mesh.face_count(), 2 * CHUNK_WIDTH * CHUNK_DEPTH);
+    }
+
+    #[test]
+    fn iter_blocks_matches_get_block() {
+        let chunk = Chunk::generate(Vector2::new(0, 0), |position| match (position.x + position.y + position.z) % 7 {
+            0 => Block::new_stone(),
+            3 => Block::new_dirt(),
+            _ => Block::new_air(),
+        });
+
+        let mut count = 0;
+        for (position, block) in chunk.iter_blocks() {
+            assert_ne!(*block, Block::new_air());
+            assert_eq!(chunk.get_block(position), Some(block));
+            count += 1;
+        }
+
+        let solid = chunk.iter_all_blocks().filter(|(_, block)| **block != Block::new_air()).count();
+        assert_eq!(count, solid);
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn local_y_covers_min_y_to_max_y() {
+        assert_eq!(Chunk::local_y(MIN_Y), 0);
+        assert_eq!(Chunk::local_y(MAX_Y), CHUNK_HEIGHT - 1);
+        assert_eq!(Chunk::world_y(0), MIN_Y);
+        assert_eq!(Chunk::world_y(CHUNK_HEIGHT - 1), MAX_Y);
+
+        // Just outside the range lands outside the arrays instead of wrapping into them
+        assert!(Chunk::local_y(MIN_Y - 1) >= CHUNK_HEIGHT);
+        assert!(Chunk::local_y(MAX_Y + 1) >= CHUNK_HEIGHT);
+
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+        for y in [MIN_Y, MAX_Y] {
+            chunk.set_block(Vector3::new(0, y, 0), Block::new_stone()).unwrap();
+            assert_eq!(chunk.get_block(Vector3::new(0, y, 0)), Some(&Block::new_stone()));
+        }
+        for y in [MIN_Y - 1, MAX_Y + 1] {
+            assert!(chunk.set_block(Vector3::new(0, y, 0), Block::new_stone()).is_err());
+            assert_eq!(chunk.get_block(Vector3::new(0, y, 0)), None);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn compacting_an_emptied_mesh_shrinks_its_buffers() {
+        let renderer = crate::renderer::tests::headless();
+        let (device, queue) = (&renderer.device, &renderer.queue);
+        let textures = TextureTable::default();
+
+        let full = Chunk::generate(Vector2::new(0, 0), |position| {
+            if position.y < 4 { Block::new_stone() } else { Block::new_air() }
+        });
+        let mut mesh = ChunkMesh::new(0, device);
+        mesh.upload(ChunkMeshData::from_chunk(&full, [None; 4], &textures), device, queue);
+        let full_bytes = mesh.stats().gpu_bytes;
+        assert!(full_bytes > 0);
+
+        // Emptying the mesh keeps the old buffers around
+        mesh.upload(ChunkMeshData::from_chunk(&Chunk::new(Vector2::new(0, 0)), [None; 4], &textures), device, queue);
+        assert_eq!(mesh.num_elements(), 0);
+        assert_eq!(mesh.stats().gpu_bytes, full_bytes);
+
+        assert!(mesh.compact(device) >= full_bytes);
+        assert_eq!(mesh.stats().gpu_bytes, 0);
+        assert_eq!(mesh.reclaimable_bytes(), 0);
+    }
+
+    #[test]
+    fn compacting_mesh_data_after_removing_every_face_leaves_nothing() {
+        let textures = TextureTable::default();
+        let mut data = ChunkMeshData::new();
+        for x in 0..8 {
+            for face in Direction::ALL.iter() {
+                data.add_face(Vector3::new(x, 0, 0), face, &Block::new_stone(), MAX_LIGHT, &textures);
+            }
+        }
+        assert_eq!(data.index_count(), 8 * 6 * 6);
+
+        for x in 0..8 {
+            for face in Direction::ALL.iter() {
+                data.remove_face(Vector3::new(x, 0, 0), face);
+            }
+        }
+        assert_eq!(data.index_count(), 0);
+        assert!(data.reclaimable_bytes() > 0);
+
+        assert!(data.compact() > 0);
+        assert_eq!(data.face_count(), 0);
+        assert_eq!(data.index_count(), 0);
+        assert_eq!(data.reclaimable_bytes(), 0);
+    }
+
+    #[test]
+    fn iterating_a_chunk_with_two_blocks() {
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+        let (stone, dirt) = (Vector3::new(3, MIN_Y, 9), Vector3::new(15, MAX_Y, 0));
+        chunk.set_block(stone, Block::new_stone()).unwrap();
+        chunk.set_block(dirt, Block::new_dirt()).unwrap();
+
+        let mut blocks = chunk.iter_blocks().map(|(position, block)| (position, *block)).collect::<Vec<_>>();
+        blocks.sort_by_key(|(position, _)| (position.x, position.y, position.z));
+        assert_eq!(blocks, vec![(stone, Block::new_stone()), (dirt, Block::new_dirt())]);
+
+        // Air included, every position comes up once, from the bottom corner to the top one
+        let all = chunk.iter_all_blocks().map(|(position, _)| position).collect::<Vec<_>>();
+        assert_eq!(all.len(), CHUNK_WIDTH * CHUNK_HEIGHT * CHUNK_DEPTH);
+        assert_eq!(all.first(), Some(&Vector3::new(0, MIN_Y, 0)));
+        assert_eq!(all.last(), Some(&Vector3::new(15, MAX_Y, 15)));
+
+        let changed = chunk.update_blocks(|_, block| (*block == Block::new_dirt()).then(Block::new_sand));
+        assert_eq!(changed, 1);
+        assert_eq!(chunk.get_block(dirt), Some(&Block::new_sand()));
+        assert_eq!(chunk.get_block(stone), Some(&Block::new_stone()));
+    }
+}
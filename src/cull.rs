@@ -0,0 +1,338 @@
+use std::sync::mpsc;
+
+use anyhow::Context;
+use bytemuck::{Pod, Zeroable};
+use cgmath::Vector3;
+
+use crate::camera::Frustum;
+use crate::chunk::ChunkMesh;
+use crate::renderer::Draw;
+
+/// A chunk's bounding box and how many indices it draws, as the cull shader reads it
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct ChunkBounds {
+    min: [f32; 4],
+    max: [f32; 4],
+    index_count: u32,
+    _padding: [u32; 3],
+}
+
+impl ChunkBounds {
+    /// The box from `Chunk::bounds` and the mesh's `num_elements`
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>, index_count: u32) -> Self {
+        Self {
+            min: min.extend(0.0).into(),
+            max: max.extend(0.0).into(),
+            index_count,
+            _padding: [0; 3],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct FrustumUniform {
+    planes: [[f32; 4]; 6],
+    chunk_count: u32,
+    _padding: [u32; 3],
+}
+
+/// The arguments of a `draw_indexed_indirect`, which the cull shader writes one of per chunk
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+pub struct DrawIndexedIndirect {
+    pub index_count: u32,
+    /// 1 if the chunk is in the frustum, 0 if it was culled
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+
+/// Frustum culls chunks in a compute shader instead of on the CPU. Every chunk's bounds are
+/// uploaded to a storage buffer and the shader writes a `DrawIndexedIndirect` for each of them,
+/// with an instance count of 0 for the ones outside the frustum. Chunks are then drawn with
+/// `draw_indexed_indirect`, so culled ones cost a draw call but no vertices.
+///
+/// Every chunk has its own vertex and index buffers, so the draws can't be batched into one
+/// `multi_draw_indexed_indirect` until the meshes share a buffer.
+pub struct GpuCuller {
+    pipeline: wgpu::ComputePipeline,
+    layout: wgpu::BindGroupLayout,
+    frustum_buffer: wgpu::Buffer,
+    bounds_buffer: wgpu::Buffer,
+    /// Where the shader writes the draws, read by `draw_indexed_indirect`
+    draw_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    /// How many chunks the buffers have room for
+    capacity: u32,
+}
+
+impl GpuCuller {
+    /// What the adapter has to support to run the cull at all. `State` falls back to culling on
+    /// the CPU without them, like on WebGL.
+    pub const REQUIRED_DOWNLEVEL_FLAGS: wgpu::DownlevelFlags = wgpu::DownlevelFlags::COMPUTE_SHADERS
+        .union(wgpu::DownlevelFlags::INDIRECT_EXECUTION);
+    /// The shader's `@workgroup_size`
+    const WORKGROUP_SIZE: u32 = 64;
+
+    pub fn is_supported(downlevel_flags: wgpu::DownlevelFlags) -> bool {
+        downlevel_flags.contains(GpuCuller::REQUIRED_DOWNLEVEL_FLAGS)
+    }
+
+    /// A culler with room for `capacity` chunks to start with, at least 1. The buffers double in
+    /// size whenever there are more chunks than that.
+    pub fn new(capacity: u32, device: &wgpu::Device) -> Self {
+        let capacity = capacity.max(1);
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<FrustumUniform>() as u64),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("cull bind group layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+            label: Some("cull pipeline layout"),
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            source: wgpu::ShaderSource::Wgsl(include_str!("cull.wgsl").into()),
+            label: Some("Cull Shader"),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("cull pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+        });
+
+        let frustum_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Frustum Buffer"),
+            size: std::mem::size_of::<FrustumUniform>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let (bounds_buffer, draw_buffer) = GpuCuller::create_buffers(capacity, device);
+        let bind_group = GpuCuller::create_bind_group(&layout, &frustum_buffer, &bounds_buffer, &draw_buffer, device);
+
+        Self {
+            pipeline,
+            layout,
+            frustum_buffer,
+            bounds_buffer,
+            draw_buffer,
+            bind_group,
+            capacity,
+        }
+    }
+
+    fn create_buffers(capacity: u32, device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
+        let bounds_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Bounds Buffer"),
+            size: capacity as wgpu::BufferAddress * std::mem::size_of::<ChunkBounds>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let draw_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Draw Buffer"),
+            size: capacity as wgpu::BufferAddress * GpuCuller::draw_offset(1),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        (bounds_buffer, draw_buffer)
+    }
+
+    fn create_bind_group(
+        layout: &wgpu::BindGroupLayout,
+        frustum_buffer: &wgpu::Buffer,
+        bounds_buffer: &wgpu::Buffer,
+        draw_buffer: &wgpu::Buffer,
+        device: &wgpu::Device,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: frustum_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: bounds_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: draw_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("cull bind group"),
+        })
+    }
+
+    fn grow(&mut self, capacity: u32, device: &wgpu::Device) {
+        log::debug!("growing the cull buffers from {} to {} chunks", self.capacity, capacity);
+
+        let (bounds_buffer, draw_buffer) = GpuCuller::create_buffers(capacity, device);
+        self.bind_group = GpuCuller::create_bind_group(&self.layout, &self.frustum_buffer, &bounds_buffer, &draw_buffer, device);
+        self.bounds_buffer = bounds_buffer;
+        self.draw_buffer = draw_buffer;
+        self.capacity = capacity;
+    }
+
+    /// Uploads the frustum and `chunks` and runs the cull. The draw for `chunks[i]` ends up in
+    /// `draw_buffer` at `draw_offset(i)`, ready for the next submission.
+    pub fn cull(&mut self, frustum: &Frustum, chunks: &[ChunkBounds], device: &wgpu::Device, queue: &wgpu::Queue) {
+        if chunks.is_empty() {
+            return;
+        }
+
+        let count = chunks.len() as u32;
+        if count > self.capacity {
+            self.grow(count.next_power_of_two(), device);
+        }
+
+        let uniform = FrustumUniform {
+            planes: frustum.planes().map(Into::into),
+            chunk_count: count,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.frustum_buffer, 0, bytemuck::cast_slice(&[uniform]));
+        queue.write_buffer(&self.bounds_buffer, 0, bytemuck::cast_slice(chunks));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Cull Encoder"),
+        });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Cull Pass"),
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.dispatch_workgroups(count.div_ceil(GpuCuller::WORKGROUP_SIZE), 1, 1);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    pub fn draw_buffer(&self) -> &wgpu::Buffer {
+        &self.draw_buffer
+    }
+
+    /// Where the draw of the chunk at `index` in the last `cull` starts in `draw_buffer`
+    pub fn draw_offset(index: usize) -> wgpu::BufferAddress {
+        (index * std::mem::size_of::<DrawIndexedIndirect>()) as wgpu::BufferAddress
+    }
+
+    /// Copies the first `count` draws back from the GPU, blocking until they're there. Only meant
+    /// for debugging and tests, since it stalls the pipeline.
+    pub fn read_draws(&self, count: usize, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<Vec<DrawIndexedIndirect>> {
+        let count = count.min(self.capacity as usize);
+        let size = GpuCuller::draw_offset(count);
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Read Buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Cull Read Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.draw_buffer, 0, &buffer, 0, size);
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv()?.context("unable to map the cull read buffer")?;
+
+        let draws = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        buffer.unmap();
+
+        Ok(draws)
+    }
+}
+
+/// A chunk drawn with the arguments `GpuCuller` wrote for it
+pub struct CulledChunk<'a> {
+    pub mesh: &'a ChunkMesh,
+    pub draws: &'a wgpu::Buffer,
+    pub offset: wgpu::BufferAddress,
+}
+
+impl Draw for CulledChunk<'_> {
+    fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup, uniforms: &'a wgpu::BindGroup) {
+        self.mesh.draw_indirect(render_pass, camera_bind_group, uniforms, self.draws, self.offset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Deg;
+
+    use super::*;
+    use crate::camera::{Camera, Projection};
+    use crate::renderer;
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn chunks_behind_the_camera_are_drawn_with_no_instances() {
+        let renderer = renderer::tests::headless();
+        assert!(GpuCuller::is_supported(renderer.downlevel_flags), "the adapter can't run the cull shader");
+
+        // Looking down +x, so the second chunk is behind the camera
+        let camera = Camera::new((0.0, 0.0, 0.0), Deg(0.0), Deg(0.0));
+        let projection = Projection::new(100, 100, Deg(90.0), 0.1, 100.0);
+        let frustum = Frustum::from_matrix(projection.calc_matrix() * camera.calc_matrix());
+        let chunks = [
+            ChunkBounds::new(Vector3::new(8.0, -8.0, -8.0), Vector3::new(24.0, 8.0, 8.0), 36),
+            ChunkBounds::new(Vector3::new(-24.0, -8.0, -8.0), Vector3::new(-8.0, 8.0, 8.0), 42),
+        ];
+
+        let mut culler = GpuCuller::new(1, &renderer.device);
+        culler.cull(&frustum, &chunks, &renderer.device, &renderer.queue);
+        let draws = culler.read_draws(chunks.len(), &renderer.device, &renderer.queue).unwrap();
+
+        assert_eq!(draws.len(), 2);
+        assert_eq!((draws[0].index_count, draws[0].instance_count), (36, 1));
+        assert_eq!(draws[1].instance_count, 0);
+    }
+}
@@ -0,0 +1,53 @@
+struct Frustum {
+    // Left, right, bottom, top, near and far, as (normal, distance) with the normals pointing inwards
+    planes: array<vec4<f32>, 6>,
+    chunk_count: u32,
+};
+@group(0) @binding(0)
+var<uniform> frustum: Frustum;
+
+struct ChunkBounds {
+    min: vec4<f32>,
+    max: vec4<f32>,
+    index_count: u32,
+};
+@group(0) @binding(1)
+var<storage, read> chunks: array<ChunkBounds>;
+
+// Laid out like the arguments of `draw_indexed_indirect`
+struct DrawIndexedIndirect {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+};
+@group(0) @binding(2)
+var<storage, read_write> draws: array<DrawIndexedIndirect>;
+
+// `GpuCuller::WORKGROUP_SIZE`
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= frustum.chunk_count) {
+        return;
+    }
+    let chunk = chunks[index];
+
+    // Same test as `Frustum::intersects_aabb`
+    var visible = true;
+    for (var i = 0u; i < 6u; i = i + 1u) {
+        let plane = frustum.planes[i];
+        // The corner of the box furthest along the plane's normal
+        let corner = select(chunk.min.xyz, chunk.max.xyz, plane.xyz >= vec3<f32>(0.0));
+        if (dot(plane.xyz, corner) + plane.w < 0.0) {
+            visible = false;
+        }
+    }
+
+    draws[index].index_count = chunk.index_count;
+    draws[index].instance_count = select(0u, 1u, visible);
+    draws[index].first_index = 0u;
+    draws[index].base_vertex = 0;
+    draws[index].first_instance = 0u;
+}
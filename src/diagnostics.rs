@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The last few durations recorded for a span
+#[derive(Debug, Clone, Default)]
+struct Span {
+    samples: VecDeque<Duration>,
+    /// The sum of `samples`, so the average doesn't have to add them up every time
+    total: Duration,
+}
+
+/// Rolling averages of how long named parts of the frame take, like "update" or "render". Spans
+/// show up in the order they were first recorded.
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    spans: Vec<(&'static str, Span)>,
+    /// How many of the latest samples each average is over
+    window: usize,
+}
+
+impl Diagnostics {
+    /// Averages over about a second at 60 frames per second
+    pub const DEFAULT_WINDOW: usize = 60;
+
+    /// Averages each span over its last `window` samples, at least 1
+    pub fn new(window: usize) -> Self {
+        Self {
+            spans: Vec::new(),
+            window: window.max(1),
+        }
+    }
+
+    /// Adds a sample to the span called `name`, dropping its oldest one if the window is full
+    pub fn record(&mut self, name: &'static str, duration: Duration) {
+        let index = match self.spans.iter().position(|(span_name, _)| *span_name == name) {
+            Some(index) => index,
+            None => {
+                self.spans.push((name, Span::default()));
+                self.spans.len() - 1
+            }
+        };
+        let span = &mut self.spans[index].1;
+
+        if span.samples.len() == self.window {
+            let oldest = span.samples.pop_front().unwrap();
+            span.total -= oldest;
+        }
+        span.samples.push_back(duration);
+        span.total += duration;
+    }
+
+    fn span(&self, name: &str) -> Option<&Span> {
+        self.spans.iter().find(|(span_name, _)| *span_name == name).map(|(_, span)| span)
+    }
+
+    /// The average of the span's samples in the window, `None` if nothing was recorded for it
+    pub fn average(&self, name: &str) -> Option<Duration> {
+        self.span(name).map(|span| span.total / span.samples.len() as u32)
+    }
+
+    /// How many samples of the span are in the window
+    pub fn sample_count(&self, name: &str) -> usize {
+        self.span(name).map_or(0, |span| span.samples.len())
+    }
+
+    /// Every span's name and average
+    pub fn averages(&self) -> impl Iterator<Item = (&'static str, Duration)> + '_ {
+        self.spans.iter().map(|(name, span)| (*name, span.total / span.samples.len() as u32))
+    }
+}
+
+impl Default for Diagnostics {
+    fn default() -> Self {
+        Diagnostics::new(Diagnostics::DEFAULT_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_span_averages_its_samples() {
+        let mut diagnostics = Diagnostics::new(4);
+        for ms in [2, 4, 6] {
+            diagnostics.record("render", Duration::from_millis(ms));
+        }
+
+        assert_eq!(diagnostics.sample_count("render"), 3);
+        assert_eq!(diagnostics.average("render"), Some(Duration::from_millis(4)));
+        assert_eq!(diagnostics.sample_count("update"), 0);
+        assert_eq!(diagnostics.average("update"), None);
+    }
+
+    #[test]
+    fn old_samples_roll_out_of_the_window() {
+        let mut diagnostics = Diagnostics::new(2);
+        for ms in [100, 2, 4] {
+            diagnostics.record("update", Duration::from_millis(ms));
+        }
+        diagnostics.record("render", Duration::from_millis(1));
+
+        assert_eq!(diagnostics.sample_count("update"), 2);
+        assert_eq!(diagnostics.average("update"), Some(Duration::from_millis(3)));
+        assert_eq!(
+            diagnostics.averages().collect::<Vec<_>>(),
+            vec![("update", Duration::from_millis(3)), ("render", Duration::from_millis(1))],
+        );
+    }
+}
@@ -1,8 +1,41 @@
+use std::iter;
+use std::time::Duration;
+
+use cgmath::Point3;
 use imgui::FontSource;
 use imgui_wgpu::RendererConfig;
 
+use crate::block::{Block, SoundCategory};
 use crate::get_bytes;
 
+/// The numbers shown in the debug overlay
+#[derive(Debug, Clone)]
+pub struct DebugStats {
+	pub fps: usize,
+	/// The average frame time over the last second, and the time the slowest 1% of frames took
+	pub frame_time: Option<Duration>,
+	pub low_frame_time: Option<Duration>,
+	pub loaded_chunks: usize,
+	/// How many chunks were requested and aren't loaded yet
+	pub queued_chunks: usize,
+	/// How many chunks have mesh changes that weren't uploaded yet
+	pub dirty_chunks: usize,
+	/// How many chunks were drawn and frustum culled last frame. `None` when they're culled on the
+	/// GPU, which doesn't report back, so every chunk counts as drawn.
+	pub chunks_drawn: usize,
+	pub chunks_culled: Option<usize>,
+	pub camera_position: Point3<f32>,
+	pub paused: bool,
+	/// The block the camera is looking at, if it's close enough to reach
+	pub targeted_block: Option<Block>,
+	/// The block the hotbar has selected for placing
+	pub selected_block: Block,
+	/// How many blocks have dropped from the blocks broken so far
+	pub collected_blocks: usize,
+	/// The average time each profiled part of the frame takes, see `Diagnostics`
+	pub spans: Vec<(&'static str, Duration)>,
+}
+
 pub struct Gui {
 	pub imgui: imgui::Context,
 	pub platform: imgui_winit_support::WinitPlatform,
@@ -10,6 +43,8 @@ pub struct Gui {
 
 	pub last_cursor: Option<imgui::MouseCursor>,
 	pub ui_focus: bool,
+	/// Whether the game has grabbed the cursor for mouse look
+	pub cursor_grabbed: bool,
 }
 
 impl Gui {
@@ -53,8 +88,8 @@ impl Gui {
 
 		let gui_renderer = imgui_wgpu::Renderer::new(
 			&mut imgui,
-			&device,
-			&queue,
+			device,
+			queue,
 			renderer_config,
 		);
 
@@ -65,6 +100,128 @@ impl Gui {
 
 			last_cursor: None,
 			ui_focus: false,
+			cursor_grabbed: false,
 		}
 	}
-}
\ No newline at end of file
+
+	/// Builds the debug overlay for this frame and draws it on top of `view`
+	pub fn draw_debug(
+		&mut self,
+		window: &winit::window::Window,
+		stats: &DebugStats,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		view: &wgpu::TextureView,
+	) {
+		let bold_font = self.imgui.fonts().fonts()[1];
+
+		self.render(window, device, queue, view, |ui| {
+			imgui::Window::new("Debug")
+			.position([10.0, 10.0], imgui::Condition::FirstUseEver)
+				.always_auto_resize(true)
+				.bg_alpha(0.6)
+				.build(ui, || {
+					let font = ui.push_font(bold_font);
+					ui.text(format!("FPS: {}", stats.fps));
+					if stats.paused {
+						ui.same_line();
+						ui.text("(paused)");
+					}
+					font.pop();
+					if let (Some(average), Some(low)) = (stats.frame_time, stats.low_frame_time) {
+						ui.text(format!(
+							"Frame time: {:.2} ms, 1% low {:.2} ms",
+							average.as_secs_f64() * 1000.0,
+							low.as_secs_f64() * 1000.0,
+						));
+					}
+
+					ui.separator();
+					ui.text(format!("Chunks loaded: {}", stats.loaded_chunks));
+					ui.text(format!("Chunks queued: {}", stats.queued_chunks));
+					ui.text(format!("Chunks dirty: {}", stats.dirty_chunks));
+					ui.text(format!("Chunks drawn: {}", stats.chunks_drawn));
+					match stats.chunks_culled {
+						Some(culled) => ui.text(format!("Chunks culled: {}", culled)),
+						None => ui.text("Chunks culled: on the GPU"),
+					}
+					ui.separator();
+					for (name, average) in &stats.spans {
+						ui.text(format!("{}: {:.2} ms", name, average.as_secs_f64() * 1000.0));
+					}
+					ui.separator();
+					ui.text(format!(
+						"Camera: {:.1}, {:.1}, {:.1}",
+						stats.camera_position.x, stats.camera_position.y, stats.camera_position.z,
+					));
+					ui.text(format!("Holding: {}", stats.selected_block.name()));
+					ui.text(format!("Collected: {} blocks", stats.collected_blocks));
+					if let Some(block) = &stats.targeted_block {
+						let sound = match block.sound_category() {
+							SoundCategory::Grass => "grass",
+							SoundCategory::Stone => "stone",
+							SoundCategory::Wood => "wood",
+							SoundCategory::Sand => "sand",
+							SoundCategory::Glass => "glass",
+							SoundCategory::None => "none",
+						};
+						ui.text(format!("Looking at: {} (sounds like {})", block.name(), sound));
+					}
+				});
+		});
+	}
+
+	/// Runs a whole imgui frame: `build` creates the windows, which are then drawn on top of
+	/// whatever is already in `view`. Also keeps the OS cursor and `ui_focus` in sync with imgui.
+	pub fn render(
+		&mut self,
+		window: &winit::window::Window,
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		view: &wgpu::TextureView,
+		build: impl FnOnce(&imgui::Ui),
+	) {
+		if let Err(e) = self.platform.prepare_frame(self.imgui.io_mut(), window) {
+			eprintln!("Unable to prepare gui frame: {:?}", e);
+			return;
+		}
+
+		let ui = self.imgui.frame();
+		build(&ui);
+
+		// Only touch the OS cursor when imgui wants a different one
+		if self.last_cursor != ui.mouse_cursor() {
+			self.last_cursor = ui.mouse_cursor();
+			self.platform.prepare_render(&ui, window);
+		}
+
+		let draw_data = ui.render();
+
+		let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+			label: Some("Gui Encoder"),
+		});
+
+		{
+			let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some("Gui Render Pass"),
+				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+					view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Load,
+						store: true,
+					},
+				})],
+				depth_stencil_attachment: None,
+			});
+
+			if let Err(e) = self.gui_renderer.render(draw_data, queue, device, &mut render_pass) {
+				eprintln!("Unable to render gui: {:?}", e);
+			}
+		}
+
+		queue.submit(iter::once(encoder.finish()));
+
+		self.ui_focus = self.imgui.io().want_capture_mouse;
+	}
+}
@@ -0,0 +1,57 @@
+use winit::dpi::PhysicalPosition;
+use winit::event::MouseScrollDelta;
+
+use crate::block::Block;
+
+/// The blocks the player can place, and which one is selected
+#[derive(Debug)]
+pub struct Hotbar {
+    slots: Vec<Block>,
+    selected: usize,
+    /// Pixel scrolling left over from the last scroll that wasn't enough for a whole slot
+    scroll: f32,
+}
+
+impl Hotbar {
+    /// How many pixels of smooth scrolling move the selection by a slot
+    const PIXELS_PER_SLOT: f32 = 50.0;
+
+    /// A hotbar holding the given blocks, with the first one selected. Panics if there are none.
+    pub fn new(slots: Vec<Block>) -> Self {
+        assert!(!slots.is_empty(), "a hotbar needs at least one slot");
+
+        Self {
+            slots,
+            selected: 0,
+            scroll: 0.0,
+        }
+    }
+
+    pub fn selected(&self) -> Block {
+        self.slots[self.selected]
+    }
+
+    /// Moves the selection by `slots`, wrapping around at either end
+    pub fn cycle(&mut self, slots: i32) {
+        self.selected = (self.selected as i32 + slots).rem_euclid(self.slots.len() as i32) as usize;
+    }
+
+    /// Scrolling down moves to the next slot, up to the previous one
+    pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
+        self.scroll -= match delta {
+            MouseScrollDelta::LineDelta(_, lines) => *lines * Hotbar::PIXELS_PER_SLOT,
+            MouseScrollDelta::PixelDelta(PhysicalPosition { y, .. }) => *y as f32,
+        };
+
+        let slots = (self.scroll / Hotbar::PIXELS_PER_SLOT).trunc();
+        self.scroll -= slots * Hotbar::PIXELS_PER_SLOT;
+        self.cycle(slots as i32);
+    }
+}
+
+impl Default for Hotbar {
+    /// Every built-in block that can be placed
+    fn default() -> Self {
+        Hotbar::new(Block::all().filter(|block| *block != Block::new_air()).collect())
+    }
+}
@@ -0,0 +1,222 @@
+use hashbrown::{HashMap, HashSet};
+use winit::event::{ElementState, KeyboardInput, ScanCode, VirtualKeyCode};
+
+/// A logical action the game reacts to, independent of the key that triggers it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    ToggleCursorGrab,
+    ToggleWireframe,
+    ToggleWalk,
+    Screenshot,
+    IncreaseRenderDistance,
+    DecreaseRenderDistance,
+    TogglePause,
+    SaveWorld,
+    LoadWorld,
+    IncreaseFov,
+    DecreaseFov,
+    /// Narrows the field of view while held
+    Zoom,
+    /// Switches between the first person camera and orbiting what it's looking at
+    ToggleOrbit,
+}
+
+/// A physical key an action can be bound to. Scan codes refer to the key's position on the
+/// keyboard, so they stay put across keyboard layouts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyBinding {
+    Key(VirtualKeyCode),
+    ScanCode(ScanCode),
+}
+
+/// Maps actions to the keys bound to them and tracks which of those keys are held
+#[derive(Debug)]
+pub struct ActionMap {
+    bindings: HashMap<Action, Vec<KeyBinding>>,
+    pressed: HashSet<KeyBinding>,
+    just_pressed: HashSet<KeyBinding>,
+}
+
+impl ActionMap {
+    /// Creates an action map with no bindings
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::new(),
+            pressed: HashSet::new(),
+            just_pressed: HashSet::new(),
+        }
+    }
+
+    /// Binds an additional key to the action
+    pub fn bind<K: Into<KeyBinding>>(&mut self, action: Action, key: K) {
+        let key = key.into();
+        let keys = self.bindings.entry(action).or_default();
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+
+    /// Removes every key bound to the action
+    pub fn unbind(&mut self, action: Action) {
+        self.bindings.remove(&action);
+    }
+
+    /// Replaces every key bound to the action with a single key
+    pub fn rebind<K: Into<KeyBinding>>(&mut self, action: Action, key: K) {
+        self.unbind(action);
+        self.bind(action, key);
+    }
+
+    pub fn bindings(&self, action: Action) -> &[KeyBinding] {
+        self.bindings.get(&action).map_or(&[], |keys| keys.as_slice())
+    }
+
+    /// Updates the held keys from a keyboard event. Returns true if the key is bound to an action.
+    pub fn process_keyboard(&mut self, input: &KeyboardInput) -> bool {
+        let mut keys = vec![KeyBinding::ScanCode(input.scancode)];
+        if let Some(key) = input.virtual_keycode {
+            keys.push(KeyBinding::Key(key));
+        }
+
+        for key in keys.iter() {
+            match input.state {
+                ElementState::Pressed => {
+                    if self.pressed.insert(*key) {
+                        self.just_pressed.insert(*key);
+                    }
+                }
+                ElementState::Released => {
+                    self.pressed.remove(key);
+                }
+            }
+        }
+
+        self.bindings.values().flatten().any(|bound| keys.contains(bound))
+    }
+
+    /// Whether any key bound to the action is held
+    pub fn pressed(&self, action: Action) -> bool {
+        self.bindings(action).iter().any(|key| self.pressed.contains(key))
+    }
+
+    /// Whether any key bound to the action was pressed since the last `clear_just_pressed`
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.bindings(action).iter().any(|key| self.just_pressed.contains(key))
+    }
+
+    /// Should be called once at the end of every frame
+    pub fn clear_just_pressed(&mut self) {
+        self.just_pressed.clear();
+    }
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        let mut action_map = Self::new();
+
+        action_map.bind(Action::MoveForward, VirtualKeyCode::W);
+        action_map.bind(Action::MoveForward, VirtualKeyCode::Up);
+        action_map.bind(Action::MoveBackward, VirtualKeyCode::S);
+        action_map.bind(Action::MoveBackward, VirtualKeyCode::Down);
+        action_map.bind(Action::MoveLeft, VirtualKeyCode::A);
+        action_map.bind(Action::MoveLeft, VirtualKeyCode::Left);
+        action_map.bind(Action::MoveRight, VirtualKeyCode::D);
+        action_map.bind(Action::MoveRight, VirtualKeyCode::Right);
+        action_map.bind(Action::MoveUp, VirtualKeyCode::Space);
+        action_map.bind(Action::MoveDown, VirtualKeyCode::LShift);
+        action_map.bind(Action::ToggleCursorGrab, VirtualKeyCode::Tab);
+        action_map.bind(Action::ToggleWireframe, VirtualKeyCode::F3);
+        action_map.bind(Action::ToggleWalk, VirtualKeyCode::F);
+        action_map.bind(Action::Screenshot, VirtualKeyCode::F2);
+        action_map.bind(Action::IncreaseRenderDistance, VirtualKeyCode::Equals);
+        action_map.bind(Action::DecreaseRenderDistance, VirtualKeyCode::Minus);
+        action_map.bind(Action::TogglePause, VirtualKeyCode::P);
+        action_map.bind(Action::SaveWorld, VirtualKeyCode::F5);
+        action_map.bind(Action::LoadWorld, VirtualKeyCode::F9);
+        action_map.bind(Action::IncreaseFov, VirtualKeyCode::RBracket);
+        action_map.bind(Action::DecreaseFov, VirtualKeyCode::LBracket);
+        action_map.bind(Action::Zoom, VirtualKeyCode::C);
+        action_map.bind(Action::ToggleOrbit, VirtualKeyCode::O);
+
+        action_map
+    }
+}
+
+impl From<VirtualKeyCode> for KeyBinding {
+    fn from(key: VirtualKeyCode) -> Self {
+        KeyBinding::Key(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[allow(deprecated)]
+    fn key_event(scancode: ScanCode, key: Option<VirtualKeyCode>, state: ElementState) -> KeyboardInput {
+        KeyboardInput {
+            scancode,
+            state,
+            virtual_keycode: key,
+            modifiers: Default::default(),
+        }
+    }
+
+    #[test]
+    fn rebinding_replaces_every_key_of_the_action() {
+        let mut action_map = ActionMap::default();
+        assert_eq!(action_map.bindings(Action::MoveForward).len(), 2);
+
+        action_map.rebind(Action::MoveForward, VirtualKeyCode::I);
+        assert_eq!(action_map.bindings(Action::MoveForward), &[KeyBinding::Key(VirtualKeyCode::I)]);
+
+        action_map.process_keyboard(&key_event(17, Some(VirtualKeyCode::W), ElementState::Pressed));
+        assert!(!action_map.pressed(Action::MoveForward));
+
+        action_map.process_keyboard(&key_event(23, Some(VirtualKeyCode::I), ElementState::Pressed));
+        assert!(action_map.pressed(Action::MoveForward));
+    }
+
+    #[test]
+    fn scan_code_bindings_ignore_the_layout() {
+        let mut action_map = ActionMap::new();
+        action_map.bind(Action::MoveForward, KeyBinding::ScanCode(17));
+
+        // The same physical key reports a different virtual key on an AZERTY layout
+        assert!(action_map.process_keyboard(&key_event(17, Some(VirtualKeyCode::Z), ElementState::Pressed)));
+        assert!(action_map.pressed(Action::MoveForward));
+
+        action_map.process_keyboard(&key_event(17, Some(VirtualKeyCode::Z), ElementState::Released));
+        assert!(!action_map.pressed(Action::MoveForward));
+    }
+
+    #[test]
+    fn just_pressed_lasts_until_cleared() {
+        let mut action_map = ActionMap::default();
+        let press = key_event(25, Some(VirtualKeyCode::P), ElementState::Pressed);
+
+        action_map.process_keyboard(&press);
+        assert!(action_map.just_pressed(Action::TogglePause));
+
+        // Key repeat sends more presses without a release, which don't count again
+        action_map.clear_just_pressed();
+        action_map.process_keyboard(&press);
+        assert!(!action_map.just_pressed(Action::TogglePause));
+        assert!(action_map.pressed(Action::TogglePause));
+    }
+
+    #[test]
+    fn unbound_keys_are_reported() {
+        let mut action_map = ActionMap::default();
+        action_map.unbind(Action::TogglePause);
+
+        assert!(action_map.bindings(Action::TogglePause).is_empty());
+        assert!(!action_map.process_keyboard(&key_event(25, Some(VirtualKeyCode::P), ElementState::Pressed)));
+    }
+}
@@ -0,0 +1,57 @@
+/// Counts how many times a fixed period has passed as time goes by. Whatever is left over carries
+/// on to the next call, so work done every period keeps a steady rate at any frame rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    /// In seconds
+    period: f32,
+    /// Time since the last period ended
+    elapsed: f32,
+}
+
+impl Interval {
+    pub fn new(period: f32) -> Self {
+        Self {
+            period,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Adds `dt` seconds and returns how many whole periods ended since the last call
+    pub fn advance(&mut self, dt: f32) -> u32 {
+        self.elapsed += dt;
+
+        let mut periods = 0;
+        while self.elapsed >= self.period {
+            self.elapsed -= self.period;
+            periods += 1;
+        }
+
+        periods
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stepping_past_the_period_fires_once_each_time() {
+        let mut interval = Interval::new(300.0);
+
+        for _ in 0..5 {
+            assert_eq!(interval.advance(299.0), 0);
+            assert_eq!(interval.advance(2.0), 1);
+            // The extra second carries over
+            assert_eq!(interval.advance(298.0), 0);
+            assert_eq!(interval.advance(1.0), 1);
+        }
+    }
+
+    #[test]
+    fn a_long_frame_counts_every_period_it_covers() {
+        let mut interval = Interval::new(0.25);
+
+        assert_eq!(interval.advance(1.375), 5);
+        assert_eq!(interval.advance(0.125), 1);
+    }
+}
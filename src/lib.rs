@@ -1,11 +1,11 @@
 extern crate core;
 
 
-use std::mem;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use cgmath::{Vector2, Vector3};
-use wgpu::util::{align_to, DeviceExt};
+use cgmath::{Vector2, Vector3, Vector4};
+use wgpu::util::DeviceExt;
 use winit::{
     dpi::PhysicalSize,
     event::*,
@@ -13,57 +13,167 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
-use crate::block::Block;
-use crate::chunk::{CHUNK_DEPTH, CHUNK_WIDTH, ChunkUniform, Vertex};
-use crate::gui::Gui;
-use crate::renderer::Renderer;
-use crate::resources::get_bytes;
-use crate::world::World;
-
-mod block;
-mod camera;
-mod chunk;
-mod renderer;
-mod resources;
-mod texture;
-mod gui;
-mod world;
+use crate::block::{Axis, Block};
+use crate::chunk::{CHUNK_DEPTH, CHUNK_WIDTH, Chunk, ChunkUniform, Vertex};
+use crate::cull::{ChunkBounds, CulledChunk, GpuCuller};
+use crate::diagnostics::Diagnostics;
+use crate::gui::{DebugStats, Gui};
+use crate::hotbar::Hotbar;
+use crate::input::{Action, ActionMap};
+use crate::interval::Interval;
+use crate::loader::ChunkLoader;
+use crate::mining::MiningState;
+use crate::registry::BlockRegistry;
+use crate::renderer::{Draw, FogUniform, RenderSettings, Renderer, RequestedFeatures};
+use crate::resources::{get_bytes, get_resource};
+use crate::tags::BlockTags;
+use crate::uniforms::ChunkUniforms;
+use crate::terrain::WorldSeed;
+use crate::wireframe::{LineVertex, WireframeMesh, WireframeUniform};
+use crate::world::{BlockHit, World};
+
+pub mod block;
+pub mod camera;
+pub mod chunk;
+pub mod cull;
+pub mod diagnostics;
+pub mod renderer;
+pub mod resources;
+pub mod save;
+pub mod texture;
+pub mod gui;
+pub mod hotbar;
+pub mod input;
+pub mod interval;
+pub mod loader;
+pub mod mining;
+pub mod palette;
+pub mod registry;
+pub mod tags;
+pub mod terrain;
+pub mod uniforms;
+pub mod wireframe;
+pub mod world;
+
+pub use crate::terrain::GeneratorKind;
+
+/// How far away blocks can be targeted, in blocks
+const REACH: f32 = 8.0;
+/// Where screenshots are saved, relative to the resource folder
+const SCREENSHOTS_FOLDER: &str = "screenshots";
+/// The folder the world is saved to and loaded from, relative to the resource folder
+const SAVE_FOLDER: &str = "world";
+/// How many times a second blocks get random ticks
+const RANDOM_TICK_RATE: f32 = 20.0;
+/// How many random positions in each chunk get a tick every time
+const RANDOM_TICKS_PER_CHUNK: usize = 48;
+/// How often the world saves itself while the game is running, in seconds
+const AUTOSAVE_INTERVAL: f32 = 300.0;
+/// Seeds the terrain generator, the same seed always generates the same world
+const WORLD_SEED: WorldSeed = WorldSeed(0x5EED_1808);
+/// Terrain below this height is flooded
+const WATER_LEVEL: i32 = 4;
+/// How many chunks are generated at once in the background
+const MAX_CHUNKS_IN_FLIGHT: usize = 16;
+/// How many generated chunks are added to the world each frame at most, so streaming in a lot of
+/// them at once doesn't cause a hitch
+const MAX_CHUNK_UPLOADS_PER_FRAME: usize = 4;
+
+/// Whether the game is being simulated. Rendering and input keep going while it's paused, so the
+/// window stays responsive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AppState {
+    Running,
+    Paused,
+}
 
 struct State {
     renderer: Renderer,
     gui: Gui,
     camera: camera::Camera,
+    /// Set while orbiting what the camera was looking at, which is drawn from here instead.
+    /// `camera` stays where it was in the meantime.
+    orbit_camera: Option<camera::OrbitCamera>,
     projection: camera::Projection,
 
     camera_controller: camera::CameraController,
+    action_map: ActionMap,
     camera_uniform: renderer::CameraUniform,
     camera_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
 
-    // chunk_uniform_buffer: wgpu::Buffer,
-    chunk_uniform_bind_group: wgpu::BindGroup,
+    render_settings: RenderSettings,
+    camera_settings: camera::CameraSettings,
 
     render_pipeline: wgpu::RenderPipeline,
+    /// Draws chunks as wireframes to debug mesh topology, `None` if the device can't
+    wireframe_render_pipeline: Option<wgpu::RenderPipeline>,
+    show_wireframe: bool,
     world: World,
+    /// Generates chunks as they come into range
+    chunk_loader: ChunkLoader,
+    /// Which generator the chunk loader uses, and the seed it was given
+    generator: GeneratorKind,
+    world_seed: WorldSeed,
+
+    /// Outlines the block the camera is looking at, if any
+    highlight: WireframeMesh,
+    highlight_buffer: wgpu::Buffer,
+    highlight_bind_group: wgpu::BindGroup,
+    wireframe_pipeline: wgpu::RenderPipeline,
+    targeted_block: Option<BlockHit>,
+    mining: MiningState,
+    /// How many blocks breaking blocks has dropped. There's no inventory to put them in yet.
+    collected_blocks: usize,
+    hotbar: Hotbar,
+    /// Set by a right click, placing the selected block on the next update
+    place_requested: bool,
+    random_ticks: Interval,
+    autosave: Interval,
+    app_state: AppState,
+
     mouse_pressed: bool,
+
+    /// Frustum culls the chunks in a compute shader, `None` if the adapter can't, in which case
+    /// they're culled on the CPU
+    gpu_culler: Option<GpuCuller>,
+    /// How many chunks were drawn and frustum culled last frame, see `DebugStats`
+    chunks_drawn: usize,
+    chunks_culled: Option<usize>,
+    /// How long the update, render and chunk uploads took over the last few frames
+    diagnostics: Diagnostics,
 }
 
 impl State {
-    fn new(window: &Window) -> Self {
-        let renderer = Renderer::new(window);
+    fn new(window: &Window, generator: GeneratorKind) -> Self {
+        let mut renderer = Renderer::new(window, 4, RequestedFeatures::default())
+            .expect("Unable to create the renderer");
 
         let gui = Gui::new(window, &renderer.config, &renderer.device, &renderer.queue);
 
-        let camera = camera::Camera::new((0.0, 5.0, 10.0), cgmath::Deg(-90.0), cgmath::Deg(-20.0));
+        let terrain = generator.build(WORLD_SEED, WATER_LEVEL);
+
+        // Start a few blocks above the ground, or the water if it's flooded
+        let spawn_height = terrain.height_at(0, 10).max(WATER_LEVEL) as f32 + 5.0;
+        let camera = camera::Camera::new((0.0, spawn_height, 10.0), cgmath::Deg(-90.0), cgmath::Deg(-20.0));
+        let render_settings = RenderSettings::default();
+        let camera_settings = camera::CameraSettings::default();
         let projection = camera::Projection::new(
             renderer.config.width,
             renderer.config.height,
-            cgmath::Deg(45.0),
+            camera_settings.fovy(),
             0.1,
-            100.0,
+            render_settings.far_plane(),
         );
         let camera_controller = camera::CameraController::new(16.0, 0.4);
 
+        // Fade into the sky so chunks don't pop in at the far plane
+        let sky = renderer.clear_color();
+        renderer.set_fog(FogUniform::for_far_plane(
+            Vector3::new(sky.r as f32, sky.g as f32, sky.b as f32),
+            projection.far(),
+        ));
+
         let mut camera_uniform = renderer::CameraUniform::new();
         camera_uniform.update_view_proj(&camera, &projection);
 
@@ -75,22 +185,7 @@ impl State {
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             });
 
-        let camera_bind_group_layout =
-            renderer
-                .device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    entries: &[wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    }],
-                    label: Some("camera bind layout group"),
-                });
+        let camera_bind_group_layout = camera_bind_group_layout(&renderer.device);
 
         let camera_bind_group = renderer
             .device
@@ -103,163 +198,155 @@ impl State {
                 label: Some("camera bind group"),
             });
 
-        let chunk_uniform_size = mem::size_of::<ChunkUniform>().next_power_of_two() as wgpu::BufferAddress;
-        // Make the `uniform_alignment` >= `chunk_uniform_size` and aligned to `min_uniform_buffer_offset_alignment`
-        let uniform_alignment = {
-            let alignment = renderer
-                .device
-                .limits()
-                .min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
-            align_to(chunk_uniform_size, alignment)
-        };
+        let block_registry = BlockRegistry::load().unwrap_or_else(|e| panic!("Unable to load blocks: {:?}", e));
+        log::info!("loaded {} block definitions", block_registry.len());
+
+        let tags = BlockTags::load().unwrap_or_else(|e| panic!("Unable to load block tags: {:?}", e));
+        if tags.install().is_err() {
+            log::warn!("Block tags were already installed, keeping those");
+        }
+
+        let diffuse_texture = texture::TextureArray::new(
+            Path::new("sprite_atlas.png"),
+            chunk::TEXTURE_SIZE as u32,
+            &renderer.device,
+            &renderer.queue,
+        );
+        // Room for every chunk at the starting render distance, it grows if that's turned up
+        let chunk_uniforms = ChunkUniforms::new(diffuse_texture, render_settings.max_loaded_chunks() as u32, &renderer.device);
 
         let world = {
-            let mut world = World::new();
-
-            let mut off = 0;
-
-            for chunk_x in -1..=1 {
-                for chunk_y in -1..=1 {
-                    let uniform_offset = (off as u64 * uniform_alignment) as _;
-                    off += 1;
-
-                    let i = world.new_chunk(Vector2::new(chunk_x, chunk_y), uniform_offset, &renderer.device);
-
-                    for x in 0..16 {
-                        for y in -128..(chunk_x+chunk_y+2) {
-                            let block = if y < chunk_x+chunk_y+1 { Block::new_stone() } else { Block::new_grass() };
-                            for z in 0..16 {
-                                world.set_block(
-                                    i,
-                                    Vector3::new(x, y, z),
-                                    block,
-                                );
-                            }
-                        }
-                    }
-
-                    world.set_block(i, Vector3::new(8, chunk_x + chunk_y + 1, 8), Block::new_air());
+            let mut world = World::new(block_registry.texture_table(), chunk_uniforms);
+
+            let chunks = World::chunks_in_range(Vector2::new(0, 0), render_settings.render_distance());
+            world.generate_terrain_parallel(&chunks, &renderer.device, &renderer.queue, terrain.as_ref());
+
+            for location in &chunks {
+                let (x, z) = (location.x * CHUNK_WIDTH as i32 + 8, location.y * CHUNK_DEPTH as i32 + 8);
+                world.set_block(*location, Vector3::new(8, terrain.height_at(x, z), 8), Block::new_air());
+            }
+            world.set_block(Vector2::new(0, 0), Vector3::new(4, terrain.height_at(4, 4), 4), Block::new_slab());
+
+            // One of each block in a row starting on the middle chunk, to check their textures
+            let showcase = [
+                Block::new_grass(true),
+                Block::new_dirt(),
+                Block::new_sand(),
+                Block::new_cobblestone(),
+                Block::new_oak_log(Axis::Y),
+                Block::new_oak_log(Axis::X),
+                Block::new_oak_log(Axis::Z),
+                Block::new_oak_leaves(),
+                Block::new_water(),
+                Block::new_glowstone(),
+                Block::new_magma(),
+            ];
+            for (i, block) in showcase.into_iter().enumerate() {
+                let x = 2 + i as i32 * 2;
+                let ground = terrain.height_at(x, 12);
+                if let Err(e) = world.set_block_global(Vector3::new(x, ground + 1, 12), block) {
+                    log::warn!("Unable to place {} in the showcase: {}", block.name(), e);
                 }
             }
 
+            // A tree in the corner of the middle chunk
+            world.grow_tree(Vector3::new(2, terrain.height_at(2, 2), 2));
+
+            // A patch of tall grass and flowers on the middle chunk, to check cross shaped blocks
+            for x in 9..13 {
+                world.set_block(Vector2::new(0, 0), Vector3::new(x, terrain.height_at(x, 6) + 1, 6), Block::new_tall_grass());
+            }
+            for x in [9, 11] {
+                world.set_block(Vector2::new(0, 0), Vector3::new(x, terrain.height_at(x, 8) + 1, 8), Block::new_flower());
+            }
+
+            // `set_block` only patched the meshes on this side
+            world.update_buffers(&renderer.device, &renderer.queue);
+
             // let chunk1 = world.new_chunk(Vector2::new(0, 0), 0, &renderer.device);
             // let chunk2 = world.new_chunk(Vector2::new(-1, 0), uniform_alignment as _, &renderer.device);
             //
-            // world.set_block(chunk1, Vector3::new(0, 0, 0), Block::new_grass());
+            // world.set_block(chunk1, Vector3::new(0, 0, 0), Block::new_grass(false));
             // world.set_block(chunk1, Vector3::new(0, 1, 0), Block::new_stone());
             // world.set_block(chunk2, Vector3::new(15, 0, 0), Block::new_stone());
-            // world.set_block(chunk2, Vector3::new(15, 0, 1), Block::new_grass());
-
-            world.update_buffers(&renderer.queue);
+            // world.set_block(chunk2, Vector3::new(15, 0, 1), Block::new_grass(false));
 
             world
         };
+        let chunk_loader = ChunkLoader::new(terrain, world.textures(), MAX_CHUNKS_IN_FLIGHT, MAX_CHUNK_UPLOADS_PER_FRAME);
 
-        let mut local_buf = encase::DynamicUniformBuffer::new_with_alignment(Vec::new(), uniform_alignment);
+        let gpu_culler = GpuCuller::is_supported(renderer.downlevel_flags)
+            .then(|| GpuCuller::new(world.chunk_count() as u32, &renderer.device));
+        if gpu_culler.is_none() {
+            log::info!("the adapter can't run compute shaders or indirect draws, culling chunks on the CPU");
+        }
 
-        for (_i, chunk) in world.chunks_iter().enumerate() {
-            let data = ChunkUniform::new(
-                Vector3::new(
-                    (chunk.world_offset.x * CHUNK_WIDTH as i32) as f32,
-                    0.0,
-                    (chunk.world_offset.y * CHUNK_DEPTH as i32) as f32,
-                ),
-            );
+        let render_pipeline_layout = chunk_pipeline_layout(&renderer, &camera_bind_group_layout, world.uniforms().layout());
+        let chunk_pipeline = |polygon_mode| chunk_render_pipeline(&renderer, &render_pipeline_layout, polygon_mode);
 
-            local_buf.write(&data).unwrap();
-        }
+        let render_pipeline = chunk_pipeline(wgpu::PolygonMode::Fill);
+        // Drawing polygons as lines needs a device feature, without it there's no wireframe view
+        let wireframe_render_pipeline = renderer
+            .device
+            .features()
+            .contains(wgpu::Features::POLYGON_MODE_LINE)
+            .then(|| chunk_pipeline(wgpu::PolygonMode::Line));
+
+        let highlight = WireframeMesh::new(&renderer.device);
+
+        let mut highlight_uniform = encase::UniformBuffer::new(Vec::new());
+        highlight_uniform.write(&WireframeUniform { color: Vector4::new(0.0, 0.0, 0.0, 1.0) }).unwrap();
 
-        // Note: dynamic uniform offsets also have to be aligned to `Limits::min_uniform_buffer_offset_alignment`.
-        let chunk_uniform_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Chunk Uniform Buffer"),
-            contents: local_buf.as_ref(),
+        let highlight_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Highlight Uniform Buffer"),
+            contents: &highlight_uniform.into_inner(),
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let local_bind_group_layout = renderer.device
+        let wireframe_bind_group_layout = renderer.device
             .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Texture {
-                            multisampled: false,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                        },
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                        count: None,
-                    },
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: true,
-                            min_binding_size: wgpu::BufferSize::new(chunk_uniform_size),
-                        },
-                        count: None,
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
                     },
-                ],
-                label: None,
+                    count: None,
+                }],
+                label: Some("wireframe bind group layout"),
             });
 
-        let diffuse_texture = texture::Texture::new(
-            Path::new("sprite_atlas.png"),
-            false,
-            &renderer.device,
-            &renderer.queue,
-        );
-
-        let chunk_uniform_bind_group = renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &local_bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                        buffer: &chunk_uniform_buffer,
-                        offset: 0,
-                        size: wgpu::BufferSize::new(chunk_uniform_size),
-                    }),
-                },
-            ],
-            label: None,
+        let highlight_bind_group = renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &wireframe_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: highlight_buffer.as_entire_binding(),
+            }],
+            label: Some("highlight bind group"),
         });
 
-        let render_pipeline_layout =
-            renderer
-                .device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    bind_group_layouts: &[&camera_bind_group_layout, &local_bind_group_layout],
-                    push_constant_ranges: &[],
-                    label: Some("render pipeline layout"),
-                });
-
-        let render_pipeline = {
+        let wireframe_pipeline = {
+            let layout = renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                bind_group_layouts: &[&camera_bind_group_layout, &wireframe_bind_group_layout],
+                push_constant_ranges: &[],
+                label: Some("wireframe pipeline layout"),
+            });
             let shader = wgpu::ShaderModuleDescriptor {
-                source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-                label: Some("Texture Shader"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("wireframe.wgsl").into()),
+                label: Some("Wireframe Shader"),
             };
             renderer::create_render_pipeline(
                 &renderer.device,
-                &render_pipeline_layout,
+                &layout,
                 renderer.config.format,
                 Some(texture::Texture::DEPTH_FORMAT),
-                &[chunk::ChunkVertex::desc()],
+                &[LineVertex::desc()],
                 shader,
+                renderer.sample_count,
+                wgpu::PrimitiveTopology::LineList,
+                wgpu::PolygonMode::Fill,
             )
         };
 
@@ -267,54 +354,65 @@ impl State {
             renderer,
             gui,
             camera,
+            orbit_camera: None,
             projection,
             camera_controller,
+            action_map: ActionMap::default(),
             camera_uniform,
             camera_buffer,
             camera_bind_group,
-            // chunk_uniform_buffer,
-            chunk_uniform_bind_group,
+            render_settings,
+            camera_settings,
             render_pipeline,
+            wireframe_render_pipeline,
+            show_wireframe: false,
             world,
+            chunk_loader,
+            generator,
+            world_seed: WORLD_SEED,
+            highlight,
+            highlight_buffer,
+            highlight_bind_group,
+            wireframe_pipeline,
+            targeted_block: None,
+            mining: MiningState::new(),
+            collected_blocks: 0,
+            hotbar: Hotbar::default(),
+            place_requested: false,
+            random_ticks: Interval::new(1.0 / RANDOM_TICK_RATE),
+            autosave: Interval::new(AUTOSAVE_INTERVAL),
+            app_state: AppState::Running,
             mouse_pressed: false,
+
+            gpu_culler,
+            chunks_drawn: 0,
+            chunks_culled: None,
+            diagnostics: Diagnostics::default(),
         }
     }
 
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
-            self.renderer.size = new_size;
-
             self.projection.resize(new_size.width, new_size.height);
 
-            self.renderer.config.width = new_size.width;
-            self.renderer.config.height = new_size.height;
-
-            self.renderer
-                .surface
-                .configure(&self.renderer.device, &self.renderer.config);
-
-            self.renderer.depth_texture = texture::Texture::create_depth_texture(
-                &self.renderer.device,
-                &self.renderer.config,
-                "depth texture",
-            );
+            self.renderer.resize(new_size);
         }
     }
 
     #[allow(unused_variables)]
     fn input(&mut self, event: &WindowEvent) -> bool {
         match event {
-            WindowEvent::KeyboardInput {
-                input:
-                    KeyboardInput {
-                        virtual_keycode: Some(key),
-                        state,
-                        ..
-                    },
-                ..
-            } => self.camera_controller.process_keyboard(*key, *state),
+            WindowEvent::KeyboardInput { input, .. } => self.action_map.process_keyboard(input),
+            // Scrolling moves the orbit camera closer or further away, picks a block while playing,
+            // and otherwise changes the flying speed
             WindowEvent::MouseWheel { delta, .. } => {
-                self.camera_controller.process_scroll(delta);
+                if let Some(orbit_camera) = &mut self.orbit_camera {
+                    orbit_camera.process_scroll(delta);
+                } else if self.gui.cursor_grabbed {
+                    self.hotbar.process_scroll(delta);
+                } else {
+                    self.camera_controller.process_scroll(delta);
+                }
                 true
             }
             WindowEvent::MouseInput {
@@ -325,59 +423,447 @@ impl State {
                 self.mouse_pressed = *state == ElementState::Pressed;
                 true
             }
+            WindowEvent::MouseInput {
+                button: MouseButton::Right,
+                state: ElementState::Pressed,
+                ..
+            } => {
+                self.place_requested = true;
+                true
+            }
             _ => false,
         }
     }
 
     fn update(&mut self, dt: f32) {
-        self.camera_controller.update_camera(&mut self.camera, dt);
+        if self.action_map.just_pressed(Action::TogglePause) {
+            self.app_state = match self.app_state {
+                AppState::Running => AppState::Paused,
+                AppState::Paused => AppState::Running,
+            };
+        }
+
+        if self.action_map.just_pressed(Action::SaveWorld) {
+            self.save_world();
+        }
+        if self.action_map.just_pressed(Action::LoadWorld) {
+            self.load_world();
+        }
+
+        self.world.drain_finished_meshes(&self.renderer.device, &self.renderer.queue);
+        self.world.compact_meshes(&self.renderer.device);
+
+        if self.app_state == AppState::Running {
+            self.simulate(dt);
+        }
+
+        let camera_chunk = Vector2::new(
+            (self.camera.position.x / CHUNK_WIDTH as f32).floor() as i32,
+            (self.camera.position.z / CHUNK_DEPTH as f32).floor() as i32,
+        );
+        if camera_chunk.x.abs().max(camera_chunk.y.abs()) > world::REBASE_DISTANCE {
+            let shift = self.world.rebase_origin(self.world.origin() + camera_chunk);
+            self.camera.position -= shift;
+        }
+
+        let upload_start = instant::Instant::now();
+        self.world.update_streaming(
+            self.camera.position,
+            self.render_settings.render_distance(),
+            &mut self.chunk_loader,
+            &self.renderer.device,
+            &self.renderer.queue,
+        );
+        self.diagnostics.record("mesh_upload", upload_start.elapsed());
+
+        self.targeted_block = self.world.raycast(self.camera.position, self.camera.forward(), REACH);
+        if let Some(hit) = &self.targeted_block {
+            self.highlight.set_block(&self.renderer.queue, self.world.to_render_space(hit.position));
+        }
+
+        if self.app_state == AppState::Running {
+            self.mine(dt);
+            self.place();
+        }
+        self.place_requested = false;
+
+        // Zooming only narrows the projection, the setting stays as it is
+        let fovy = if self.action_map.pressed(Action::Zoom) {
+            self.camera_settings.zoomed_fovy()
+        } else {
+            self.camera_settings.fovy()
+        };
+        self.projection.set_fovy(fovy);
+
+        if self.action_map.just_pressed(Action::ToggleOrbit) {
+            self.toggle_orbit();
+        }
+        let orbit_view = self.orbit_camera.as_ref().map(camera::OrbitCamera::to_camera);
+
         self.camera_uniform
-            .update_view_proj(&self.camera, &self.projection);
+            .update_view_proj(orbit_view.as_ref().unwrap_or(&self.camera), &self.projection);
         self.renderer.queue.write_buffer(
             &self.camera_buffer,
             0,
             bytemuck::cast_slice(&[self.camera_uniform]),
         );
 
+        if self.action_map.just_pressed(Action::ToggleWireframe) {
+            if self.wireframe_render_pipeline.is_some() {
+                self.show_wireframe = !self.show_wireframe;
+            } else {
+                log::warn!("Wireframe rendering isn't supported by the device");
+            }
+        }
+
+        if self.action_map.just_pressed(Action::Screenshot) {
+            match screenshot_path() {
+                Ok(path) => self.renderer.capture_next_frame(path),
+                Err(e) => log::warn!("Unable to take a screenshot: {:?}", e),
+            }
+        }
+
+        if self.action_map.just_pressed(Action::IncreaseRenderDistance) {
+            self.set_render_distance(self.render_settings.render_distance() + 1);
+        }
+        if self.action_map.just_pressed(Action::DecreaseRenderDistance) {
+            self.set_render_distance(self.render_settings.render_distance().saturating_sub(1));
+        }
+
+        if self.action_map.just_pressed(Action::IncreaseFov) {
+            self.set_fovy(self.camera_settings.fovy() + camera::CameraSettings::FOVY_STEP);
+        }
+        if self.action_map.just_pressed(Action::DecreaseFov) {
+            self.set_fovy(self.camera_settings.fovy() - camera::CameraSettings::FOVY_STEP);
+        }
+
         self.renderer.fps_counter.tick();
+
+        self.action_map.clear_just_pressed();
+    }
+
+    /// Moves the game forward by `dt` seconds: animations, random ticks, autosaves and the camera
+    fn simulate(&mut self, dt: f32) {
+        self.camera_uniform.time += dt;
+
+        let mut changed_blocks = 0;
+        for _ in 0..self.random_ticks.advance(dt) {
+            changed_blocks += self.world.random_tick(&mut rand::thread_rng(), RANDOM_TICKS_PER_CHUNK);
+        }
+        if changed_blocks > 0 {
+            self.world.update_buffers(&self.renderer.device, &self.renderer.queue);
+        }
+
+        if self.autosave.advance(dt) > 0 {
+            self.save_world();
+        }
+
+        // The player stays put while the orbit camera looks around
+        if self.orbit_camera.is_none() {
+            self.camera_controller.process_actions(&self.action_map);
+            self.camera_controller.update_camera(&mut self.camera, &self.world, dt);
+        }
+    }
+
+    /// Mines the targeted block while the left mouse button is held with the cursor grabbed, and
+    /// breaks it once that's gone on for long enough. The highlight turns red along the way.
+    fn mine(&mut self, dt: f32) {
+        let target = self.targeted_block
+            .as_ref()
+            .filter(|_| self.mouse_pressed && self.gui.cursor_grabbed && !self.gui.ui_focus)
+            .and_then(|hit| self.world.get_block_global(hit.position).map(|block| (hit.position, *block)));
+
+        if let Some(event) = self.mining.update(target, dt) {
+            let drops = self.world.break_and_collect(event.position);
+            self.collected_blocks += drops.len();
+            self.world.update_buffers(&self.renderer.device, &self.renderer.queue);
+            log::debug!("broke {} at {:?}, dropping {:?}", event.block.name(), event.position, drops);
+        }
+
+        let mut highlight_uniform = encase::UniformBuffer::new(Vec::new());
+        highlight_uniform
+            .write(&WireframeUniform { color: Vector4::new(self.mining.progress(), 0.0, 0.0, 1.0) })
+            .unwrap();
+        self.renderer.queue.write_buffer(&self.highlight_buffer, 0, &highlight_uniform.into_inner());
+    }
+
+    /// Places the selected block against the targeted one after a right click with the cursor
+    /// grabbed
+    fn place(&mut self) {
+        if !self.place_requested || !self.gui.cursor_grabbed || self.gui.ui_focus {
+            return;
+        }
+
+        let hit = match &self.targeted_block {
+            Some(hit) => hit.clone(),
+            None => return,
+        };
+
+        if self.world.place_block(&hit, self.hotbar.selected(), &camera::player_body(self.camera.position)) {
+            self.world.update_buffers(&self.renderer.device, &self.renderer.queue);
+        }
+    }
+
+    fn save_world(&self) {
+        let path = get_resource(SAVE_FOLDER);
+
+        match self.world.save(&path, self.world_seed, self.camera.state(&self.projection)) {
+            Ok(()) => log::info!("saved {} chunks to {}", self.world.chunk_count(), path.display()),
+            Err(e) => log::warn!("Unable to save the world: {}", e),
+        }
+    }
+
+    /// Replaces the world with the one in the save folder and puts the camera back where it was.
+    /// The current world is kept if the save can't be loaded.
+    fn load_world(&mut self) {
+        let path = get_resource(SAVE_FOLDER);
+
+        let header = match self.world.load(&path, &self.renderer.device, &self.renderer.queue) {
+            Ok(header) => header,
+            Err(e) => {
+                log::warn!("Unable to load the world from {}: {}", path.display(), e);
+                return;
+            }
+        };
+        log::info!("loaded {} chunks from {}", self.world.chunk_count(), path.display());
+
+        // The save doesn't say which generator made it, so the one picked at startup keeps going
+        self.world_seed = WorldSeed(header.seed);
+        self.chunk_loader = ChunkLoader::new(
+            self.generator.build(self.world_seed, WATER_LEVEL),
+            self.world.textures(),
+            MAX_CHUNKS_IN_FLIGHT,
+            MAX_CHUNK_UPLOADS_PER_FRAME,
+        );
+        header.camera.restore(&mut self.camera, &mut self.projection);
+        if let Some(fovy) = self.projection.fovy() {
+            self.camera_settings = camera::CameraSettings::new(fovy);
+        }
+
+        self.orbit_camera = None;
+        self.targeted_block = None;
+        self.mining.reset();
+    }
+
+    /// Changes the render distance, clamped like `RenderSettings::new`, moving the far plane and
+    /// the fog along with it. Chunks are streamed in or out to match from the next update.
+    fn set_render_distance(&mut self, render_distance: u32) {
+        self.render_settings = RenderSettings::new(render_distance);
+        log::info!("render distance is now {} chunks", self.render_settings.render_distance());
+
+        self.projection.set_far(self.render_settings.far_plane());
+        let sky = self.renderer.clear_color();
+        self.renderer.set_fog(FogUniform::for_far_plane(
+            Vector3::new(sky.r as f32, sky.g as f32, sky.b as f32),
+            self.projection.far(),
+        ));
     }
 
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        // let fps = self.renderer.fps_counter.last_second_frames.len();
-        // let bold_font = self.gui.imgui.fonts().fonts()[1];
+    /// Starts orbiting the point where the camera is looking at the targeted block, or a point
+    /// straight ahead if there isn't one, from where the camera is. Stops orbiting if it already
+    /// was.
+    fn toggle_orbit(&mut self) {
+        const FOCUS_DISTANCE: f32 = 10.0;
 
+        self.orbit_camera = match self.orbit_camera {
+            Some(_) => None,
+            None => {
+                let focus = match &self.targeted_block {
+                    Some(hit) => hit.point,
+                    None => self.camera.position + self.camera.forward() * FOCUS_DISTANCE,
+                };
+
+                Some(camera::OrbitCamera::looking_from(&self.camera, focus))
+            }
+        };
+    }
+
+    /// Changes the field of view, clamped like `CameraSettings::new`
+    fn set_fovy(&mut self, fovy: cgmath::Deg<f32>) {
+        self.camera_settings = camera::CameraSettings::new(fovy);
+        log::info!("field of view is now {:.0} degrees", self.camera_settings.fovy().0);
+    }
+
+    fn render(&mut self, window: &Window) -> Result<(), wgpu::SurfaceError> {
         // update uniforms
-        // for chunk in self.chunks.iter() {
-        //     let data = ChunkUniform::new(
-        //         Vector3::new(
-        //             (chunk.world_offset.x * CHUNK_WIDTH as i32) as f32,
-        //             0.0,
-        //             (chunk.world_offset.y * CHUNK_DEPTH as i32) as f32,
-        //         ),
-        //     );
-        //
-        //     self.renderer.queue.write_buffer(
-        //         &self.chunk_uniform_buffer,
-        //         chunk.mesh.uniform_offset as wgpu::BufferAddress,
-        //         bytemuck::bytes_of(&data),
-        //     );
-        // }
-
-        self.renderer.render(
-            &self.render_pipeline,
-            &self.camera_bind_group,
-            &self
+        let now = instant::Instant::now();
+        for (chunk, mesh) in self.world.iter() {
+            let fade = self.world.fade(chunk.world_offset, now);
+            self.world.uniforms().write(mesh.uniform_offset, &chunk_uniform(chunk, self.world.origin(), fade), &self.renderer.queue);
+        }
+
+        let frustum = camera::Frustum::from_matrix(self.camera_uniform.view_proj);
+
+        if let Some(culler) = &mut self.gpu_culler {
+            let bounds = self
                 .world
-                .chunk_mesh_iter()
-                .map(|mesh| (mesh, &self.chunk_uniform_bind_group))
-                .collect::<Vec<_>>(),
-        )?;
+                .iter()
+                .map(|(chunk, mesh)| {
+                    let (min, max) = chunk.bounds(self.world.origin());
+                    ChunkBounds::new(min, max, mesh.num_elements())
+                })
+                .collect::<Vec<_>>();
+            culler.cull(&frustum, &bounds, &self.renderer.device, &self.renderer.queue);
+        }
+
+        let mut objects = Vec::new();
+        let mut culled_objects = Vec::new();
+        match &self.gpu_culler {
+            Some(culler) => {
+                culled_objects.extend(self.world.iter().enumerate().map(|(index, (_, mesh))| CulledChunk {
+                    mesh,
+                    draws: culler.draw_buffer(),
+                    offset: GpuCuller::draw_offset(index),
+                }));
+
+                self.chunks_drawn = culled_objects.len();
+                self.chunks_culled = None;
+            }
+            None => {
+                objects.extend(self.world.iter().filter(|(chunk, _)| {
+                    let (min, max) = chunk.bounds(self.world.origin());
+                    frustum.intersects_aabb(min, max)
+                }));
+
+                self.chunks_drawn = objects.len();
+                self.chunks_culled = Some(self.world.chunk_count() - objects.len());
+            }
+        }
+
+        let stats = DebugStats {
+            fps: self.renderer.fps_counter.last_second_frames.len(),
+            frame_time: self.renderer.fps_counter.average_frame_time(),
+            low_frame_time: self.renderer.fps_counter.percentile(99.0),
+            loaded_chunks: self.world.chunk_count(),
+            queued_chunks: self.chunk_loader.queue_depth(),
+            dirty_chunks: self.world.dirty_chunk_count(),
+            chunks_drawn: self.chunks_drawn,
+            chunks_culled: self.chunks_culled,
+            camera_position: self.camera.position,
+            paused: self.app_state == AppState::Paused,
+            targeted_block: self.targeted_block.as_ref().and_then(|hit| self.world.get_block_global(hit.position)).copied(),
+            selected_block: self.hotbar.selected(),
+            collected_blocks: self.collected_blocks,
+            spans: self.diagnostics.averages().collect(),
+        };
+        let gui = &mut self.gui;
+
+        let mut extras: Vec<(&wgpu::RenderPipeline, &dyn Draw, &wgpu::BindGroup)> = Vec::new();
+        if self.targeted_block.is_some() {
+            extras.push((&self.wireframe_pipeline, &self.highlight, &self.highlight_bind_group));
+        }
+
+        let render_pipeline = match &self.wireframe_render_pipeline {
+            Some(wireframe_render_pipeline) if self.show_wireframe => wireframe_render_pipeline,
+            _ => &self.render_pipeline,
+        };
+
+        let uniforms = self.world.uniforms().bind_group();
+        let draw_gui = |device: &wgpu::Device, queue: &wgpu::Queue, view: &wgpu::TextureView| {
+            gui.draw_debug(window, &stats, device, queue, view)
+        };
+        if self.gpu_culler.is_some() {
+            let objects = culled_objects.iter().map(|chunk| (chunk, uniforms)).collect::<Vec<_>>();
+            self.renderer.render(render_pipeline, &self.camera_bind_group, &objects, &extras, draw_gui)?;
+        } else {
+            let objects = objects.iter().map(|(_, mesh)| (*mesh, uniforms)).collect::<Vec<_>>();
+            self.renderer.render(render_pipeline, &self.camera_bind_group, &objects, &extras, draw_gui)?;
+        }
 
         Ok(())
     }
 }
 
-pub fn run() {
+/// Chunks are drawn untinted, `fade` is how far a newly streamed in chunk has faded in
+fn chunk_uniform(chunk: &Chunk, origin: Vector2<i32>, fade: f32) -> ChunkUniform {
+    ChunkUniform::new(chunk.model_matrix(origin), Vector4::new(1.0, 1.0, 1.0, 1.0), fade)
+}
+
+/// The layout of the bind group holding the `CameraUniform`, shared by every pipeline
+fn camera_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }],
+        label: Some("camera bind layout group"),
+    })
+}
+
+fn chunk_pipeline_layout(
+    renderer: &Renderer,
+    camera_bind_group_layout: &wgpu::BindGroupLayout,
+    uniforms_layout: &wgpu::BindGroupLayout,
+) -> wgpu::PipelineLayout {
+    renderer.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        bind_group_layouts: &[
+            camera_bind_group_layout,
+            uniforms_layout,
+            &renderer.light_bind_group_layout,
+            &renderer.fog_bind_group_layout,
+        ],
+        push_constant_ranges: &[],
+        label: Some("render pipeline layout"),
+    })
+}
+
+/// The pipeline chunks are drawn with, filled in or as lines for the wireframe view
+fn chunk_render_pipeline(renderer: &Renderer, layout: &wgpu::PipelineLayout, polygon_mode: wgpu::PolygonMode) -> wgpu::RenderPipeline {
+    let shader = wgpu::ShaderModuleDescriptor {
+        source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        label: Some("Texture Shader"),
+    };
+    renderer::create_render_pipeline(
+        &renderer.device,
+        layout,
+        renderer.config.format,
+        Some(texture::Texture::DEPTH_FORMAT),
+        &[chunk::ChunkVertex::desc()],
+        shader,
+        renderer.sample_count,
+        wgpu::PrimitiveTopology::TriangleList,
+        polygon_mode,
+    )
+}
+
+/// A new file in the resource folder's `screenshots` directory, named after the current time
+fn screenshot_path() -> anyhow::Result<PathBuf> {
+    let directory = get_resource(SCREENSHOTS_FOLDER);
+    std::fs::create_dir_all(directory.as_path())?;
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    Ok(directory.join(format!("screenshot_{}.png", timestamp)))
+}
+
+/// Grabs the cursor and hides it, or releases and shows it again. Returns whether the cursor
+/// ended up grabbed, since some platforms refuse the grab.
+fn set_cursor_grab(window: &Window, grabbed: bool) -> bool {
+    if let Err(e) = window.set_cursor_grab(grabbed) {
+        log::warn!("Unable to change cursor grab: {:?}", e);
+        window.set_cursor_visible(true);
+        return false;
+    }
+
+    window.set_cursor_visible(!grabbed);
+
+    grabbed
+}
+
+/// Prints the table of atlas cells every block face samples, see `block::dump_uv_table`
+pub fn dump_uv_table() -> anyhow::Result<String> {
+    let block_registry = BlockRegistry::load()?;
+
+    Ok(block::dump_uv_table(&block_registry.texture_table()))
+}
+
+/// Opens the game window, with chunks filled by `generator`
+pub fn run(generator: GeneratorKind) {
     env_logger::init();
 
     let event_loop = EventLoop::new();
@@ -386,7 +872,7 @@ pub fn run() {
         .with_inner_size(PhysicalSize::new(1280, 720))
         .build(&event_loop)
         .unwrap();
-    let mut state = State::new(&window);
+    let mut state = State::new(&window, generator);
 
     let mut last_render_time = instant::Instant::now();
 
@@ -399,6 +885,21 @@ pub fn run() {
                 ref event,
                 window_id,
             } if window_id == window.id() && !state.input(event) => match event {
+                // Escape and losing focus release the cursor grab
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(VirtualKeyCode::Escape),
+                            ..
+                        },
+                    ..
+                } if state.gui.cursor_grabbed => {
+                    state.gui.cursor_grabbed = set_cursor_grab(&window, false);
+                }
+                WindowEvent::Focused(false) if state.gui.cursor_grabbed => {
+                    state.gui.cursor_grabbed = set_cursor_grab(&window, false);
+                }
                 WindowEvent::CloseRequested
                 | WindowEvent::KeyboardInput {
                     input:
@@ -420,9 +921,13 @@ pub fn run() {
             Event::DeviceEvent {
                 event: DeviceEvent::MouseMotion { delta },
                 ..
-            } => {
-                if state.mouse_pressed && !state.gui.ui_focus {
-                    state.camera_controller.process_mouse(delta.0, delta.1)
+            } if (state.mouse_pressed || state.gui.cursor_grabbed)
+                && !state.gui.ui_focus
+                && state.app_state == AppState::Running =>
+            {
+                match &mut state.orbit_camera {
+                    Some(orbit_camera) => orbit_camera.process_mouse(delta.0, delta.1),
+                    None => state.camera_controller.process_mouse(delta.0, delta.1),
                 }
             }
             Event::RedrawRequested(window_id) if window_id == window.id() => {
@@ -432,8 +937,18 @@ pub fn run() {
 
                 state.gui.imgui.io_mut().update_delta_time(dt);
 
+                if state.action_map.just_pressed(Action::ToggleCursorGrab) {
+                    state.gui.cursor_grabbed = set_cursor_grab(&window, !state.gui.cursor_grabbed);
+                }
+
+                let update_start = instant::Instant::now();
                 state.update(dt.as_secs_f32());
-                match state.render() {
+                state.diagnostics.record("update", update_start.elapsed());
+
+                let render_start = instant::Instant::now();
+                let rendered = state.render(&window);
+                state.diagnostics.record("render", render_start.elapsed());
+                match rendered {
                     Ok(_) => {}
                     // Reconfigure the surface if lost
                     Err(wgpu::SurfaceError::Lost) => state.resize(state.renderer.size),
@@ -451,3 +966,66 @@ pub fn run() {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::ChunkMesh;
+
+    /// Draws the chunks of `world` from `camera` into the headless renderer and reads the frame back
+    fn render_world(renderer: &mut Renderer, world: &world::World, camera: &camera::Camera) -> Vec<u8> {
+        let projection = camera::Projection::new(renderer.config.width, renderer.config.height, cgmath::Deg(70.0), 0.1, 100.0);
+        let mut camera_uniform = renderer::CameraUniform::new();
+        camera_uniform.update_view_proj(camera, &projection);
+
+        let camera_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&[camera_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let camera_bind_group_layout = camera_bind_group_layout(&renderer.device);
+        let camera_bind_group = renderer.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: None,
+        });
+
+        let layout = chunk_pipeline_layout(renderer, &camera_bind_group_layout, world.uniforms().layout());
+        let pipeline = chunk_render_pipeline(renderer, &layout, wgpu::PolygonMode::Fill);
+
+        for (chunk, mesh) in world.iter() {
+            world.uniforms().write(mesh.uniform_offset, &chunk_uniform(chunk, world.origin(), 1.0), &renderer.queue);
+        }
+        let objects = world.iter().map(|(_, mesh)| (mesh, world.uniforms().bind_group())).collect::<Vec<(&ChunkMesh, _)>>();
+        renderer.render(&pipeline, &camera_bind_group, &objects, &[], |_, _, _| {}).unwrap();
+
+        renderer.read_pixels().unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn a_grass_chunk_renders_the_same_every_time() {
+        let mut renderer = renderer::tests::headless();
+        let camera = camera::Camera::new((7.5, 8.0, 28.0), cgmath::Deg(-90.0), cgmath::Deg(-30.0));
+
+        let empty = world::tests::test_world(&renderer);
+        let background = render_world(&mut renderer, &empty, &camera);
+
+        let mut world = world::tests::test_world(&renderer);
+        world.generate_chunks_parallel(&[Vector2::new(0, 0)], &renderer.device, &renderer.queue, |_, position| match position.y {
+            -3..=-1 => Block::new_stone(),
+            0 => Block::new_grass(false),
+            _ => Block::new_air(),
+        });
+        let first = render_world(&mut renderer, &world, &camera);
+        let second = render_world(&mut renderer, &world, &camera);
+
+        // The chunk covers a good part of the frame
+        let covered = first.chunks(4).zip(background.chunks(4)).filter(|(pixel, clear)| pixel != clear).count();
+        assert!(covered > first.len() / 4 / 8, "only {} pixels show the chunk", covered);
+        assert_eq!(first, second);
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+
+use cgmath::Vector2;
+use hashbrown::HashMap;
+
+use crate::chunk::{Chunk, ChunkMeshData};
+use crate::registry::TextureTable;
+use crate::terrain::WorldGenerator;
+use crate::world::World;
+
+/// A chunk a worker finished generating and meshing
+struct GeneratedChunk {
+    id: u64,
+    chunk: Chunk,
+    data: ChunkMeshData,
+}
+
+/// A chunk that was asked for and isn't in the world yet
+struct Request {
+    id: u64,
+    /// Set when the request is cancelled, so a worker that hasn't started on it can skip it
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Generates and meshes chunks on rayon's thread pool, so streaming in terrain doesn't stall the
+/// frame. Finished chunks wait in a channel until `upload_finished` moves a few of them into the
+/// world each frame.
+pub struct ChunkLoader {
+    generator: Arc<dyn WorldGenerator>,
+    textures: Arc<TextureTable>,
+
+    /// Every chunk that was requested and isn't uploaded yet, queued or being generated
+    requests: HashMap<Vector2<i32>, Request>,
+    /// Requests waiting for a worker, in the order they were made
+    queued: VecDeque<Vector2<i32>>,
+    /// How many requests were handed to a worker that hasn't sent its chunk back yet
+    in_flight: usize,
+    next_id: u64,
+    finished_sender: Sender<GeneratedChunk>,
+    finished_receiver: Receiver<GeneratedChunk>,
+
+    max_in_flight: usize,
+    max_uploads_per_frame: usize,
+}
+
+impl ChunkLoader {
+    /// A loader running at most `max_in_flight` generation jobs at once and uploading at most
+    /// `max_uploads_per_frame` chunks per `upload_finished`. Both are at least 1.
+    pub fn new(
+        generator: Arc<dyn WorldGenerator>,
+        textures: Arc<TextureTable>,
+        max_in_flight: usize,
+        max_uploads_per_frame: usize,
+    ) -> Self {
+        let (finished_sender, finished_receiver) = mpsc::channel();
+
+        Self {
+            generator,
+            textures,
+
+            requests: HashMap::new(),
+            queued: VecDeque::new(),
+            in_flight: 0,
+            next_id: 0,
+            finished_sender,
+            finished_receiver,
+
+            max_in_flight: max_in_flight.max(1),
+            max_uploads_per_frame: max_uploads_per_frame.max(1),
+        }
+    }
+
+    /// Asks for the chunk to be generated. Does nothing if it was already requested, returns
+    /// whether this made a new request.
+    pub fn request(&mut self, location: Vector2<i32>) -> bool {
+        if self.requests.contains_key(&location) {
+            return false;
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.requests.insert(location, Request { id, cancelled: Arc::new(AtomicBool::new(false)) });
+        self.queued.push_back(location);
+        self.dispatch();
+
+        true
+    }
+
+    /// Drops the request for the chunk, if there is one. A worker that already started on it
+    /// still finishes, but the chunk is thrown away instead of uploaded.
+    pub fn cancel(&mut self, location: Vector2<i32>) -> bool {
+        let request = match self.requests.remove(&location) {
+            Some(request) => request,
+            None => return false,
+        };
+
+        request.cancelled.store(true, Ordering::Relaxed);
+        self.queued.retain(|queued| *queued != location);
+
+        true
+    }
+
+    /// Cancels every request that isn't within `render_distance` chunks of `center`, see
+    /// `World::chunks_out_of_range`. Returns how many were cancelled.
+    pub fn cancel_out_of_range(&mut self, center: Vector2<i32>, render_distance: u32) -> usize {
+        let distance = render_distance as i32;
+        let out_of_range = self.requests
+            .keys()
+            .filter(|location| {
+                let offset = *location - center;
+                offset.x.abs().max(offset.y.abs()) > distance
+            })
+            .copied()
+            .collect::<Vec<_>>();
+
+        out_of_range.into_iter().filter(|location| self.cancel(*location)).count()
+    }
+
+    /// Hands queued requests to the thread pool until `max_in_flight` are being worked on
+    fn dispatch(&mut self) {
+        while self.in_flight < self.max_in_flight {
+            let location = match self.queued.pop_front() {
+                Some(location) => location,
+                None => break,
+            };
+            let request = &self.requests[&location];
+
+            let id = request.id;
+            let cancelled = request.cancelled.clone();
+            let generator = self.generator.clone();
+            let textures = self.textures.clone();
+            let sender = self.finished_sender.clone();
+            self.in_flight += 1;
+
+            rayon::spawn(move || {
+                // A cancelled job still reports back, so it stops counting as in flight
+                let (chunk, data) = if cancelled.load(Ordering::Relaxed) {
+                    (Chunk::new(location), ChunkMeshData::new())
+                } else {
+                    let chunk = generator.generate_chunk(location);
+                    // The neighbors aren't known here, the world remeshes the chunk once it's
+                    // inserted next to them
+                    let mut data = ChunkMeshData::from_chunk(&chunk, [None; 4], &textures);
+                    data.compact();
+                    (chunk, data)
+                };
+
+                // The loader is gone, so nobody wants the chunk anymore
+                let _ = sender.send(GeneratedChunk { id, chunk, data });
+            });
+        }
+    }
+
+    /// Inserts up to `max_uploads_per_frame` finished chunks into the world and uploads their
+    /// meshes. Should be called once per frame, returns how many chunks were uploaded.
+    pub fn upload_finished(&mut self, world: &mut World, device: &wgpu::Device, queue: &wgpu::Queue) -> usize {
+        let finished = self.take_finished();
+        let uploaded = finished.len();
+
+        for (chunk, data) in finished {
+            world.insert_meshed_chunk(chunk, data, device, queue);
+        }
+
+        uploaded
+    }
+
+    /// Takes up to `max_uploads_per_frame` finished chunks that are still wanted off the channel,
+    /// then hands the freed up slots to queued requests
+    fn take_finished(&mut self) -> Vec<(Chunk, ChunkMeshData)> {
+        let mut finished = Vec::new();
+
+        while finished.len() < self.max_uploads_per_frame {
+            let generated = match self.finished_receiver.try_recv() {
+                Ok(generated) => generated,
+                Err(_) => break,
+            };
+            self.in_flight -= 1;
+
+            // Cancelled, or cancelled and requested again, since the job started
+            let location = generated.chunk.world_offset;
+            if self.requests.get(&location).map(|request| request.id) != Some(generated.id) {
+                continue;
+            }
+            self.requests.remove(&location);
+
+            finished.push((generated.chunk, generated.data));
+        }
+
+        self.dispatch();
+
+        finished
+    }
+
+    pub fn generator(&self) -> &dyn WorldGenerator {
+        self.generator.as_ref()
+    }
+
+    /// How many requested chunks aren't in the world yet
+    pub fn queue_depth(&self) -> usize {
+        self.requests.len()
+    }
+
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight
+    }
+
+    /// Changes how many chunks are generated at once, at least 1. Jobs that already started
+    /// aren't affected.
+    pub fn set_max_in_flight(&mut self, max_in_flight: usize) {
+        self.max_in_flight = max_in_flight.max(1);
+        self.dispatch();
+    }
+
+    pub fn max_uploads_per_frame(&self) -> usize {
+        self.max_uploads_per_frame
+    }
+
+    /// Changes how many chunks `upload_finished` uploads at most, at least 1
+    pub fn set_max_uploads_per_frame(&mut self, max_uploads_per_frame: usize) {
+        self.max_uploads_per_frame = max_uploads_per_frame.max(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::chunk::MIN_Y;
+
+    /// Leaves chunks empty, so the tests don't wait on terrain
+    struct AirGenerator;
+
+    impl WorldGenerator for AirGenerator {
+        fn generate(&self, _offset: Vector2<i32>, _chunk: &mut Chunk) {}
+
+        fn height_at(&self, _x: i32, _z: i32) -> i32 {
+            MIN_Y
+        }
+    }
+
+    fn loader(max_in_flight: usize, max_uploads_per_frame: usize) -> ChunkLoader {
+        ChunkLoader::new(
+            Arc::new(AirGenerator),
+            Arc::new(TextureTable::default()),
+            max_in_flight,
+            max_uploads_per_frame,
+        )
+    }
+
+    /// Takes finished chunks frame by frame until every request is done, waiting on the workers
+    /// between frames that had nothing to take. Returns the chunks taken each frame.
+    fn take_all(loader: &mut ChunkLoader) -> Vec<Vec<Vector2<i32>>> {
+        let mut frames = Vec::new();
+
+        while loader.queue_depth() > 0 || loader.in_flight > 0 {
+            let finished = loader.take_finished();
+            if finished.is_empty() {
+                std::thread::sleep(Duration::from_millis(1));
+                continue;
+            }
+
+            frames.push(finished.into_iter().map(|(chunk, _)| chunk.world_offset).collect());
+        }
+
+        frames
+    }
+
+    #[test]
+    fn uploads_are_spread_over_frames_by_the_budget() {
+        let mut loader = loader(16, 8);
+        for x in 0..10 {
+            for z in 0..10 {
+                assert!(loader.request(Vector2::new(x, z)));
+            }
+        }
+        assert_eq!(loader.queue_depth(), 100);
+
+        let frames = take_all(&mut loader);
+
+        assert!(frames.iter().all(|frame| frame.len() <= 8));
+        assert!(frames.len() >= 100usize.div_ceil(8));
+        assert_eq!(frames.iter().map(Vec::len).sum::<usize>(), 100);
+    }
+
+    #[test]
+    fn requests_are_deduplicated_and_cancellable() {
+        let mut loader = loader(1, 8);
+        let (first, second, far) = (Vector2::new(0, 0), Vector2::new(1, 0), Vector2::new(20, 0));
+
+        assert!(loader.request(first));
+        assert!(loader.request(second));
+        assert!(loader.request(far));
+        assert!(!loader.request(second));
+        assert_eq!(loader.queue_depth(), 3);
+
+        // The first one is already being worked on, the other two are still queued
+        assert!(loader.cancel(first));
+        assert!(!loader.cancel(first));
+        assert_eq!(loader.cancel_out_of_range(Vector2::new(0, 0), 8), 1);
+        assert_eq!(loader.queue_depth(), 1);
+
+        assert_eq!(take_all(&mut loader).concat(), vec![second]);
+    }
+}
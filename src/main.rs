@@ -9,5 +9,30 @@ fn main() {
     if cfg!(debug_assertions) {
         env::set_var("RUST_BACKTRACE", "1");
     }
-    wgpu_voxel_game::run();
+
+    // Prints which part of the atlas every block face samples, without opening a window
+    if env::args().any(|arg| arg == "--dump-uvs") {
+        match wgpu_voxel_game::dump_uv_table() {
+            Ok(table) => print!("{}", table),
+            Err(e) => {
+                eprintln!("Unable to load blocks: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // Picks the world generator, like `--generator flat`
+    let generator = match env::args().skip_while(|arg| arg != "--generator").nth(1) {
+        Some(name) => match name.parse() {
+            Ok(generator) => generator,
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => wgpu_voxel_game::GeneratorKind::default(),
+    };
+
+    wgpu_voxel_game::run(generator);
 }
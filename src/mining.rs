@@ -0,0 +1,90 @@
+use cgmath::Vector3;
+
+use crate::block::Block;
+
+/// A block that was mined until it broke, in world space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockBreakEvent {
+    pub position: Vector3<i32>,
+    pub block: Block,
+}
+
+/// How far along breaking the targeted block is. Switching to another block, or letting go,
+/// starts over.
+#[derive(Debug, Default)]
+pub struct MiningState {
+    target: Option<(Vector3<i32>, Block)>,
+    /// Seconds spent mining the target
+    elapsed: f32,
+}
+
+impl MiningState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mines `target` for another `dt` seconds, or stops mining if there's nothing to mine.
+    /// Returns the break once the time spent reaches the block's `hardness`, then starts over.
+    pub fn update(&mut self, target: Option<(Vector3<i32>, Block)>, dt: f32) -> Option<BlockBreakEvent> {
+        if target != self.target {
+            self.reset();
+            self.target = target;
+        }
+
+        let (position, block) = self.target?;
+        self.elapsed += dt;
+
+        if self.elapsed < block.hardness() {
+            return None;
+        }
+
+        self.reset();
+        Some(BlockBreakEvent { position, block })
+    }
+
+    /// Stops mining, losing the progress
+    pub fn reset(&mut self) {
+        self.target = None;
+        self.elapsed = 0.0;
+    }
+
+    /// How far along breaking the target is, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        match self.target {
+            Some((_, block)) if block.hardness() > 0.0 => (self.elapsed / block.hardness()).min(1.0),
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holding_on_a_block_for_its_hardness_breaks_it_once() {
+        let (position, stone) = (Vector3::new(3, 4, 5), Block::new_stone());
+        let half = stone.hardness() / 2.0;
+        let mut mining = MiningState::new();
+
+        assert_eq!(mining.update(Some((position, stone)), half), None);
+        assert_eq!(mining.progress(), 0.5);
+        assert_eq!(mining.update(Some((position, stone)), half), Some(BlockBreakEvent { position, block: stone }));
+        assert_eq!(mining.progress(), 0.0);
+    }
+
+    #[test]
+    fn switching_targets_or_letting_go_starts_over() {
+        let stone = Block::new_stone();
+        let half = stone.hardness() / 2.0;
+        let mut mining = MiningState::new();
+
+        mining.update(Some((Vector3::new(0, 0, 0), stone)), half);
+        assert_eq!(mining.update(Some((Vector3::new(1, 0, 0), stone)), half), None);
+        assert_eq!(mining.progress(), 0.5);
+
+        assert_eq!(mining.update(None, half), None);
+        assert_eq!(mining.progress(), 0.0);
+        assert_eq!(mining.update(Some((Vector3::new(1, 0, 0), stone)), half), None);
+    }
+}
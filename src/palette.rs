@@ -0,0 +1,236 @@
+use crate::block::Block;
+use crate::chunk::{CHUNK_DEPTH, CHUNK_HEIGHT, CHUNK_WIDTH};
+
+pub const SECTION_HEIGHT: usize = 16;
+const SECTION_VOLUME: usize = CHUNK_WIDTH * SECTION_HEIGHT * CHUNK_DEPTH;
+const SECTION_COUNT: usize = CHUNK_HEIGHT / SECTION_HEIGHT;
+
+const _: () = assert!(SECTION_COUNT * SECTION_HEIGHT == CHUNK_HEIGHT, "CHUNK_HEIGHT must be a multiple of SECTION_HEIGHT");
+
+/// A 16x16x16 slice of a chunk's blocks. Every distinct block in the section is stored once in
+/// the palette and positions refer to it by index, so sections made of a single kind of block
+/// (like open sky) don't store anything per position.
+#[derive(Debug, Clone)]
+struct Section {
+    palette: Vec<Block>,
+    /// How many positions use each palette entry, so entries can be dropped once they're unused
+    counts: Vec<u16>,
+    /// The palette index of every position, empty while the palette has a single entry. There
+    /// are only a few dozen distinct blocks counting their states, so the palette can't outgrow
+    /// a `u8` index.
+    indices: Vec<u8>,
+}
+
+impl Section {
+    fn from_fn(f: impl Fn(usize) -> Block) -> Self {
+        let mut section = Self {
+            palette: Vec::new(),
+            counts: Vec::new(),
+            indices: Vec::with_capacity(SECTION_VOLUME),
+        };
+
+        for i in 0..SECTION_VOLUME {
+            let index = section.palette_index(f(i));
+            section.indices.push(index);
+            section.counts[index as usize] += 1;
+        }
+
+        if section.palette.len() == 1 {
+            section.indices = Vec::new();
+        }
+
+        section
+    }
+
+    /// The block's index in the palette, adding it if it isn't there yet
+    fn palette_index(&mut self, block: Block) -> u8 {
+        match self.palette.iter().position(|entry| *entry == block) {
+            Some(index) => index as u8,
+            None => {
+                assert!(self.palette.len() <= u8::MAX as usize, "more distinct blocks than a palette index can hold");
+                self.palette.push(block);
+                self.counts.push(0);
+                (self.palette.len() - 1) as u8
+            }
+        }
+    }
+
+    fn index_at(&self, i: usize) -> usize {
+        self.indices.get(i).map_or(0, |index| *index as usize)
+    }
+
+    fn get(&self, i: usize) -> &Block {
+        &self.palette[self.index_at(i)]
+    }
+
+    /// Returns the block that was there before
+    fn set(&mut self, i: usize, block: Block) -> Block {
+        let old = self.index_at(i);
+        let previous = self.palette[old];
+        if previous == block {
+            return previous;
+        }
+
+        let new = self.palette_index(block);
+        if self.indices.is_empty() {
+            self.indices = vec![0; SECTION_VOLUME];
+        }
+
+        self.indices[i] = new;
+        self.counts[new as usize] += 1;
+        self.counts[old] -= 1;
+
+        if self.counts[old] == 0 {
+            self.remove_entry(old);
+        }
+
+        previous
+    }
+
+    /// Drops an unused palette entry. The last entry takes its place, so the indices pointing at
+    /// it are moved over.
+    fn remove_entry(&mut self, index: usize) {
+        let last = self.palette.len() - 1;
+
+        self.palette.swap_remove(index);
+        self.counts.swap_remove(index);
+
+        if self.palette.len() == 1 {
+            self.indices = Vec::new();
+        } else if index != last {
+            for entry in self.indices.iter_mut().filter(|entry| **entry as usize == last) {
+                *entry = index as u8;
+            }
+        }
+    }
+
+    fn heap_size(&self) -> usize {
+        self.palette.capacity() * std::mem::size_of::<Block>()
+            + self.counts.capacity() * std::mem::size_of::<u16>()
+            + self.indices.capacity()
+    }
+}
+
+/// The blocks of a chunk, stored as a stack of paletted sections. Positions are array indices,
+/// so y runs from 0 to `CHUNK_HEIGHT` here.
+#[derive(Debug, Clone)]
+pub struct BlockStorage {
+    sections: Vec<Section>,
+}
+
+impl BlockStorage {
+    pub fn from_fn(f: impl Fn(usize, usize, usize) -> Block) -> Self {
+        let sections = (0..SECTION_COUNT)
+            .map(|section| Section::from_fn(|i| {
+                let (x, y, z) = BlockStorage::position_in_section(i);
+                f(x, section * SECTION_HEIGHT + y, z)
+            }))
+            .collect();
+
+        Self { sections }
+    }
+
+    fn position_in_section(i: usize) -> (usize, usize, usize) {
+        (i % CHUNK_WIDTH, (i / CHUNK_WIDTH) % SECTION_HEIGHT, i / (CHUNK_WIDTH * SECTION_HEIGHT))
+    }
+
+    /// The section and the index within it, or `None` if the position is outside the chunk
+    fn locate(x: usize, y: usize, z: usize) -> Option<(usize, usize)> {
+        (x < CHUNK_WIDTH && y < CHUNK_HEIGHT && z < CHUNK_DEPTH).then(|| {
+            (y / SECTION_HEIGHT, x + CHUNK_WIDTH * (y % SECTION_HEIGHT + SECTION_HEIGHT * z))
+        })
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Option<&Block> {
+        let (section, i) = BlockStorage::locate(x, y, z)?;
+        Some(self.sections[section].get(i))
+    }
+
+    /// Returns the block that was there before. Panics if the position is outside the chunk.
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: Block) -> Block {
+        let (section, i) = BlockStorage::locate(x, y, z)
+            .unwrap_or_else(|| panic!("block position ({}, {}, {}) is outside the chunk", x, y, z));
+
+        self.sections[section].set(i, block)
+    }
+
+    /// Iterates over every position and its block, skipping sections that only hold `skip`
+    pub fn indexed_iter_except(&self, skip: Block) -> impl Iterator<Item = ((usize, usize, usize), &Block)> {
+        self.sections
+            .iter()
+            .enumerate()
+            .filter(move |(_, section)| section.palette != [skip])
+            .flat_map(|(index, section)| {
+                (0..SECTION_VOLUME).map(move |i| {
+                    let (x, y, z) = BlockStorage::position_in_section(i);
+                    ((x, index * SECTION_HEIGHT + y, z), section.get(i))
+                })
+            })
+    }
+
+    /// Roughly how many bytes the blocks take up, including the palettes
+    pub fn heap_size(&self) -> usize {
+        self.sections.iter().map(|section| std::mem::size_of::<Section>() + section.heap_size()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn air_storage() -> BlockStorage {
+        BlockStorage::from_fn(|_, _, _| Block::new_air())
+    }
+
+    #[test]
+    fn uniform_sections_store_no_indices() {
+        let storage = air_storage();
+
+        assert!(storage.sections.iter().all(|section| section.palette == [Block::new_air()] && section.indices.is_empty()));
+        assert_eq!(storage.get(15, CHUNK_HEIGHT - 1, 15), Some(&Block::new_air()));
+        assert_eq!(storage.get(16, 0, 0), None);
+        assert_eq!(storage.get(0, CHUNK_HEIGHT, 0), None);
+    }
+
+    #[test]
+    fn sections_go_back_to_a_single_entry_once_a_block_is_gone() {
+        let mut storage = air_storage();
+
+        assert_eq!(storage.set(3, 20, 5, Block::new_stone()), Block::new_air());
+        let section = &storage.sections[20 / SECTION_HEIGHT];
+        assert_eq!(section.palette.len(), 2);
+        assert_eq!(section.indices.len(), SECTION_VOLUME);
+        assert_eq!(storage.get(3, 20, 5), Some(&Block::new_stone()));
+
+        assert_eq!(storage.set(3, 20, 5, Block::new_air()), Block::new_stone());
+        let section = &storage.sections[20 / SECTION_HEIGHT];
+        assert_eq!(section.palette, [Block::new_air()]);
+        assert!(section.indices.is_empty());
+    }
+
+    #[test]
+    fn removing_a_palette_entry_keeps_the_other_blocks() {
+        let mut storage = air_storage();
+        storage.set(0, 0, 0, Block::new_stone());
+        storage.set(1, 0, 0, Block::new_dirt());
+        storage.set(2, 0, 0, Block::new_sand());
+
+        // Stone's entry is dropped and the last entry, sand, is moved into its place
+        storage.set(0, 0, 0, Block::new_air());
+        assert_eq!(storage.sections[0].palette.len(), 3);
+        assert_eq!(storage.get(0, 0, 0), Some(&Block::new_air()));
+        assert_eq!(storage.get(1, 0, 0), Some(&Block::new_dirt()));
+        assert_eq!(storage.get(2, 0, 0), Some(&Block::new_sand()));
+    }
+
+    #[test]
+    fn iterating_skips_sections_of_only_the_skipped_block() {
+        let mut storage = air_storage();
+        storage.set(0, SECTION_HEIGHT + 1, 0, Block::new_stone());
+
+        let blocks = storage.indexed_iter_except(Block::new_air()).collect::<Vec<_>>();
+        assert_eq!(blocks.len(), SECTION_VOLUME);
+        assert!(blocks.contains(&((0, SECTION_HEIGHT + 1, 0), &Block::new_stone())));
+        assert!(blocks.iter().all(|((_, y, _), _)| (SECTION_HEIGHT..2 * SECTION_HEIGHT).contains(y)));
+    }
+}
@@ -0,0 +1,405 @@
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use hashbrown::HashMap;
+use serde::Deserialize;
+
+use crate::block::{Block, TextureAnimation, TextureLayers, RETIRED_BLOCK_IDS};
+use crate::chunk::{ATLAS_SIZE, MAX_LIGHT, TEXTURE_SIZE};
+use crate::resources::get_resource;
+
+/// Where the block definitions live, relative to the resource folder
+pub const BLOCKS_FOLDER: &str = "blocks";
+
+/// Refers to a block definition in a `BlockRegistry`. Built-in blocks keep their stable
+/// `Block::id`, the rest are handed out after them in the order the files are loaded, sorted by
+/// file name, so those are only stable for the same set of files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u16);
+
+/// The atlas tiles used by a block's faces, as pixel coordinates of the tile's top left corner
+/// like `TextureLayers::atlas`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub enum BlockTextures {
+    All((u32, u32)),
+    TopBottomSides {
+        top: (u32, u32),
+        bottom: (u32, u32),
+        sides: (u32, u32),
+    },
+    Faces {
+        front: (u32, u32),
+        back: (u32, u32),
+        top: (u32, u32),
+        bottom: (u32, u32),
+        left: (u32, u32),
+        right: (u32, u32),
+    },
+}
+
+impl BlockTextures {
+    fn tiles(&self) -> Vec<(u32, u32)> {
+        match self {
+            BlockTextures::All(tile) => vec![*tile],
+            BlockTextures::TopBottomSides { top, bottom, sides } => vec![*top, *bottom, *sides],
+            BlockTextures::Faces { front, back, top, bottom, left, right } => {
+                vec![*front, *back, *top, *bottom, *left, *right]
+            }
+        }
+    }
+
+    pub fn layers(&self) -> TextureLayers {
+        let layer = |(x, y): (u32, u32)| TextureLayers::atlas(x, y);
+
+        match self {
+            BlockTextures::All(tile) => TextureLayers::all_same(layer(*tile)),
+            BlockTextures::TopBottomSides { top, bottom, sides } => {
+                TextureLayers::top_bottom_sides(layer(*top), layer(*bottom), layer(*sides))
+            }
+            BlockTextures::Faces { front, back, top, bottom, left, right } => TextureLayers {
+                front: layer(*front),
+                back: layer(*back),
+                top: layer(*top),
+                bottom: layer(*bottom),
+                left: layer(*left),
+                right: layer(*right),
+            },
+        }
+    }
+}
+
+/// A block type as described by one of the `.ron` files in `res/blocks`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BlockDef {
+    pub name: String,
+    pub textures: BlockTextures,
+    /// Whether you can see through the block, so faces behind it still have to be drawn
+    #[serde(default)]
+    pub transparent: bool,
+    /// Whether the block has collision
+    #[serde(default = "default_solid")]
+    pub solid: bool,
+    /// How much light the block gives off, from 0 to `MAX_LIGHT`
+    #[serde(default)]
+    pub light_emission: u8,
+    /// How every face's texture is animated. The frames after the first are the tiles below it.
+    #[serde(default)]
+    pub animation: TextureAnimation,
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+impl BlockDef {
+    /// Checks that every tile the block uses, including every animation frame, lies on the tile
+    /// grid inside of the atlas, and that the light emission and animation are in range
+    fn validate(&self) -> anyhow::Result<()> {
+        let (atlas_size, tile_size) = (ATLAS_SIZE as u32, TEXTURE_SIZE as u32);
+        let TextureAnimation { frames, ticks_per_frame } = self.animation;
+
+        if self.light_emission > MAX_LIGHT {
+            bail!("light emission {} is brighter than the maximum of {}", self.light_emission, MAX_LIGHT);
+        }
+
+        if !(1..=TextureAnimation::MAX_FRAMES).contains(&frames) {
+            bail!("animations need between 1 and {} frames, not {}", TextureAnimation::MAX_FRAMES, frames);
+        }
+
+        if !(1..=TextureAnimation::MAX_TICKS_PER_FRAME).contains(&ticks_per_frame) {
+            bail!(
+                "animation frames last between 1 and {} ticks, not {}",
+                TextureAnimation::MAX_TICKS_PER_FRAME,
+                ticks_per_frame
+            );
+        }
+
+        for (x, y) in self.textures.tiles() {
+            if x % tile_size != 0 || y % tile_size != 0 {
+                bail!("texture coordinates ({}, {}) aren't aligned to the {}px tile grid", x, y, tile_size);
+            }
+
+            let bottom = y + frames * tile_size;
+            if x + tile_size > atlas_size || bottom > atlas_size {
+                bail!("texture coordinates ({}, {}) are outside of the {}px atlas", x, y, atlas_size);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The texture layers and animations from the block definition files, by block id. Built-in
+/// blocks in their default state use these instead of their hardcoded `texture_layers` and
+/// `texture_animation`, so they can be retextured without recompiling. Other states, like snowy
+/// grass, keep their own textures.
+#[derive(Debug, Default, Clone)]
+pub struct TextureTable {
+    layers: Vec<Option<(TextureLayers, TextureAnimation)>>,
+}
+
+impl TextureTable {
+    /// The layers and animation to use for the block, or `None` if it should use its own
+    pub fn get(&self, block: &Block) -> Option<&(TextureLayers, TextureAnimation)> {
+        let id = block.id();
+        if Block::from_id(id).as_ref() != Some(block) {
+            return None;
+        }
+
+        self.layers.get(id as usize)?.as_ref()
+    }
+}
+
+/// Every block type loaded from the block definition files. The built-in blocks are registered
+/// first, from the files named after them, so a `Block` can always be looked up by its id.
+#[derive(Debug, Default)]
+pub struct BlockRegistry {
+    /// Indexed by id. The ids in `RETIRED_BLOCK_IDS` are left empty.
+    defs: Vec<Option<BlockDef>>,
+    ids: HashMap<String, BlockId>,
+}
+
+impl BlockRegistry {
+    /// Loads every `.ron` file in the resource folder's `blocks` directory
+    pub fn load() -> anyhow::Result<Self> {
+        BlockRegistry::load_dir(&get_resource(BLOCKS_FOLDER))
+    }
+
+    /// Loads every `.ron` file in `dir`, sorted by file name. Errors name the file at fault.
+    pub fn load_dir(dir: &Path) -> anyhow::Result<Self> {
+        let mut paths = std::fs::read_dir(dir)
+            .with_context(|| format!("unable to read block definitions from {}", dir.display()))?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|path| path.extension().is_some_and(|extension| extension == "ron"));
+        paths.sort();
+
+        let mut defs = paths
+            .iter()
+            .map(|path| {
+                BlockRegistry::load_def(path)
+                    .map(|def| (path, def))
+                    .with_context(|| format!("invalid block definition {}", path.display()))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut registry = BlockRegistry::default();
+
+        for block in Block::all() {
+            let index = defs
+                .iter()
+                .position(|(_, def)| def.name == block.key())
+                .with_context(|| format!("no definition for the built-in block {:?}", block.key()))?;
+            let (path, def) = defs.remove(index);
+
+            // Removed blocks keep their ids, so the built-in blocks after them still line up
+            while registry.defs.len() < block.id() as usize {
+                let id = registry.defs.len() as u16;
+                if !RETIRED_BLOCK_IDS.contains(&id) {
+                    bail!("no built-in block has id {}, but it isn't retired", id);
+                }
+                registry.defs.push(None);
+            }
+
+            let id = registry.register(def)
+                .with_context(|| format!("invalid block definition {}", path.display()))?;
+            if id.0 != block.id() {
+                bail!("built-in block {:?} has id {} but was registered as {}", block.key(), block.id(), id.0);
+            }
+        }
+
+        for (path, def) in defs {
+            registry.register(def)
+                .with_context(|| format!("invalid block definition {}", path.display()))?;
+        }
+
+        Ok(registry)
+    }
+
+    fn load_def(path: &Path) -> anyhow::Result<BlockDef> {
+        let source = std::fs::read_to_string(path)?;
+        let def: BlockDef = ron::from_str(&source)?;
+        def.validate()?;
+
+        Ok(def)
+    }
+
+    /// Adds a block definition, failing if its name is already taken
+    pub fn register(&mut self, def: BlockDef) -> anyhow::Result<BlockId> {
+        if self.ids.contains_key(&def.name) {
+            bail!("a block named {:?} is already registered", def.name);
+        }
+
+        let id = BlockId(u16::try_from(self.defs.len()).context("too many block definitions")?);
+        self.ids.insert(def.name.clone(), id);
+        self.defs.push(Some(def));
+
+        Ok(id)
+    }
+
+    /// The definition of a block. Ids always come from the registry, so this panics on ids from a
+    /// different one, or retired ones.
+    pub fn get(&self, id: BlockId) -> &BlockDef {
+        self.defs[id.0 as usize].as_ref().unwrap_or_else(|| panic!("block id {} is retired", id.0))
+    }
+
+    /// The built-in block with the given id, or `None` for blocks that only exist as definitions
+    pub fn block(&self, id: BlockId) -> Option<Block> {
+        Block::from_id(id.0)
+    }
+
+    pub fn id_of(&self, block: &Block) -> BlockId {
+        BlockId(block.id())
+    }
+
+    /// The texture layers of every definition, for the mesher
+    pub fn texture_table(&self) -> TextureTable {
+        TextureTable {
+            layers: self.defs.iter().map(|def| def.as_ref().map(|def| (def.textures.layers(), def.animation))).collect(),
+        }
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<BlockId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.defs.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &BlockDef)> {
+        self.defs.iter().enumerate().filter_map(|(i, def)| Some((BlockId(i as u16), def.as_ref()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// A fresh directory holding a copy of the shipped block definitions
+    fn definitions_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wgpu_voxel_game-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for entry in std::fs::read_dir(get_resource(BLOCKS_FOLDER).as_path()).unwrap() {
+            let path = entry.unwrap().path();
+            std::fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn the_shipped_definitions_register_every_built_in_block() {
+        let registry = BlockRegistry::load().unwrap();
+
+        for block in Block::all() {
+            let id = registry.by_name(block.key()).unwrap();
+            assert_eq!(id, registry.id_of(&block));
+            assert_eq!(registry.get(id).name, block.key());
+        }
+        assert_eq!(registry.by_name("not a block"), None);
+    }
+
+    #[test]
+    fn a_definition_outside_of_the_atlas_names_its_file() {
+        let dir = definitions_dir("outside-atlas");
+        let source = format!("BlockDef(name: \"offscreen\", textures: All(({}, 0)))", ATLAS_SIZE);
+        std::fs::write(dir.join("offscreen.ron"), source).unwrap();
+
+        let error = format!("{:#}", BlockRegistry::load_dir(&dir).unwrap_err());
+        assert!(error.contains("offscreen.ron"), "{}", error);
+        assert!(error.contains("outside of the"), "{}", error);
+    }
+
+    #[test]
+    fn a_misaligned_or_too_bright_definition_is_rejected() {
+        let def = |textures, light_emission| BlockDef {
+            name: "test".to_string(),
+            textures,
+            transparent: false,
+            solid: true,
+            light_emission,
+            animation: TextureAnimation::default(),
+        };
+
+        assert!(def(BlockTextures::All((0, 0)), MAX_LIGHT).validate().is_ok());
+        assert!(def(BlockTextures::All((8, 0)), 0).validate().is_err());
+        assert!(def(BlockTextures::All((0, 0)), MAX_LIGHT + 1).validate().is_err());
+    }
+
+    #[test]
+    fn unreadable_or_missing_definitions_are_errors() {
+        let dir = definitions_dir("missing-built-in");
+        std::fs::remove_file(dir.join("stone.ron")).unwrap();
+        let error = format!("{:#}", BlockRegistry::load_dir(&dir).unwrap_err());
+        assert!(error.contains("\"stone\""), "{}", error);
+
+        std::fs::write(dir.join("broken.ron"), "BlockDef(name: ").unwrap();
+        let error = format!("{:#}", BlockRegistry::load_dir(&dir).unwrap_err());
+        assert!(error.contains("broken.ron"), "{}", error);
+
+        assert!(BlockRegistry::load_dir(&dir.join("missing")).is_err());
+    }
+
+    #[test]
+    fn a_name_can_only_be_registered_once() {
+        let mut registry = BlockRegistry::load().unwrap();
+        let stone = registry.get(registry.by_name("stone").unwrap()).clone();
+
+        assert!(registry.register(stone).is_err());
+    }
+
+    #[test]
+    fn added_definitions_come_after_the_built_in_blocks() {
+        let dir = definitions_dir("added");
+        std::fs::write(dir.join("marble.ron"), "BlockDef(name: \"marble\", textures: All((0, 16)))").unwrap();
+
+        let registry = BlockRegistry::load_dir(&dir).unwrap();
+        let marble = registry.by_name("marble").unwrap();
+
+        // Retired ids stay empty, so marble gets the id after the last built-in block
+        let last_id = Block::all().map(|block| block.id()).max().unwrap();
+        assert_eq!(marble.0, last_id + 1);
+        assert_eq!(registry.len(), Block::all().count() + 1);
+        assert_eq!(registry.block(marble), None);
+    }
+
+    #[test]
+    fn meshing_uses_the_registry_textures() {
+        use cgmath::{Vector2, Vector3};
+
+        use crate::chunk::{Chunk, ChunkMeshData};
+
+        let dir = definitions_dir("retextured");
+        std::fs::write(dir.join("stone.ron"), "BlockDef(name: \"stone\", textures: All((64, 16)))").unwrap();
+        let registry = BlockRegistry::load_dir(&dir).unwrap();
+
+        let mut chunk = Chunk::new(Vector2::new(0, 0));
+        chunk.set_block(Vector3::new(0, 0, 0), Block::new_stone()).unwrap();
+        let mesh = ChunkMeshData::from_chunk(&chunk, [None, None, None, None], &registry.texture_table());
+
+        assert!(!mesh.vertices.is_empty());
+        for vertex in &mesh.vertices {
+            assert_eq!(vertex.unpack().layer, TextureLayers::atlas(64, 16));
+        }
+    }
+
+    #[test]
+    fn retired_ids_have_no_definition() {
+        let registry = BlockRegistry::load().unwrap();
+
+        assert_eq!(registry.len(), Block::all().count());
+        for id in RETIRED_BLOCK_IDS {
+            assert!(registry.iter().all(|(registered, _)| registered.0 != id));
+            assert_eq!(registry.block(BlockId(id)), None);
+        }
+    }
+}
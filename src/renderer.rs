@@ -1,30 +1,49 @@
 use std::collections::vec_deque::VecDeque;
 use std::iter;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 use std::time::{Duration, Instant};
 
 use bytemuck::{Pod, Zeroable};
-use cgmath::{Matrix4, SquareMatrix, Vector4};
+use anyhow::{bail, Context};
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3, Vector4};
+use encase::ShaderType;
+use wgpu::util::DeviceExt;
 use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
 use crate::camera;
+use crate::chunk::CHUNK_WIDTH;
 use crate::texture::Texture;
+use crate::world;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct CameraUniform {
     pub view_position: Vector4<f32>,
     pub view_proj: Matrix4<f32>,
+    /// Seconds since the game started, for texture animations
+    pub time: f32,
+    _padding: [f32; 3],
 }
 
 unsafe impl Pod for CameraUniform {}
 unsafe impl Zeroable for CameraUniform {}
 
+impl Default for CameraUniform {
+    fn default() -> Self {
+        CameraUniform::new()
+    }
+}
+
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
             view_position: Vector4::new(0.0, 0.0, 0.0, 0.0),
             view_proj: Matrix4::identity(),
+            time: 0.0,
+            _padding: [0.0; 3],
         }
     }
 
@@ -34,56 +53,350 @@ impl CameraUniform {
     }
 }
 
+/// A single directional light, like the sun, shading faces by how directly they face it
+#[derive(ShaderType, Debug, Copy, Clone, PartialEq)]
+pub struct LightUniform {
+    /// Normalized direction pointing from the world towards the light
+    pub direction: Vector3<f32>,
+    pub color: Vector3<f32>,
+    /// Brightness of faces pointing away from the light
+    pub ambient: f32,
+}
+
+impl LightUniform {
+    pub fn new(direction: Vector3<f32>, color: Vector3<f32>, ambient: f32) -> Self {
+        Self {
+            direction: direction.normalize(),
+            color,
+            ambient,
+        }
+    }
+}
+
+impl Default for LightUniform {
+    fn default() -> Self {
+        Self::new(Vector3::new(0.3, 1.0, 0.5), Vector3::new(0.6, 0.6, 0.6), 0.4)
+    }
+}
+
+/// Linear distance fog, fading everything between `start` and `end` units away from the camera
+/// into `color`
+#[derive(ShaderType, Debug, Copy, Clone, PartialEq)]
+pub struct FogUniform {
+    pub color: Vector3<f32>,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl FogUniform {
+    /// Returns `None` unless `start` is closer than `end`
+    pub fn new(color: Vector3<f32>, start: f32, end: f32) -> Option<Self> {
+        (start < end).then_some(Self { color, start, end })
+    }
+
+    /// Fog that hides everything right before it's cut off by the camera's far plane
+    pub fn for_far_plane(color: Vector3<f32>, far: f32) -> Self {
+        Self {
+            color,
+            start: far * 0.6,
+            end: far,
+        }
+    }
+}
+
+impl Default for FogUniform {
+    fn default() -> Self {
+        Self::for_far_plane(Vector3::new(0.1, 0.2, 0.3), 100.0)
+    }
+}
+
+/// How far around the camera the world is loaded and drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderSettings {
+    /// How many chunks are loaded in every direction from the camera's chunk
+    render_distance: u32,
+}
+
+impl RenderSettings {
+    pub const MIN_RENDER_DISTANCE: u32 = 1;
+    pub const MAX_RENDER_DISTANCE: u32 = 16;
+
+    /// Clamps `render_distance` between `MIN_RENDER_DISTANCE` and `MAX_RENDER_DISTANCE`
+    pub fn new(render_distance: u32) -> Self {
+        Self {
+            render_distance: render_distance.clamp(RenderSettings::MIN_RENDER_DISTANCE, RenderSettings::MAX_RENDER_DISTANCE),
+        }
+    }
+
+    pub fn render_distance(&self) -> u32 {
+        self.render_distance
+    }
+
+    /// The most chunks that can be loaded at once at this render distance, along with the ones
+    /// that aren't unloaded yet, see `World::update_streaming`
+    pub fn max_loaded_chunks(&self) -> usize {
+        ((self.render_distance + world::UNLOAD_MARGIN) as usize * 2 + 1).pow(2)
+    }
+
+    /// How far away the far plane should be to see the edge of the loaded chunks, but not past
+    /// it. Chunks are loaded in a square, so the corners get cut off.
+    pub fn far_plane(&self) -> f32 {
+        (self.render_distance + 1) as f32 * CHUNK_WIDTH as f32
+    }
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self::new(4)
+    }
+}
+
+/// Device features to ask the adapter for. Missing `required` features are an error, `optional`
+/// ones are only enabled if the adapter has them, so check `Device::features` before using them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestedFeatures {
+    pub required: wgpu::Features,
+    pub optional: wgpu::Features,
+}
+
+impl RequestedFeatures {
+    /// The features to actually request from an adapter supporting `available`, or the required
+    /// ones it's missing
+    pub fn negotiate(&self, available: wgpu::Features) -> Result<wgpu::Features, wgpu::Features> {
+        let missing = self.required - available;
+        if !missing.is_empty() {
+            return Err(missing);
+        }
+
+        Ok(self.required | (self.optional & available))
+    }
+}
+
+impl Default for RequestedFeatures {
+    fn default() -> Self {
+        Self {
+            required: wgpu::Features::empty(),
+            // The wireframe view
+            optional: wgpu::Features::POLYGON_MODE_LINE,
+        }
+    }
+}
+
 pub struct Renderer {
-    pub surface: wgpu::Surface,
+    /// The window's surface, or `None` for headless renderers
+    pub surface: Option<wgpu::Surface>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
     pub size: PhysicalSize<u32>,
+    /// What the adapter can do beyond the WebGPU baseline, like compute shaders on WebGL
+    pub downlevel_flags: wgpu::DownlevelFlags,
 
     pub depth_texture: Texture,
+    /// The number of samples per pixel used for MSAA, 1 when it's disabled
+    pub sample_count: u32,
+    /// The multisampled color target, only present when `sample_count` is above 1
+    pub msaa_texture: Option<Texture>,
+    /// What headless renderers draw into instead of a surface. Windowed renderers create one to
+    /// draw screenshots into, since their surface's frames can't be copied.
+    offscreen_target: Option<Texture>,
+    /// Where to save a screenshot of the next frame, see `capture_next_frame`
+    pending_capture: Option<PathBuf>,
+    /// What the frame is cleared to before anything is drawn, so it's also the sky color
+    clear_color: wgpu::Color,
+
+    light_uniform: LightUniform,
+    light_buffer: wgpu::Buffer,
+    pub light_bind_group_layout: wgpu::BindGroupLayout,
+    light_bind_group: wgpu::BindGroup,
+
+    fog_uniform: FogUniform,
+    fog_buffer: wgpu::Buffer,
+    pub fog_bind_group_layout: wgpu::BindGroupLayout,
+    fog_bind_group: wgpu::BindGroup,
 
     pub fps_counter: FPSCounter,
 }
 
 impl Renderer {
-    pub fn new(window: &Window) -> Self {
+    /// Creates the renderer for the window. `sample_count` is the requested MSAA sample count
+    /// (1, 2, 4 or 8), which falls back to one the adapter supports, see `supported_sample_count`.
+    /// wgpu 0.13 can't tell which counts above 1 an adapter supports, so 2x and 8x currently
+    /// end up as 4x.
+    /// Fails if there's no adapter or it's missing one of the required `features`.
+    ///
+    /// This blocks until the device is ready, which is what `State` uses. Use `new_async` where
+    /// blocking isn't possible, like on the web.
+    pub fn new(window: &Window, sample_count: u32, features: RequestedFeatures) -> anyhow::Result<Self> {
+        pollster::block_on(Renderer::new_async(window, sample_count, features))
+    }
+
+    /// Like `new`, but waits for the adapter and device asynchronously
+    pub async fn new_async(window: &Window, sample_count: u32, features: RequestedFeatures) -> anyhow::Result<Self> {
         let size = window.inner_size();
 
         // The instance is a handle to our GPU
         // BackendBit::PRIMARY => Vulkan + Metal + DX12 + Browser WebGPU
         let instance = wgpu::Instance::new(wgpu::Backends::all());
         let surface = unsafe { instance.create_surface(window) };
-        let adapter = pollster::block_on(instance
+        let adapter = Renderer::request_adapter(&instance, Some(&surface)).await?;
+        let (device, queue) = Renderer::request_device(&adapter, features).await?;
+
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface.get_supported_formats(&adapter)[0],
+            width: size.width,
+            height: size.height,
+            present_mode: wgpu::PresentMode::Fifo,
+        };
+        surface.configure(&device, &config);
+
+        Ok(Renderer::from_parts(Some(surface), &adapter, device, queue, config, sample_count))
+    }
+
+    /// Creates a renderer without a window, which draws into a texture of its own instead of a
+    /// surface, for screenshots and tests. Frames are read back with `read_pixels`. MSAA is off
+    /// and only the default optional features are requested.
+    pub fn new_headless(width: u32, height: u32, format: wgpu::TextureFormat) -> anyhow::Result<Self> {
+        pollster::block_on(async {
+            let instance = wgpu::Instance::new(wgpu::Backends::all());
+            let adapter = Renderer::request_adapter(&instance, None).await?;
+            let (device, queue) = Renderer::request_device(&adapter, RequestedFeatures::default()).await?;
+
+            let config = wgpu::SurfaceConfiguration {
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                format,
+                width,
+                height,
+                present_mode: wgpu::PresentMode::Fifo,
+            };
+
+            Ok(Renderer::from_parts(None, &adapter, device, queue, config, 1))
+        })
+    }
+
+    async fn request_adapter(instance: &wgpu::Instance, surface: Option<&wgpu::Surface>) -> anyhow::Result<wgpu::Adapter> {
+        instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
+                compatible_surface: surface,
                 force_fallback_adapter: false,
-            }))
-            .unwrap();
+            })
+            .await
+            .ok_or_else(|| anyhow::anyhow!("no compatible graphics adapter found"))
+    }
+
+    async fn request_device(adapter: &wgpu::Adapter, features: RequestedFeatures) -> anyhow::Result<(wgpu::Device, wgpu::Queue)> {
+        let features = features
+            .negotiate(adapter.features())
+            .map_err(|missing| anyhow::anyhow!("the graphics adapter doesn't support {:?}", missing))?;
+        log::info!("requesting device features {:?}", features);
+
+        // Every chunk's uniforms live in one buffer bound at dynamic offsets, so take whatever the
+        // adapter allows for those instead of the conservative defaults
+        let adapter_limits = adapter.limits();
+        let limits = wgpu::Limits {
+            max_uniform_buffer_binding_size: adapter_limits.max_uniform_buffer_binding_size,
+            max_dynamic_uniform_buffers_per_pipeline_layout: adapter_limits.max_dynamic_uniform_buffers_per_pipeline_layout,
+            min_uniform_buffer_offset_alignment: adapter_limits.min_uniform_buffer_offset_alignment,
+            ..wgpu::Limits::default()
+        };
 
-        let (device, queue) = pollster::block_on(adapter
+        let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
+                    features,
+                    limits,
                 },
                 // Some(&std::path::Path::new("trace")), // Trace path
                 None,
-            ))
-            .unwrap();
+            )
+            .await?;
 
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_supported_formats(&adapter)[0],
-            width: size.width,
-            height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
-        };
-        surface.configure(&device, &config);
+        Ok((device, queue))
+    }
 
-        let depth_texture = Texture::create_depth_texture(&device, &config, "depth_texture");
+    /// Sets up everything that doesn't depend on where the frames end up. Headless renderers
+    /// (without a `surface`) get their render target here.
+    fn from_parts(
+        surface: Option<wgpu::Surface>,
+        adapter: &wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: wgpu::SurfaceConfiguration,
+        sample_count: u32,
+    ) -> Self {
+        let size = PhysicalSize::new(config.width, config.height);
+
+        let sample_count = Renderer::supported_sample_count(adapter, config.format, sample_count);
+
+        let depth_texture = Texture::create_depth_texture(&device, &config, sample_count, "depth_texture");
+        let msaa_texture = (sample_count > 1)
+            .then(|| Texture::create_msaa_texture(&device, &config, sample_count, "msaa_texture"));
+        let offscreen_target = surface
+            .is_none()
+            .then(|| Texture::create_render_target(&device, &config, "offscreen_target"));
+
+        let light_uniform = LightUniform::default();
+        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Buffer"),
+            contents: &uniform_bytes(&light_uniform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let light_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(LightUniform::min_size()),
+                },
+                count: None,
+            }],
+            label: Some("light bind group layout"),
+        });
+
+        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &light_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: light_buffer.as_entire_binding(),
+            }],
+            label: Some("light bind group"),
+        });
+
+        let fog_uniform = FogUniform::default();
+        let fog_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Fog Buffer"),
+            contents: &uniform_bytes(&fog_uniform),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let fog_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: Some(FogUniform::min_size()),
+                },
+                count: None,
+            }],
+            label: Some("fog bind group layout"),
+        });
+
+        let fog_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &fog_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: fog_buffer.as_entire_binding(),
+            }],
+            label: Some("fog bind group"),
+        });
 
         let fps_counter = FPSCounter::new();
 
@@ -93,36 +406,292 @@ impl Renderer {
             queue,
             config,
             size,
+            downlevel_flags: adapter.get_downlevel_capabilities().flags,
 
             depth_texture,
+            sample_count,
+            msaa_texture,
+            offscreen_target,
+            pending_capture: None,
+            clear_color: wgpu::Color {
+                r: 0.1,
+                g: 0.2,
+                b: 0.3,
+                a: 1.0,
+            },
+
+            light_uniform,
+            light_buffer,
+            light_bind_group_layout,
+            light_bind_group,
+
+            fog_uniform,
+            fog_buffer,
+            fog_bind_group_layout,
+            fog_bind_group,
 
             fps_counter,
         }
     }
 
+    pub fn clear_color(&self) -> wgpu::Color {
+        self.clear_color
+    }
+
+    /// Changes the color the frame is cleared to, taking effect from the next frame
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = color;
+    }
+
+    pub fn light(&self) -> &LightUniform {
+        &self.light_uniform
+    }
+
+    /// Replaces the directional light, taking effect from the next frame
+    pub fn set_light(&mut self, light: LightUniform) {
+        self.light_uniform = light;
+        self.queue.write_buffer(&self.light_buffer, 0, &uniform_bytes(&self.light_uniform));
+    }
+
+    /// Points the directional light in a new direction (towards the light), e.g. to follow the sun
+    pub fn set_light_direction(&mut self, direction: Vector3<f32>) {
+        self.set_light(LightUniform {
+            direction: direction.normalize(),
+            ..self.light_uniform
+        });
+    }
+
+    pub fn fog(&self) -> &FogUniform {
+        &self.fog_uniform
+    }
+
+    /// Replaces the fog, taking effect from the next frame
+    pub fn set_fog(&mut self, fog: FogUniform) {
+        self.fog_uniform = fog;
+        self.queue.write_buffer(&self.fog_buffer, 0, &uniform_bytes(&self.fog_uniform));
+    }
+
+    /// Changes the fog color, e.g. to follow the sky over a day
+    pub fn set_fog_color(&mut self, color: Vector3<f32>) {
+        self.set_fog(FogUniform {
+            color,
+            ..self.fog_uniform
+        });
+    }
+
+    /// Changes where the fog starts and ends, ignoring ranges where `start` isn't closer than `end`
+    pub fn set_fog_range(&mut self, start: f32, end: f32) {
+        match FogUniform::new(self.fog_uniform.color, start, end) {
+            Some(fog) => self.set_fog(fog),
+            None => log::warn!("Ignoring fog range {}..{}, it has to start before it ends", start, end),
+        }
+    }
+
+    /// Picks the closest sample count to `requested` that the adapter can render with. wgpu only
+    /// reports whether a format can be multisampled at all, and WebGPU only guarantees 4 samples,
+    /// so any request above 1 becomes 4 if both the color and depth formats allow it and 1 otherwise.
+    pub fn supported_sample_count(adapter: &wgpu::Adapter, color_format: wgpu::TextureFormat, requested: u32) -> u32 {
+        if requested <= 1 {
+            return 1;
+        }
+
+        let multisampled = [color_format, Texture::DEPTH_FORMAT].iter().all(|format| {
+            adapter
+                .get_texture_format_features(*format)
+                .flags
+                .contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE)
+        });
+
+        let sample_count = Renderer::fallback_sample_count(requested, multisampled);
+        if sample_count != requested {
+            log::warn!("{}x MSAA isn't supported by the adapter, using {}x instead", requested, sample_count);
+        }
+
+        sample_count
+    }
+
+    /// The sample count `supported_sample_count` falls back to for a request, given whether the
+    /// formats can be multisampled at all
+    fn fallback_sample_count(requested: u32, multisampled: bool) -> u32 {
+        match requested {
+            0 | 1 => 1,
+            _ if multisampled => 4,
+            _ => 1,
+        }
+    }
+
+    /// Reconfigures the surface and recreates the size dependent render targets
+    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.size = new_size;
+
+        self.config.width = new_size.width;
+        self.config.height = new_size.height;
+
+        match &self.surface {
+            Some(surface) => {
+                surface.configure(&self.device, &self.config);
+                // Made again at the new size for the next screenshot
+                self.offscreen_target = None;
+            }
+            None => {
+                self.offscreen_target = Some(Texture::create_render_target(&self.device, &self.config, "offscreen_target"));
+            }
+        }
+
+        self.depth_texture = Texture::create_depth_texture(
+            &self.device,
+            &self.config,
+            self.sample_count,
+            "depth_texture",
+        );
+        self.msaa_texture = (self.sample_count > 1).then(|| {
+            Texture::create_msaa_texture(&self.device, &self.config, self.sample_count, "msaa_texture")
+        });
+    }
+
     /// Renders the given objects using the supplied render pass, objects must have same uniform layout (subject to change)
-    pub fn render<T>(
+    /// `extras` are drawn in the same pass afterwards, each with its own pipeline. `overlay` is
+    /// called with the frame's view once everything is drawn, for things like the GUI that are
+    /// drawn on top.
+    pub fn render<T, F>(
         &mut self,
         render_pipeline: &wgpu::RenderPipeline,
         camera_bind_group: &wgpu::BindGroup,
         objects: &[(&T, &wgpu::BindGroup)],
+        extras: &[(&wgpu::RenderPipeline, &dyn Draw, &wgpu::BindGroup)],
+        overlay: F,
     ) -> Result<(), wgpu::SurfaceError>
-        where T: Draw
+        where T: Draw, F: FnOnce(&wgpu::Device, &wgpu::Queue, &wgpu::TextureView)
     {
-        let output = self.surface.get_current_texture()?;
+        let (output, view) = match &self.surface {
+            Some(surface) => {
+                let output = surface.get_current_texture()?;
+                let view = output
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+
+                (Some(output), view)
+            }
+            None => {
+                let target = self.offscreen_target.as_ref().expect("headless renderers always have a target");
+                (None, target.texture.create_view(&wgpu::TextureViewDescriptor::default()))
+            }
+        };
 
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.render_objects(render_pipeline, camera_bind_group, objects, extras, &view)?;
 
-        self.render_objects(render_pipeline, camera_bind_group, objects, &view)?;
+        overlay(&self.device, &self.queue, &view);
 
-        output.present();
+        if let Some(output) = output {
+            output.present();
+        }
+
+        if let Some(path) = self.pending_capture.take() {
+            // The frame is already in the target for headless renderers. Windowed ones draw it
+            // again, without the overlay.
+            if self.surface.is_some() {
+                let target = self.offscreen_target
+                    .get_or_insert_with(|| Texture::create_render_target(&self.device, &self.config, "offscreen_target"));
+                let view = target.texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+                self.render_objects(render_pipeline, camera_bind_group, objects, extras, &view)?;
+            }
+
+            match self.capture_to_png(&path) {
+                Ok(()) => log::info!("saved a screenshot to {}", path.display()),
+                Err(e) => log::warn!("Unable to save a screenshot to {}: {:?}", path.display(), e),
+            }
+        }
 
         Ok(())
     }
 
-    pub fn render_objects<T: Draw>(&mut self, render_pipeline: &wgpu::RenderPipeline, camera_bind_group: &wgpu::BindGroup, objects: &[(&T, &wgpu::BindGroup)], view: &wgpu::TextureView) -> Result<(), wgpu::SurfaceError> {
+    /// Saves a screenshot of the next frame rendered to `path` as a PNG
+    pub fn capture_next_frame(&mut self, path: PathBuf) {
+        self.pending_capture = Some(path);
+    }
+
+    /// Saves the frame in the offscreen target as a PNG, see `read_pixels`. The alpha channel is
+    /// dropped, since it doesn't mean anything once the frame is on screen.
+    pub fn capture_to_png(&self, path: &Path) -> anyhow::Result<()> {
+        let mut pixels = self.read_pixels()?;
+
+        match self.config.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => {}
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+                pixels.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+            }
+            format => bail!("frames in {:?} can't be saved as a PNG", format),
+        }
+
+        let image = image::RgbaImage::from_raw(self.config.width, self.config.height, pixels)
+            .context("the frame is smaller than its size")?;
+        image::DynamicImage::ImageRgba8(image).to_rgb8().save(path)?;
+
+        Ok(())
+    }
+
+    /// Copies the frame in the offscreen target back from the GPU, as rows of pixels in the
+    /// target's format from the top down, without any padding. That's the last frame for
+    /// headless renderers, and the last screenshot for windowed ones, whose frames are gone once
+    /// they're presented.
+    pub fn read_pixels(&self) -> anyhow::Result<Vec<u8>> {
+        let target = self.offscreen_target
+            .as_ref()
+            .context("there's no offscreen frame to read back")?;
+
+        // Rows in the buffer have to start at multiples of 256 bytes
+        let row_bytes = self.config.width * self.config.format.describe().block_size as u32;
+        let padded_row_bytes = wgpu::util::align_to(row_bytes, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("read pixels buffer"),
+            size: (padded_row_bytes * self.config.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Read Pixels Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            target.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(padded_row_bytes),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: self.config.width,
+                height: self.config.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()?.context("unable to map the read pixels buffer")?;
+
+        let pixels = slice
+            .get_mapped_range()
+            .chunks(padded_row_bytes as usize)
+            .flat_map(|row| &row[..row_bytes as usize])
+            .copied()
+            .collect();
+        buffer.unmap();
+
+        Ok(pixels)
+    }
+
+    pub fn render_objects<T: Draw>(&mut self, render_pipeline: &wgpu::RenderPipeline, camera_bind_group: &wgpu::BindGroup, objects: &[(&T, &wgpu::BindGroup)], extras: &[(&wgpu::RenderPipeline, &dyn Draw, &wgpu::BindGroup)], view: &wgpu::TextureView) -> Result<(), wgpu::SurfaceError> {
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -133,15 +702,10 @@ impl Renderer {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
+                    view: self.msaa_texture.as_ref().map_or(view, |msaa| &msaa.view),
+                    resolve_target: self.msaa_texture.as_ref().map(|_| view),
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
+                        load: wgpu::LoadOp::Clear(self.clear_color),
                         store: true,
                     },
                 })],
@@ -155,10 +719,17 @@ impl Renderer {
                 }),
             });
             render_pass.set_pipeline(render_pipeline);
+            render_pass.set_bind_group(2, &self.light_bind_group, &[]);
+            render_pass.set_bind_group(3, &self.fog_bind_group, &[]);
 
             for (object, uniforms) in objects {
                 object.draw(&mut render_pass, camera_bind_group, uniforms);
             }
+
+            for (pipeline, object, uniforms) in extras {
+                render_pass.set_pipeline(pipeline);
+                object.draw(&mut render_pass, camera_bind_group, uniforms);
+            }
         }
 
         self.queue.submit(iter::once(encoder.finish()));
@@ -167,6 +738,12 @@ impl Renderer {
     }
 }
 
+fn uniform_bytes<T: ShaderType + encase::internal::WriteInto>(value: &T) -> Vec<u8> {
+    let mut buffer = encase::UniformBuffer::new(Vec::new());
+    buffer.write(value).expect("Unable to write uniform");
+    buffer.into_inner()
+}
+
 pub trait Draw {
     fn draw<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>, camera_bind_group: &'a wgpu::BindGroup, uniforms: &'a wgpu::BindGroup);
 }
@@ -174,32 +751,82 @@ pub trait Draw {
 #[derive(Debug)]
 pub struct FPSCounter {
     pub last_second_frames: VecDeque<Instant>,
+    /// How long each frame of the last second took, since the frame before it, along with when
+    /// it ended
+    frame_times: VecDeque<(Instant, Duration)>,
+}
+
+impl Default for FPSCounter {
+    fn default() -> Self {
+        FPSCounter::new()
+    }
 }
 
 impl FPSCounter {
     pub fn new() -> FPSCounter {
         FPSCounter {
             last_second_frames: VecDeque::with_capacity(128),
+            frame_times: VecDeque::with_capacity(128),
         }
     }
 
     pub fn tick(&mut self) -> usize {
-        let now = Instant::now();
+        self.tick_at(Instant::now())
+    }
+
+    /// Like `tick`, for a frame that ended at `now`
+    pub fn tick_at(&mut self, now: Instant) -> usize {
         let a_second_ago = now - Duration::from_secs(1);
 
-        while self
-            .last_second_frames
-            .front()
-            .map_or(false, |t| *t < a_second_ago)
-        {
+        if let Some(last_frame) = self.last_second_frames.back() {
+            self.frame_times.push_back((now, now - *last_frame));
+        }
+
+        while self.last_second_frames.front().is_some_and(|t| *t < a_second_ago) {
             self.last_second_frames.pop_front();
         }
+        while self.frame_times.front().is_some_and(|(t, _)| *t < a_second_ago) {
+            self.frame_times.pop_front();
+        }
 
         self.last_second_frames.push_back(now);
         self.last_second_frames.len()
     }
+
+    /// The average time a frame took over the last second, `None` until there have been two
+    pub fn average_frame_time(&self) -> Option<Duration> {
+        if self.frame_times.is_empty() {
+            return None;
+        }
+
+        Some(self.frame_times.iter().map(|(_, time)| *time).sum::<Duration>() / self.frame_times.len() as u32)
+    }
+
+    pub fn min_frame_time(&self) -> Option<Duration> {
+        self.frame_times.iter().map(|(_, time)| *time).min()
+    }
+
+    pub fn max_frame_time(&self) -> Option<Duration> {
+        self.frame_times.iter().map(|(_, time)| *time).max()
+    }
+
+    /// The time `p` percent of the frames in the last second took at most, with `p` clamped
+    /// between 0 and 100. `percentile(99.0)` is the "1% low" frame time.
+    pub fn percentile(&self, p: f32) -> Option<Duration> {
+        if self.frame_times.is_empty() {
+            return None;
+        }
+
+        let mut times = self.frame_times.iter().map(|(_, time)| *time).collect::<Vec<_>>();
+        times.sort_unstable();
+
+        // The nearest rank, so the result is always one of the frame times
+        let rank = (p.clamp(0.0, 100.0) / 100.0 * times.len() as f32).ceil() as usize;
+        Some(times[rank.saturating_sub(1)])
+    }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn create_render_pipeline(
     device: &wgpu::Device,
     layout: &wgpu::PipelineLayout,
@@ -207,6 +834,9 @@ pub(crate) fn create_render_pipeline(
     depth_format: Option<wgpu::TextureFormat>,
     vertex_layouts: &[wgpu::VertexBufferLayout],
     shader: wgpu::ShaderModuleDescriptor,
+    sample_count: u32,
+    topology: wgpu::PrimitiveTopology,
+    polygon_mode: wgpu::PolygonMode,
 ) -> wgpu::RenderPipeline {
     let shader = device.create_shader_module(shader);
 
@@ -232,15 +862,14 @@ pub(crate) fn create_render_pipeline(
             // targets: &[Some(color_format.into())],
         }),
         primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
+            topology,
             strip_index_format: None,
             front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            // cull_mode: None,
-            polygon_mode: wgpu::PolygonMode::Fill,
+            // Lines have no faces to cull
+            cull_mode: (topology == wgpu::PrimitiveTopology::TriangleList).then_some(wgpu::Face::Back),
+            polygon_mode,
             unclipped_depth: false,
             conservative: false,
-            ..Default::default()
         },
         depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
             format,
@@ -249,12 +878,133 @@ pub(crate) fn create_render_pipeline(
             stencil: wgpu::StencilState::default(),
             bias: wgpu::DepthBiasState::default(),
         }),
-        // multisample: wgpu::MultisampleState {
-        //     count: 1,
-        //     mask: !0,
-        //     alpha_to_coverage_enabled: false,
-        // },
-        multisample: wgpu::MultisampleState::default(),
+        multisample: wgpu::MultisampleState {
+            count: sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
         multiview: None,
     })
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A small headless renderer for tests that need a device. Those tests are `#[ignore]`d, so
+    /// they only run where there's an adapter, with `cargo test -- --ignored`.
+    pub(crate) fn headless() -> Renderer {
+        Renderer::new_headless(64, 64, wgpu::TextureFormat::Rgba8UnormSrgb).expect("no adapter to run GPU tests on")
+    }
+
+    #[test]
+    fn sample_counts_fall_back_to_4x_or_none() {
+        for requested in [0, 1] {
+            assert_eq!(Renderer::fallback_sample_count(requested, true), 1);
+        }
+        for requested in [2, 4, 8, 16] {
+            assert_eq!(Renderer::fallback_sample_count(requested, true), 4);
+            assert_eq!(Renderer::fallback_sample_count(requested, false), 1);
+        }
+    }
+
+    /// Reads the `f32` at `offset` in an encoded uniform
+    fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+        f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn light_uniform_matches_the_std140_layout() {
+        let light = LightUniform::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.25, 0.5, 0.75), 0.4);
+        let bytes = uniform_bytes(&light);
+
+        // Both vec3s are 16 byte aligned, and ambient fills the padding after color
+        assert_eq!(LightUniform::min_size().get(), 32);
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(f32_at(&bytes, 4), 1.0);
+        assert_eq!(f32_at(&bytes, 16), 0.25);
+        assert_eq!(f32_at(&bytes, 28), 0.4);
+    }
+
+    #[test]
+    fn fog_uniform_matches_the_std140_layout() {
+        let fog = FogUniform::new(Vector3::new(0.5, 0.6, 0.7), 60.0, 100.0).unwrap();
+        let bytes = uniform_bytes(&fog);
+
+        // start packs into the padding after the vec3, end starts the next 16 byte row
+        assert_eq!(FogUniform::min_size().get(), 32);
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(f32_at(&bytes, 8), 0.7);
+        assert_eq!(f32_at(&bytes, 12), 60.0);
+        assert_eq!(f32_at(&bytes, 16), 100.0);
+    }
+
+    #[test]
+    fn fog_has_to_start_before_it_ends() {
+        assert!(FogUniform::new(Vector3::new(0.0, 0.0, 0.0), 100.0, 60.0).is_none());
+        assert!(FogUniform::new(Vector3::new(0.0, 0.0, 0.0), 60.0, 60.0).is_none());
+
+        let fog = FogUniform::for_far_plane(Vector3::new(0.0, 0.0, 0.0), 100.0);
+        assert!(fog.start < fog.end);
+        assert_eq!(fog.end, 100.0);
+    }
+
+    #[test]
+    fn frame_time_percentiles_use_the_nearest_rank() {
+        let mut counter = FPSCounter::new();
+        let start = Instant::now();
+        assert_eq!(counter.percentile(50.0), None);
+
+        // Nine 10ms frames and a single 50ms hitch
+        let mut now = start;
+        counter.tick_at(now);
+        for ms in [10, 10, 10, 10, 50, 10, 10, 10, 10, 10] {
+            now += Duration::from_millis(ms);
+            counter.tick_at(now);
+        }
+
+        assert_eq!(counter.percentile(50.0), Some(Duration::from_millis(10)));
+        assert_eq!(counter.percentile(90.0), Some(Duration::from_millis(10)));
+        assert_eq!(counter.percentile(99.0), Some(Duration::from_millis(50)));
+        assert_eq!(counter.percentile(150.0), Some(Duration::from_millis(50)));
+        assert_eq!(counter.percentile(-5.0), Some(Duration::from_millis(10)));
+
+        // A second later the hitch has dropped out
+        counter.tick_at(now + Duration::from_millis(1010));
+        assert_eq!(counter.percentile(99.0), Some(Duration::from_millis(1010)));
+        assert_eq!(counter.percentile(0.0), Some(Duration::from_millis(1010)));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn screenshots_keep_the_frame_size_when_rows_are_padded() {
+        // 100 pixels of 4 bytes don't fill a whole number of 256 byte rows
+        let mut renderer = Renderer::new_headless(100, 30, wgpu::TextureFormat::Rgba8UnormSrgb).unwrap();
+        let red = wgpu::Color { r: 1.0, g: 0.0, b: 0.0, a: 1.0 };
+        renderer.set_clear_color(red);
+
+        let view = renderer.offscreen_target.as_ref().unwrap().texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = renderer.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(renderer.clear_color()),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        renderer.queue.submit(iter::once(encoder.finish()));
+
+        let path = std::env::temp_dir().join(format!("wgpu_voxel_game-screenshot-{}.png", std::process::id()));
+        renderer.capture_to_png(&path).unwrap();
+        let image = image::open(&path).unwrap().to_rgb8();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(image.dimensions(), (100, 30));
+        assert!(image.pixels().all(|pixel| pixel.0 == [255, 0, 0]));
+    }
+}
@@ -0,0 +1,474 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use cgmath::Vector2;
+use hashbrown::HashMap;
+use ndarray::Array3;
+use serde::{Deserialize, Serialize};
+
+use crate::block::Block;
+use crate::camera::CameraState;
+use crate::chunk::{CHUNK_DIMS, CHUNK_HEIGHT, CHUNK_SIZE, CHUNK_WIDTH, Chunk, MIN_Y};
+
+/// The first bytes of the metadata file
+pub const SAVE_MAGIC: [u8; 4] = *b"VXLW";
+/// The first bytes of every region file
+pub const REGION_MAGIC: [u8; 4] = *b"VXLR";
+/// The version of the format written by `write`. Bump it whenever the format changes, files with
+/// any other version are refused by `read`.
+pub const SAVE_VERSION: u8 = 2;
+
+/// The file in a save folder with the `SaveHeader`
+pub const METADATA_FILE: &str = "world.dat";
+/// The folder in a save folder with the region files
+pub const REGIONS_FOLDER: &str = "regions";
+const REGION_EXTENSION: &str = "vxr";
+/// How many chunks wide and deep each region file is
+pub const REGION_SIZE: i32 = 32;
+
+/// Why a save couldn't be written or read
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    /// The metadata file doesn't start with `SAVE_MAGIC`
+    NotASave,
+    /// The file was written with another version of the format
+    UnsupportedVersion(u8),
+    /// The file has the right version but its contents don't make sense
+    Corrupt(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "{}", e),
+            SaveError::NotASave => write!(f, "not a save file"),
+            SaveError::UnsupportedVersion(version) => {
+                write!(f, "save file version {} isn't supported, expected version {}", version, SAVE_VERSION)
+            }
+            SaveError::Corrupt(reason) => write!(f, "save file is corrupt: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for SaveError {
+    fn from(e: bincode::Error) -> Self {
+        match *e {
+            bincode::ErrorKind::Io(e) => SaveError::Io(e),
+            e => SaveError::Corrupt(e.to_string()),
+        }
+    }
+}
+
+/// Everything in a save besides the chunks themselves, which is kept in the metadata file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveHeader {
+    /// The seed of the terrain generator that made the world
+    pub seed: u64,
+    /// The world's render origin, see `World::origin`
+    pub origin: [i32; 2],
+    pub camera: CameraState,
+}
+
+/// A chunk's blocks and light, run length encoded in the order of `Chunk::iter_all_blocks`, since
+/// most of a chunk is long runs of air or stone
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedChunk {
+    blocks: Vec<(u32, Block)>,
+    light: Vec<(u32, u8)>,
+}
+
+impl SavedChunk {
+    pub fn from_chunk(chunk: &Chunk) -> Self {
+        Self {
+            blocks: run_length_encode(chunk.iter_all_blocks().map(|(_, block)| *block)),
+            light: run_length_encode(chunk.light.iter().copied()),
+        }
+    }
+
+    /// The chunk these blocks belong to, at `location`
+    pub fn to_chunk(&self, location: Vector2<i32>) -> Result<Chunk, SaveError> {
+        let blocks = run_length_decode(&self.blocks)
+            .ok_or_else(|| SaveError::Corrupt(format!("chunk {:?} doesn't have {} blocks", location, CHUNK_SIZE)))?;
+        let light = run_length_decode(&self.light)
+            .ok_or_else(|| SaveError::Corrupt(format!("chunk {:?} doesn't have {} light levels", location, CHUNK_SIZE)))?;
+
+        let mut chunk = Chunk::generate(location, |position| {
+            let y = (position.y - MIN_Y) as usize;
+            blocks[position.x as usize + CHUNK_WIDTH * (y + CHUNK_HEIGHT * position.z as usize)]
+        });
+        chunk.light = Array3::from_shape_vec(CHUNK_DIMS, light).unwrap();
+
+        Ok(chunk)
+    }
+}
+
+/// Saved chunks along with their locations
+pub type SavedChunks = Vec<(Vector2<i32>, SavedChunk)>;
+
+fn run_length_encode<T: PartialEq + Copy>(values: impl Iterator<Item = T>) -> Vec<(u32, T)> {
+    let mut runs: Vec<(u32, T)> = Vec::new();
+
+    for value in values {
+        match runs.last_mut() {
+            Some((count, last)) if *last == value => *count += 1,
+            _ => runs.push((1, value)),
+        }
+    }
+
+    runs
+}
+
+/// The values of a whole chunk, or `None` if the runs don't add up to one
+fn run_length_decode<T: Copy>(runs: &[(u32, T)]) -> Option<Vec<T>> {
+    let total = runs.iter().try_fold(0usize, |total, (count, _)| total.checked_add(*count as usize))?;
+    if total != CHUNK_SIZE {
+        return None;
+    }
+
+    Some(runs.iter().flat_map(|(count, value)| std::iter::repeat_n(*value, *count as usize)).collect())
+}
+
+/// Writes the world to the `dir` folder: the header to `METADATA_FILE`, and the chunks to a region
+/// file for each `REGION_SIZE` by `REGION_SIZE` area that has any, see `write_region`. Region files
+/// left over from an earlier save that don't have any chunks anymore are removed. Every file is
+/// replaced in one step, see `write_atomically`, with the metadata file going last.
+pub fn write(dir: &Path, header: &SaveHeader, chunks: &[(Vector2<i32>, SavedChunk)]) -> Result<(), SaveError> {
+    let regions_dir = dir.join(REGIONS_FOLDER);
+    fs::create_dir_all(&regions_dir)?;
+
+    let mut regions: HashMap<Vector2<i32>, Vec<(Vector2<i32>, &SavedChunk)>> = HashMap::new();
+    for (location, chunk) in chunks {
+        regions.entry(region_of(*location)).or_default().push((*location, chunk));
+    }
+
+    for (region, chunks) in &regions {
+        write_region(&regions_dir.join(region_file_name(*region)), *region, chunks)?;
+    }
+    for entry in fs::read_dir(&regions_dir)? {
+        let path = entry?.path();
+        if parse_region_file_name(&path).is_some_and(|region| !regions.contains_key(&region)) {
+            fs::remove_file(path)?;
+        }
+    }
+
+    write_atomically(&dir.join(METADATA_FILE), |writer| {
+        writer.write_all(&SAVE_MAGIC)?;
+        writer.write_all(&[SAVE_VERSION])?;
+        bincode::serialize_into(writer, header)?;
+        Ok(())
+    })
+}
+
+/// Writes a file next to `path` first and renames it over `path` once it's complete, so a crash
+/// or a full disk halfway through leaves the previous save as it was instead of a truncated file
+fn write_atomically(path: &Path, write: impl FnOnce(&mut BufWriter<File>) -> Result<(), SaveError>) -> Result<(), SaveError> {
+    let file_name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let temp_path = path.with_file_name(format!("{}.tmp", file_name));
+
+    let result = File::create(&temp_path).map_err(SaveError::from).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+/// Reads a folder written by `write`, checking the magic number and version of every file first.
+/// Fails if any region file is corrupt or truncated, instead of loading what's left of it.
+pub fn read(dir: &Path) -> Result<(SaveHeader, SavedChunks), SaveError> {
+    let mut reader = BufReader::new(File::open(dir.join(METADATA_FILE))?);
+    read_magic(&mut reader, SAVE_MAGIC)?;
+    let header: SaveHeader = bincode::deserialize_from(&mut reader)?;
+
+    let mut chunks = Vec::new();
+    let regions_dir = dir.join(REGIONS_FOLDER);
+    if regions_dir.is_dir() {
+        for entry in fs::read_dir(&regions_dir)? {
+            let path = entry?.path();
+            if let Some(region) = parse_region_file_name(&path) {
+                chunks.extend(read_region(&path, region)?);
+            }
+        }
+    }
+
+    Ok((header, chunks))
+}
+
+/// Checks that the reader starts with `magic` and `SAVE_VERSION`
+fn read_magic(reader: &mut impl Read, magic: [u8; 4]) -> Result<(), SaveError> {
+    let mut found = [0; 4];
+    let mut version = [0];
+    if reader.read_exact(&mut found).is_err() || found != magic {
+        return Err(SaveError::NotASave);
+    }
+    reader.read_exact(&mut version)?;
+    if version[0] != SAVE_VERSION {
+        return Err(SaveError::UnsupportedVersion(version[0]));
+    }
+
+    Ok(())
+}
+
+/// The region a chunk is stored in
+pub fn region_of(location: Vector2<i32>) -> Vector2<i32> {
+    location.map(|axis| axis.div_euclid(REGION_SIZE))
+}
+
+/// The chunk's slot in its region's offset table
+fn region_index(location: Vector2<i32>) -> usize {
+    let local = location.map(|axis| axis.rem_euclid(REGION_SIZE));
+    (local.x + local.y * REGION_SIZE) as usize
+}
+
+/// Like `r.-1.2.vxr` for the region at x -1 and z 2
+fn region_file_name(region: Vector2<i32>) -> String {
+    format!("r.{}.{}.{}", region.x, region.y, REGION_EXTENSION)
+}
+
+/// The region in a file name made by `region_file_name`, `None` for any other file
+fn parse_region_file_name(path: &Path) -> Option<Vector2<i32>> {
+    let name = path.file_name()?.to_str()?;
+    let mut parts = name.strip_prefix("r.")?.strip_suffix(REGION_EXTENSION)?.strip_suffix('.')?.split('.');
+
+    let x = parts.next()?.parse().ok()?;
+    let z = parts.next()?.parse().ok()?;
+    parts.next().is_none().then(|| Vector2::new(x, z))
+}
+
+/// How many bytes the magic number, version and offset table at the start of a region file take
+const REGION_HEADER_SIZE: usize = REGION_MAGIC.len() + 1 + REGION_CHUNKS * 8;
+/// How many chunks a region has room for
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE) as usize;
+
+/// Writes the chunks of one region. The file starts with `REGION_MAGIC` and the version, followed
+/// by a table with the offset and length of every chunk in the region as little endian `u32`s,
+/// ordered by x then z within the region. Chunks that aren't stored have both set to 0. The
+/// chunks themselves come after the table, each one a bincode encoded `SavedChunk`.
+fn write_region(path: &Path, region: Vector2<i32>, chunks: &[(Vector2<i32>, &SavedChunk)]) -> Result<(), SaveError> {
+    let mut table = vec![(0u32, 0u32); REGION_CHUNKS];
+    let mut data = Vec::new();
+    for (location, chunk) in chunks {
+        debug_assert_eq!(region_of(*location), region, "chunk {:?} isn't in region {:?}", location, region);
+
+        let start = data.len();
+        bincode::serialize_into(&mut data, chunk)?;
+        table[region_index(*location)] = ((REGION_HEADER_SIZE + start) as u32, (data.len() - start) as u32);
+    }
+
+    write_atomically(path, |writer| {
+        writer.write_all(&REGION_MAGIC)?;
+        writer.write_all(&[SAVE_VERSION])?;
+        for (offset, length) in table {
+            writer.write_all(&offset.to_le_bytes())?;
+            writer.write_all(&length.to_le_bytes())?;
+        }
+        writer.write_all(&data)?;
+        Ok(())
+    })
+}
+
+/// Reads every chunk in a file written by `write_region`
+fn read_region(path: &Path, region: Vector2<i32>) -> Result<SavedChunks, SaveError> {
+    let bytes = fs::read(path)?;
+    let name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+
+    read_magic(&mut bytes.as_slice(), REGION_MAGIC).map_err(|e| match e {
+        SaveError::NotASave => SaveError::Corrupt(format!("{} isn't a region file", name)),
+        e => e,
+    })?;
+    if bytes.len() < REGION_HEADER_SIZE {
+        return Err(SaveError::Corrupt(format!("region file {} is too short for its offset table", name)));
+    }
+
+    let table = &bytes[REGION_MAGIC.len() + 1..REGION_HEADER_SIZE];
+    let mut chunks = Vec::new();
+    for (index, entry) in table.chunks_exact(8).enumerate() {
+        let offset = u32::from_le_bytes(entry[..4].try_into().unwrap()) as usize;
+        let length = u32::from_le_bytes(entry[4..].try_into().unwrap()) as usize;
+        if length == 0 {
+            continue;
+        }
+
+        let index = index as i32;
+        let location = region * REGION_SIZE + Vector2::new(index % REGION_SIZE, index / REGION_SIZE);
+        let chunk_bytes = offset
+            .checked_add(length)
+            .filter(|end| offset >= REGION_HEADER_SIZE && *end <= bytes.len())
+            .map(|end| &bytes[offset..end])
+            .ok_or_else(|| SaveError::Corrupt(format!("chunk {:?} runs past the end of region file {}", location, name)))?;
+        let chunk = bincode::deserialize(chunk_bytes)
+            .map_err(|e| SaveError::Corrupt(format!("chunk {:?} in region file {} can't be read: {}", location, name, e)))?;
+
+        chunks.push((location, chunk));
+    }
+
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use cgmath::Vector3;
+
+    use super::*;
+    use crate::block::Axis;
+    use crate::camera::ProjectionState;
+
+    /// A fresh, empty save folder in the temp directory
+    fn save_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wgpu_voxel_game-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn header() -> SaveHeader {
+        SaveHeader {
+            seed: 1234,
+            origin: [2, -3],
+            camera: CameraState {
+                position: [1.5, 20.0, -7.25],
+                yaw: 0.5,
+                pitch: -0.25,
+                projection: ProjectionState::Perspective { fovy: 1.2 },
+            },
+        }
+    }
+
+    /// An empty chunk with a few blocks and light levels set
+    fn edited_chunk(location: Vector2<i32>) -> Chunk {
+        let mut chunk = Chunk::new(location);
+        chunk.set_block(Vector3::new(0, MIN_Y, 0), Block::new_stone()).unwrap();
+        chunk.set_block(Vector3::new(15, 3, 9), Block::new_oak_log(Axis::Z)).unwrap();
+        chunk.set_block(Vector3::new(4, 0, 4), Block::new_grass(true)).unwrap();
+        chunk.set_light(Vector3::new(4, 1, 4), 12).unwrap();
+
+        chunk
+    }
+
+    fn assert_same_chunk(a: &Chunk, b: &Chunk) {
+        assert_eq!(a.world_offset, b.world_offset);
+        assert!(a.iter_all_blocks().eq(b.iter_all_blocks()));
+        assert_eq!(a.light, b.light);
+    }
+
+    #[test]
+    fn a_saved_world_loads_with_the_same_blocks_and_camera() {
+        let dir = save_dir("round-trip");
+        let locations = [Vector2::new(0, 0), Vector2::new(-1, 5)];
+        let chunks = locations.map(|location| (location, SavedChunk::from_chunk(&edited_chunk(location))));
+
+        write(&dir, &header(), &chunks).unwrap();
+        let (loaded_header, mut loaded_chunks) = read(&dir).unwrap();
+
+        assert_eq!(loaded_header, header());
+        loaded_chunks.sort_by_key(|(location, _)| (location.x, location.y));
+        assert_eq!(loaded_chunks.len(), 2);
+        for (location, saved) in loaded_chunks {
+            assert_same_chunk(&saved.to_chunk(location).unwrap(), &edited_chunk(location));
+        }
+    }
+
+    #[test]
+    fn files_from_something_else_or_another_version_are_refused() {
+        let dir = save_dir("version");
+        write(&dir, &header(), &[]).unwrap();
+        let metadata = dir.join(METADATA_FILE);
+
+        let mut bytes = fs::read(&metadata).unwrap();
+        bytes[SAVE_MAGIC.len()] = SAVE_VERSION + 1;
+        fs::write(&metadata, &bytes).unwrap();
+        assert!(matches!(read(&dir), Err(SaveError::UnsupportedVersion(version)) if version == SAVE_VERSION + 1));
+
+        fs::write(&metadata, b"PNG and some more bytes").unwrap();
+        assert!(matches!(read(&dir), Err(SaveError::NotASave)));
+    }
+
+    /// Saves a single chunk and returns the path of its region file
+    fn saved_region(dir: &Path) -> PathBuf {
+        let location = Vector2::new(-1, 40);
+        write(dir, &header(), &[(location, SavedChunk::from_chunk(&edited_chunk(location)))]).unwrap();
+
+        dir.join(REGIONS_FOLDER).join(region_file_name(region_of(location)))
+    }
+
+    fn corrupt_reason(dir: &Path) -> String {
+        match read(dir) {
+            Err(SaveError::Corrupt(reason)) => reason,
+            other => panic!("expected a corrupt save, got {:?}", other.map(|(header, _)| header)),
+        }
+    }
+
+    #[test]
+    fn regions_are_named_after_their_position() {
+        assert_eq!(region_of(Vector2::new(-1, 40)), Vector2::new(-1, 1));
+        assert_eq!(region_of(Vector2::new(31, -32)), Vector2::new(0, -1));
+        assert_eq!(region_file_name(Vector2::new(-1, 1)), "r.-1.1.vxr");
+        assert_eq!(parse_region_file_name(Path::new("r.-1.1.vxr")), Some(Vector2::new(-1, 1)));
+        assert_eq!(parse_region_file_name(Path::new("r.-1.1.vxr.tmp")), None);
+        assert_eq!(parse_region_file_name(Path::new("world.dat")), None);
+    }
+
+    #[test]
+    fn truncated_region_files_are_corrupt() {
+        let dir = save_dir("truncated");
+        let path = saved_region(&dir);
+        let bytes = fs::read(&path).unwrap();
+
+        fs::write(&path, &bytes[..bytes.len() - 10]).unwrap();
+        assert!(corrupt_reason(&dir).contains("runs past the end"));
+
+        fs::write(&path, &bytes[..REGION_HEADER_SIZE - 1]).unwrap();
+        assert!(corrupt_reason(&dir).contains("too short"));
+
+        fs::write(&path, b"VXL").unwrap();
+        assert!(corrupt_reason(&dir).contains("isn't a region file"));
+    }
+
+    #[test]
+    fn garbled_chunks_are_corrupt() {
+        let dir = save_dir("garbled");
+        let path = saved_region(&dir);
+        let mut bytes = fs::read(&path).unwrap();
+
+        for byte in &mut bytes[REGION_HEADER_SIZE..] {
+            *byte = 0xff;
+        }
+        fs::write(&path, &bytes).unwrap();
+
+        assert!(corrupt_reason(&dir).contains("can't be read"));
+
+        // Readable, but short of a whole chunk
+        let short = SavedChunk { blocks: vec![(10, Block::new_air())], light: vec![(CHUNK_SIZE as u32, 0)] };
+        assert!(matches!(short.to_chunk(Vector2::new(0, 0)), Err(SaveError::Corrupt(_))));
+    }
+
+    #[test]
+    fn saving_again_removes_regions_without_chunks() {
+        let dir = save_dir("stale");
+        let path = saved_region(&dir);
+
+        write(&dir, &header(), &[(Vector2::new(0, 0), SavedChunk::from_chunk(&Chunk::new(Vector2::new(0, 0))))]).unwrap();
+
+        assert!(!path.exists());
+        assert_eq!(read(&dir).unwrap().1.len(), 1);
+    }
+}
@@ -1,43 +1,116 @@
 struct Camera {
     view_pos: vec4<f32>,
     view_proj: mat4x4<f32>,
+    time: f32,
 };
 @group(0) @binding(0)
 var<uniform> camera: Camera;
 
 struct Chunk {
-    chunk_offset: vec3<f32>,
+    model: mat4x4<f32>,
+    tint: vec4<f32>,
+    fade: f32,
 };
 @group(1) @binding(0)
-var t_diffuse: texture_2d<f32>;
+var t_diffuse: texture_2d_array<f32>;
 @group(1) @binding(1)
 var s_diffuse: sampler;
 @group(1) @binding(2)
 var<uniform> u_chunk: Chunk;
 
+// `Tint::PALETTE`, indexed by the tint in each vertex
+struct TintPalette {
+    colors: array<vec4<f32>, 2>,
+};
+@group(1) @binding(3)
+var<uniform> tint_palette: TintPalette;
+
+struct Light {
+    direction: vec3<f32>,
+    color: vec3<f32>,
+    ambient: f32,
+};
+@group(2) @binding(0)
+var<uniform> light: Light;
+
+struct Fog {
+    color: vec3<f32>,
+    start: f32,
+    end: f32,
+};
+@group(3) @binding(0)
+var<uniform> fog: Fog;
+
+// Tiles per row of the atlas, `ATLAS_SIZE / TEXTURE_SIZE`. Animation frames are a row apart.
+let ATLAS_COLUMNS: u32 = 16u;
+// `TextureAnimation::TICK`
+let ANIMATION_TICK: f32 = 0.1;
+
+// See `ChunkVertex` for the layout
 struct VertexInput {
-    @location(0) position: vec3<f32>,
-    @location(1) tex_coord: vec2<f32>,
+    @location(0) data: vec2<u32>,
 };
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) tex_coord: vec2<f32>,
+    @location(1) @interpolate(flat) layer: u32,
+    @location(2) light: f32,
+    @location(3) normal: vec3<f32>,
+    @location(4) view_distance: f32,
+    @location(5) color: vec4<f32>,
 };
 
+// Matches `Direction::to_vec3` for each `Direction::index`
+fn face_normal(face: u32) -> vec3<f32> {
+    switch (face) {
+        case 0u: { return vec3<f32>(0.0, 0.0, 1.0); }
+        case 1u: { return vec3<f32>(0.0, 0.0, -1.0); }
+        case 2u: { return vec3<f32>(0.0, 1.0, 0.0); }
+        case 3u: { return vec3<f32>(0.0, -1.0, 0.0); }
+        case 4u: { return vec3<f32>(-1.0, 0.0, 0.0); }
+        default: { return vec3<f32>(1.0, 0.0, 0.0); }
+    }
+}
+
 @vertex
 fn vs_main(
     model: VertexInput,
 ) -> VertexOutput {
 
-    let world_position =  vec4<f32>(model.position + u_chunk.chunk_offset, 1.0);
+    let half_steps = vec3<u32>(
+        model.data.x & 63u,
+        (model.data.x >> 12u) & 1023u,
+        (model.data.x >> 6u) & 63u,
+    );
+    // y counts up from the bottom of the chunk, the model matrix moves it back into place
+    let position = vec3<f32>(half_steps) * 0.5 - vec3<f32>(0.5, 0.5, 0.5);
+
+    let world_position = u_chunk.model * vec4<f32>(position, 1.0);
 
     var result: VertexOutput;
     result.clip_position = camera.view_proj * world_position;
-    result.tex_coord = model.tex_coord;
+    result.tex_coord = vec2<f32>(vec2<u32>(model.data.y & 3u, (model.data.y >> 2u) & 3u)) * 0.5;
+    let frames = ((model.data.y >> 24u) & 15u) + 1u;
+    let ticks_per_frame = ((model.data.y >> 28u) & 15u) + 1u;
+    let frame = u32(camera.time / (ANIMATION_TICK * f32(ticks_per_frame))) % frames;
+    result.layer = ((model.data.y >> 4u) & 65535u) + frame * ATLAS_COLUMNS;
+    result.light = f32((model.data.y >> 20u) & 15u) / 15.0;
+    result.normal = face_normal((model.data.x >> 22u) & 7u);
+    result.view_distance = distance(world_position.xyz, camera.view_pos.xyz);
+    result.color = tint_palette.colors[(model.data.x >> 25u) & 127u];
     return result;
 }
 
 @fragment
 fn fs_main(vertex: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, vertex.tex_coord);
+    let color = textureSample(t_diffuse, s_diffuse, vertex.tex_coord, i32(vertex.layer)) * vertex.color * u_chunk.tint;
+    // Cut out fully transparent texels, like the holes in leaves, so they don't write depth
+    if (color.a < 0.1) {
+        discard;
+    }
+    let diffuse = max(dot(normalize(vertex.normal), light.direction), 0.0);
+    let shade = light.ambient + diffuse * light.color;
+    let fog_amount = clamp((vertex.view_distance - fog.start) / (fog.end - fog.start), 0.0, 1.0);
+    let rgb = mix(color.rgb * shade * vertex.light, fog.color, fog_amount);
+    return vec4<f32>(rgb, color.a * u_chunk.fade);
 }
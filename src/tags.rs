@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use hashbrown::{HashMap, HashSet};
+use serde::Deserialize;
+
+use crate::block::Block;
+use crate::resources::get_resource;
+
+/// Where tags are added to or replaced, relative to the resource folder
+pub const TAGS_FILE: &str = "tags.ron";
+
+/// The tags built into the game, as block keys
+const BUILTIN_TAGS: &[(&str, &[&str])] = &[
+    ("soil", &["dirt", "grass"]),
+    ("logs", &["oak_log"]),
+    ("leaves", &["oak_leaves"]),
+    ("plants", &["tall_grass", "flower"]),
+    ("fluids", &["water"]),
+];
+
+/// The tags every block is looked up in, see `BlockTags::install`
+static INSTALLED: OnceLock<BlockTags> = OnceLock::new();
+
+/// A tag from the tags file. Blocks are named by their key, like the files in `res/blocks`.
+#[derive(Debug, Clone, Deserialize)]
+struct TagDef {
+    /// Whether the tag's built-in blocks are dropped instead of added to
+    #[serde(default)]
+    replace: bool,
+    blocks: Vec<String>,
+}
+
+/// Named groups of blocks, like `logs`, so code can ask what kind of block something is without
+/// listing every block of that kind
+#[derive(Debug, Clone, Default)]
+pub struct BlockTags {
+    tags: HashMap<String, HashSet<u16>>,
+}
+
+impl BlockTags {
+    /// The tags built into the game, without the tags file
+    pub fn builtin() -> Self {
+        let mut tags = BlockTags::default();
+
+        for (tag, keys) in BUILTIN_TAGS {
+            let blocks = keys
+                .iter()
+                .map(|key| Block::by_name(key).unwrap_or_else(|| panic!("tag {:?} has an unknown block {:?}", tag, key)));
+            tags.extend(tag, blocks);
+        }
+
+        tags
+    }
+
+    /// The built-in tags along with the ones from the tags file in the resource folder, if there is
+    /// one
+    pub fn load() -> anyhow::Result<Self> {
+        let path = get_resource(TAGS_FILE);
+        if !path.exists() {
+            return Ok(BlockTags::builtin());
+        }
+
+        BlockTags::load_file(&path)
+    }
+
+    /// The built-in tags, extended or replaced by the ones in `path`
+    pub fn load_file(path: &Path) -> anyhow::Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("unable to read block tags from {}", path.display()))?;
+        let defs: BTreeMap<String, TagDef> = ron::from_str(&source)
+            .with_context(|| format!("invalid block tags {}", path.display()))?;
+
+        let mut tags = BlockTags::builtin();
+
+        for (tag, def) in defs {
+            let blocks = def.blocks
+                .iter()
+                .map(|key| Block::by_name(key).with_context(|| format!("tag {:?} has an unknown block {:?}", tag, key)))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            if def.replace {
+                tags.tags.remove(&tag);
+            }
+            tags.extend(&tag, blocks);
+        }
+
+        Ok(tags)
+    }
+
+    /// Adds blocks to the tag, creating it if it doesn't exist yet
+    pub fn extend(&mut self, tag: &str, blocks: impl IntoIterator<Item = Block>) {
+        self.tags
+            .entry(tag.to_string())
+            .or_default()
+            .extend(blocks.into_iter().map(|block| block.id()));
+    }
+
+    /// Whether the block has the tag. Tags only name kinds of blocks, so every state of a block has
+    /// the same tags.
+    pub fn has(&self, block: &Block, tag: &str) -> bool {
+        self.tags.get(tag).is_some_and(|ids| ids.contains(&block.id()))
+    }
+
+    /// Every built-in block with the tag, in id order
+    pub fn blocks_with(&self, tag: &str) -> Vec<Block> {
+        Block::all().filter(|block| self.has(block, tag)).collect()
+    }
+
+    /// Makes these the tags `Block::has_tag` uses. Tags can only be installed once, before the
+    /// first lookup, returns them back otherwise.
+    pub fn install(self) -> Result<(), BlockTags> {
+        INSTALLED.set(self)
+    }
+
+    /// The installed tags, or the built-in ones if none were installed
+    pub fn installed() -> &'static BlockTags {
+        INSTALLED.get_or_init(BlockTags::builtin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    /// Writes a tags file to a fresh path in the temp directory
+    fn tags_file(name: &str, source: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("wgpu_voxel_game-{}-{}.ron", name, std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        path
+    }
+
+    #[test]
+    fn built_in_tags_cover_every_state_of_their_blocks() {
+        let tags = BlockTags::builtin();
+
+        assert!(tags.has(&Block::new_grass(false), "soil"));
+        assert!(tags.has(&Block::new_grass(true), "soil"));
+        assert!(!tags.has(&Block::new_stone(), "soil"));
+        assert!(!tags.has(&Block::new_dirt(), "not a tag"));
+        assert_eq!(tags.blocks_with("soil"), vec![Block::new_grass(false), Block::new_dirt()]);
+    }
+
+    #[test]
+    fn the_tags_file_extends_or_replaces_built_in_tags() {
+        let path = tags_file(
+            "tags",
+            r#"{
+                "soil": (blocks: ["sand"]),
+                "logs": (replace: true, blocks: ["stone"]),
+                "hard": (blocks: ["stone", "cobblestone"]),
+            }"#,
+        );
+        let tags = BlockTags::load_file(&path).unwrap();
+
+        assert!(tags.has(&Block::new_sand(), "soil"));
+        assert!(tags.has(&Block::new_dirt(), "soil"));
+        assert_eq!(tags.blocks_with("logs"), vec![Block::new_stone()]);
+        assert_eq!(tags.blocks_with("hard"), vec![Block::new_stone(), Block::new_cobblestone()]);
+    }
+
+    #[test]
+    fn an_unknown_block_in_the_tags_file_is_an_error() {
+        let path = tags_file("unknown-tags", r#"{ "soil": (blocks: ["bedrock"]) }"#);
+        let error = format!("{:#}", BlockTags::load_file(&path).unwrap_err());
+
+        assert!(error.contains("bedrock"), "{}", error);
+    }
+}
@@ -0,0 +1,691 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use cgmath::{Vector2, Vector3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::block::{Axis, Block};
+use crate::chunk::{CHUNK_DEPTH, CHUNK_WIDTH, Chunk, MIN_Y};
+
+/// Fills chunks with blocks. Generators are shared with the threads generating chunks in the
+/// background, and have to give the same blocks for the same chunk every time, so chunks line up
+/// with their neighbors no matter when they're generated.
+pub trait WorldGenerator: Send + Sync {
+    /// Fills `chunk`, the empty chunk at `offset`, with blocks
+    fn generate(&self, offset: Vector2<i32>, chunk: &mut Chunk);
+
+    /// The height of the topmost solid block of the column at world space `x` and `z`, leaving
+    /// out decorations like trees
+    fn height_at(&self, x: i32, z: i32) -> i32;
+
+    /// A new chunk at `offset`, filled by `generate`
+    fn generate_chunk(&self, offset: Vector2<i32>) -> Chunk {
+        let mut chunk = Chunk::new(offset);
+        self.generate(offset, &mut chunk);
+
+        chunk
+    }
+}
+
+/// The world generators that can be picked at startup, see `run`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GeneratorKind {
+    /// `TerrainGenerator`
+    #[default]
+    Terrain,
+    /// `NoiseGenerator`
+    Noise,
+    /// `FlatGenerator`
+    Flat,
+    /// `DebugGridGenerator`
+    DebugGrid,
+}
+
+impl GeneratorKind {
+    /// The generator, using `seed` if it's random at all. Terrain below `water_level` is flooded
+    /// by the generators that have water.
+    pub fn build(self, seed: WorldSeed, water_level: i32) -> Arc<dyn WorldGenerator> {
+        match self {
+            GeneratorKind::Terrain => Arc::new(TerrainGenerator::new(seed, water_level)),
+            GeneratorKind::Noise => Arc::new(NoiseGenerator { seed, ..Default::default() }),
+            GeneratorKind::Flat => Arc::new(FlatGenerator::default()),
+            GeneratorKind::DebugGrid => Arc::new(DebugGridGenerator),
+        }
+    }
+}
+
+/// The name given to `GeneratorKind::from_str` isn't one of the generators
+#[derive(Debug)]
+pub struct UnknownGeneratorError(String);
+
+impl fmt::Display for UnknownGeneratorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown world generator `{}`, expected terrain, noise, flat or debug", self.0)
+    }
+}
+
+impl std::error::Error for UnknownGeneratorError {}
+
+impl FromStr for GeneratorKind {
+    type Err = UnknownGeneratorError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "terrain" => Ok(GeneratorKind::Terrain),
+            "noise" => Ok(GeneratorKind::Noise),
+            "flat" => Ok(GeneratorKind::Flat),
+            "debug" => Ok(GeneratorKind::DebugGrid),
+            _ => Err(UnknownGeneratorError(name.to_string())),
+        }
+    }
+}
+
+/// Seeds everything random about a world, the same seed always generates the same world
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WorldSeed(pub u64);
+
+impl WorldSeed {
+    /// The seed for the decorations of a single chunk, mixed from the world seed and the chunk's
+    /// location so neighboring chunks don't get similar ones
+    pub fn chunk_seed(self, chunk_offset: Vector2<i32>) -> u64 {
+        // Flipped, so these aren't the same values the heightmap's lattice points get
+        hash(!self.0, chunk_offset.x, chunk_offset.y)
+    }
+}
+
+/// Something placed on top of the terrain that can reach into the neighboring chunks
+#[derive(Debug, Clone, PartialEq)]
+enum Feature {
+    /// An oak tree growing on top of the grass at `ground`
+    Tree { ground: Vector3<i32> },
+    /// A blob of glowstone replacing the stone at `positions`. There are no ores yet, so these
+    /// stand in for ore veins.
+    Vein { positions: Vec<Vector3<i32>> },
+}
+
+/// Fills chunks with rolling hills from a heightmap of layered noise, then decorates them with
+/// trees and veins. Every block only depends on the seed and its world space position, so chunks
+/// can be generated in any order and still line up with their neighbors.
+///
+/// Features are picked per chunk from `WorldSeed::chunk_seed`. Since trees hang over into the
+/// next chunk, every chunk also places the parts of its neighbors' features that land in it,
+/// instead of waiting for the neighbors to write them in.
+#[derive(Debug, Clone)]
+pub struct TerrainGenerator {
+    seed: WorldSeed,
+    /// Columns whose surface is below this height are filled with water up to it, and the ones
+    /// just above it are covered in sand
+    water_level: i32,
+}
+
+impl TerrainGenerator {
+    /// The height the noise varies around
+    const BASE_HEIGHT: i32 = 8;
+    /// How far the surface can get from `BASE_HEIGHT`, in blocks
+    const AMPLITUDE: f64 = 16.0;
+    /// How many blocks the widest layer of noise takes to go from one value to the next
+    const FEATURE_SIZE: f64 = 64.0;
+    /// How many layers of noise are summed, each twice as detailed and half as strong as the last
+    const OCTAVES: u32 = 4;
+    /// How many blocks of dirt (or sand) are between the surface and the stone
+    const SOIL_DEPTH: i32 = 3;
+    /// How far above the water level the surface is still sand
+    const BEACH_HEIGHT: i32 = 1;
+    /// How many times a chunk tries to grow a tree, tries on sand are skipped
+    const TREE_ATTEMPTS: u32 = 3;
+    /// How many veins each chunk has
+    const VEINS_PER_CHUNK: u32 = 4;
+    /// How many blocks the random walk growing a vein takes, some of them may overlap
+    const VEIN_SIZE: u32 = 10;
+    /// The highest a vein can start, so they stay below the dirt
+    const VEIN_MAX_Y: i32 = -16;
+    /// How many chunks away a feature can reach, trees are never more than a few blocks wide
+    const FEATURE_REACH: i32 = 1;
+
+    pub fn new(seed: WorldSeed, water_level: i32) -> Self {
+        Self { seed, water_level }
+    }
+
+    pub fn seed(&self) -> WorldSeed {
+        self.seed
+    }
+
+    pub fn water_level(&self) -> i32 {
+        self.water_level
+    }
+
+    /// The height of the topmost solid block of the column at world space `x` and `z`
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let noise = fractal_noise(self.seed.0, x as f64 / Self::FEATURE_SIZE, z as f64 / Self::FEATURE_SIZE, Self::OCTAVES);
+
+        Self::BASE_HEIGHT + (noise * Self::AMPLITUDE).round() as i32
+    }
+
+    /// The block at a world space position, before the chunk is decorated
+    pub fn block_at(&self, position: Vector3<i32>) -> Block {
+        self.block_in_column(self.height_at(position.x, position.z), position.y)
+    }
+
+    /// Generates and decorates the chunk at `chunk_offset`. The heightmap is only sampled once per
+    /// column.
+    fn generate_decorated(&self, chunk_offset: Vector2<i32>) -> Chunk {
+        let origin = Vector2::new(chunk_offset.x * CHUNK_WIDTH as i32, chunk_offset.y * CHUNK_DEPTH as i32);
+
+        let mut heights = [[0; CHUNK_DEPTH]; CHUNK_WIDTH];
+        for (x, column) in heights.iter_mut().enumerate() {
+            for (z, height) in column.iter_mut().enumerate() {
+                *height = self.height_at(origin.x + x as i32, origin.y + z as i32);
+            }
+        }
+
+        let mut chunk = Chunk::generate(chunk_offset, |position| {
+            self.block_in_column(heights[position.x as usize][position.z as usize], position.y)
+        });
+        self.decorate(&mut chunk);
+
+        chunk
+    }
+
+    /// Places the features of the chunk and its neighbors, always going through the neighbors in
+    /// the same order so overlapping features come out the same no matter which chunk is
+    /// generated first
+    fn decorate(&self, chunk: &mut Chunk) {
+        let origin = Vector3::new(chunk.world_offset.x * CHUNK_WIDTH as i32, 0, chunk.world_offset.y * CHUNK_DEPTH as i32);
+
+        for x in -Self::FEATURE_REACH..=Self::FEATURE_REACH {
+            for z in -Self::FEATURE_REACH..=Self::FEATURE_REACH {
+                for feature in self.features(chunk.world_offset + Vector2::new(x, z)) {
+                    feature.place(chunk, origin);
+                }
+            }
+        }
+    }
+
+    /// The features starting in the chunk at `chunk_offset`, from its chunk seed
+    fn features(&self, chunk_offset: Vector2<i32>) -> Vec<Feature> {
+        let mut rng = StdRng::seed_from_u64(self.seed.chunk_seed(chunk_offset));
+        let origin = Vector2::new(chunk_offset.x * CHUNK_WIDTH as i32, chunk_offset.y * CHUNK_DEPTH as i32);
+        let mut features = Vec::new();
+
+        for _ in 0..Self::TREE_ATTEMPTS {
+            let x = origin.x + rng.gen_range(0..CHUNK_WIDTH as i32);
+            let z = origin.y + rng.gen_range(0..CHUNK_DEPTH as i32);
+            let height = self.height_at(x, z);
+
+            if height > self.water_level + Self::BEACH_HEIGHT {
+                features.push(Feature::Tree { ground: Vector3::new(x, height, z) });
+            }
+        }
+
+        for _ in 0..Self::VEINS_PER_CHUNK {
+            let mut position = Vector3::new(
+                origin.x + rng.gen_range(0..CHUNK_WIDTH as i32),
+                rng.gen_range(MIN_Y..=Self::VEIN_MAX_Y),
+                origin.y + rng.gen_range(0..CHUNK_DEPTH as i32),
+            );
+            let mut positions = Vec::new();
+
+            for _ in 0..Self::VEIN_SIZE {
+                positions.push(position);
+                match rng.gen_range(0..3) {
+                    0 => position.x += rng.gen_range(-1..=1),
+                    1 => position.y += rng.gen_range(-1..=1),
+                    _ => position.z += rng.gen_range(-1..=1),
+                }
+            }
+
+            features.push(Feature::Vein { positions });
+        }
+
+        features
+    }
+
+    /// The block at height `y` in a column whose surface is at `height`
+    fn block_in_column(&self, height: i32, y: i32) -> Block {
+        let beach = height <= self.water_level + Self::BEACH_HEIGHT;
+
+        if y > height {
+            if y <= self.water_level {
+                Block::new_water()
+            } else {
+                Block::new_air()
+            }
+        } else if y > height - Self::SOIL_DEPTH {
+            if beach {
+                Block::new_sand()
+            } else if y == height {
+                Block::new_grass(false)
+            } else {
+                Block::new_dirt()
+            }
+        } else {
+            Block::new_stone()
+        }
+    }
+}
+
+impl Feature {
+    /// Places the blocks of the feature that are in `chunk`, whose first block is at the world
+    /// space position `origin`
+    fn place(&self, chunk: &mut Chunk, origin: Vector3<i32>) {
+        let mut set_block = |position: Vector3<i32>, block: Block, replaces: fn(&Block) -> bool| {
+            let local_position = position - origin;
+
+            if chunk.get_block(local_position).is_some_and(replaces) {
+                chunk.set_block(local_position, block).unwrap();
+            }
+        };
+
+        match self {
+            Feature::Tree { ground } => {
+                for (position, block) in oak_tree(*ground) {
+                    if block == Block::new_oak_leaves() {
+                        set_block(position, block, |existing| *existing == Block::new_air());
+                    } else {
+                        set_block(position, block, |_| true);
+                    }
+                }
+            }
+            Feature::Vein { positions } => {
+                for position in positions {
+                    set_block(*position, Block::new_glowstone(), |existing| *existing == Block::new_stone());
+                }
+            }
+        }
+    }
+}
+
+/// The blocks of an oak tree growing on top of the world space position `ground`: the soil under
+/// it turned into dirt, then the trunk, then the leaves
+pub fn oak_tree(ground: Vector3<i32>) -> impl Iterator<Item = (Vector3<i32>, Block)> {
+    const TRUNK_HEIGHT: i32 = 4;
+
+    let trunk = (1..=TRUNK_HEIGHT).map(move |y| (ground + Vector3::new(0, y, 0), Block::new_oak_log(Axis::Y)));
+
+    // Two wide layers around the top of the trunk with the corners cut off, then two narrow ones
+    // above them
+    let layers: [(i32, i32); 4] = [(TRUNK_HEIGHT - 1, 2), (TRUNK_HEIGHT, 2), (TRUNK_HEIGHT + 1, 1), (TRUNK_HEIGHT + 2, 1)];
+    let leaves = layers.into_iter().flat_map(move |(y, radius)| {
+        (-radius..=radius)
+            .flat_map(move |x| (-radius..=radius).map(move |z| (x, z)))
+            .filter(move |(x, z)| {
+                let corner = x.abs() == radius && z.abs() == radius;
+                let trunk = *x == 0 && *z == 0 && y <= TRUNK_HEIGHT;
+                !(corner || trunk)
+            })
+            .map(move |(x, z)| (ground + Vector3::new(x, y, z), Block::new_oak_leaves()))
+    });
+
+    std::iter::once((ground, Block::new_dirt())).chain(trunk).chain(leaves)
+}
+
+impl WorldGenerator for TerrainGenerator {
+    fn generate(&self, offset: Vector2<i32>, chunk: &mut Chunk) {
+        *chunk = self.generate_decorated(offset);
+    }
+
+    fn height_at(&self, x: i32, z: i32) -> i32 {
+        TerrainGenerator::height_at(self, x, z)
+    }
+}
+
+/// Hills of grass on dirt on stone from layered noise, without water or anything on top like
+/// `TerrainGenerator` has, to try out other shapes of terrain
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseGenerator {
+    pub seed: WorldSeed,
+    /// How far the surface can get from y = 0, in blocks
+    pub amplitude: f64,
+    /// How many blocks the widest layer of noise takes to go from one value to the next
+    pub scale: f64,
+}
+
+impl NoiseGenerator {
+    /// How many layers of noise are summed, like `TerrainGenerator::OCTAVES`
+    const OCTAVES: u32 = 4;
+    /// How many blocks of dirt are under the grass
+    const DIRT_DEPTH: i32 = 3;
+}
+
+impl Default for NoiseGenerator {
+    fn default() -> Self {
+        Self {
+            seed: WorldSeed(0),
+            amplitude: 24.0,
+            scale: 48.0,
+        }
+    }
+}
+
+impl WorldGenerator for NoiseGenerator {
+    fn generate(&self, offset: Vector2<i32>, chunk: &mut Chunk) {
+        *chunk = Chunk::generate(offset, |position| {
+            let height = self.height_at(offset.x * CHUNK_WIDTH as i32 + position.x, offset.y * CHUNK_DEPTH as i32 + position.z);
+
+            if position.y > height {
+                Block::new_air()
+            } else if position.y == height {
+                Block::new_grass(false)
+            } else if position.y >= height - NoiseGenerator::DIRT_DEPTH {
+                Block::new_dirt()
+            } else {
+                Block::new_stone()
+            }
+        });
+    }
+
+    fn height_at(&self, x: i32, z: i32) -> i32 {
+        let noise = fractal_noise(self.seed.0, x as f64 / self.scale, z as f64 / self.scale, NoiseGenerator::OCTAVES);
+
+        (noise * self.amplitude).round() as i32
+    }
+}
+
+/// The same layers of blocks everywhere, for building on
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatGenerator {
+    /// Each block and how many layers of it there are, from the bottom of the world up
+    pub layers: Vec<(Block, u32)>,
+}
+
+impl FlatGenerator {
+    /// The block at height `y`, air above the layers
+    fn block_at(&self, y: i32) -> Block {
+        let mut top = MIN_Y;
+
+        for (block, count) in &self.layers {
+            top += *count as i32;
+            if y < top {
+                return *block;
+            }
+        }
+
+        Block::new_air()
+    }
+}
+
+impl Default for FlatGenerator {
+    /// Stone up to a few layers of dirt under grass, with the grass at y = 0
+    fn default() -> Self {
+        Self {
+            layers: vec![
+                (Block::new_stone(), (-MIN_Y - 3) as u32),
+                (Block::new_dirt(), 3),
+                (Block::new_grass(false), 1),
+            ],
+        }
+    }
+}
+
+impl WorldGenerator for FlatGenerator {
+    fn generate(&self, offset: Vector2<i32>, chunk: &mut Chunk) {
+        *chunk = Chunk::generate(offset, |position| self.block_at(position.y));
+    }
+
+    fn height_at(&self, _x: i32, _z: i32) -> i32 {
+        MIN_Y + self.layers.iter().map(|(_, count)| *count as i32).sum::<i32>() - 1
+    }
+}
+
+/// A floor at y = 0 marking out the coordinate conventions: the chunk borders are lines of stone
+/// with a cobblestone post on each chunk's corner at local x = z = 0, and logs run along the world
+/// axes, lying in their direction. The world origin has a glowstone post, with magma a few blocks
+/// along the positive x axis and leaves along the positive z axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DebugGridGenerator;
+
+impl DebugGridGenerator {
+    /// How far from the origin the markers for the positive x and z directions are
+    const MARKER_DISTANCE: i32 = 4;
+
+    /// The block at a world space position
+    fn block_at(position: Vector3<i32>) -> Block {
+        let local = Vector2::new(position.x.rem_euclid(CHUNK_WIDTH as i32), position.z.rem_euclid(CHUNK_DEPTH as i32));
+        let marker = DebugGridGenerator::MARKER_DISTANCE;
+
+        match (position.x, position.y, position.z) {
+            (0, 0, _) => Block::new_oak_log(Axis::Z),
+            (_, 0, 0) => Block::new_oak_log(Axis::X),
+            (_, 0, _) if local.x == 0 || local.y == 0 => Block::new_stone(),
+            (_, 0, _) => Block::new_sand(),
+            (0, 1..=3, 0) => Block::new_glowstone(),
+            (x, 1, 0) if x == marker => Block::new_magma(),
+            (0, 1, z) if z == marker => Block::new_oak_leaves(),
+            (_, 1..=2, _) if local.x == 0 && local.y == 0 => Block::new_cobblestone(),
+            _ => Block::new_air(),
+        }
+    }
+}
+
+impl WorldGenerator for DebugGridGenerator {
+    fn generate(&self, offset: Vector2<i32>, chunk: &mut Chunk) {
+        *chunk = Chunk::generate(offset, |position| {
+            DebugGridGenerator::block_at(Vector3::new(
+                offset.x * CHUNK_WIDTH as i32 + position.x,
+                position.y,
+                offset.y * CHUNK_DEPTH as i32 + position.z,
+            ))
+        });
+    }
+
+    fn height_at(&self, _x: i32, _z: i32) -> i32 {
+        0
+    }
+}
+
+/// Value noise summed over `octaves` layers, each at twice the frequency and half the amplitude of
+/// the one before. Returns a value between -1 and 1.
+fn fractal_noise(seed: u64, x: f64, z: f64, octaves: u32) -> f64 {
+    let (mut total, mut max) = (0.0, 0.0);
+    let (mut frequency, mut amplitude) = (1.0, 1.0);
+
+    for octave in 0..octaves {
+        total += value_noise(seed.wrapping_add(octave as u64), x * frequency, z * frequency) * amplitude;
+        max += amplitude;
+
+        frequency *= 2.0;
+        amplitude *= 0.5;
+    }
+
+    total / max
+}
+
+/// Smoothly interpolates between random values at every integer coordinate
+fn value_noise(seed: u64, x: f64, z: f64) -> f64 {
+    let (x0, z0) = (x.floor(), z.floor());
+    let (tx, tz) = (smoothstep(x - x0), smoothstep(z - z0));
+    let (x0, z0) = (x0 as i32, z0 as i32);
+
+    let corner = |dx: i32, dz: i32| lattice_value(seed, x0.wrapping_add(dx), z0.wrapping_add(dz));
+    let front = lerp(corner(0, 0), corner(1, 0), tx);
+    let back = lerp(corner(0, 1), corner(1, 1), tx);
+
+    lerp(front, back, tz)
+}
+
+/// A random value between -1 and 1 for a lattice point, the same for the same seed every time
+fn lattice_value(seed: u64, x: i32, z: i32) -> f64 {
+    (hash(seed, x, z) >> 11) as f64 / (1u64 << 53) as f64 * 2.0 - 1.0
+}
+
+/// Mixes a seed with a pair of coordinates, the same for the same inputs every time
+fn hash(seed: u64, x: i32, z: i32) -> u64 {
+    let mut hash = seed
+        ^ (x as u32 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (z as u32 as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F);
+
+    // The splitmix64 finalizer, so neighboring points don't get similar values
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    hash ^ (hash >> 31)
+}
+
+fn smoothstep(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{CHUNK_HEIGHT, MAX_Y};
+
+    /// Every block of the chunk, for comparing chunks
+    fn blocks(chunk: &Chunk) -> Vec<Block> {
+        let mut blocks = Vec::with_capacity(CHUNK_WIDTH * CHUNK_HEIGHT * CHUNK_DEPTH);
+        for x in 0..CHUNK_WIDTH as i32 {
+            for y in MIN_Y..=MAX_Y {
+                for z in 0..CHUNK_DEPTH as i32 {
+                    blocks.push(*chunk.get_block(Vector3::new(x, y, z)).unwrap());
+                }
+            }
+        }
+
+        blocks
+    }
+
+    /// The height of the topmost block of the column that's part of the ground, skipping over
+    /// water and trees
+    fn ground_height(chunk: &Chunk, x: i32, z: i32) -> i32 {
+        (MIN_Y..=MAX_Y)
+            .rev()
+            .find(|y| {
+                matches!(
+                    chunk.get_block(Vector3::new(x, *y, z)),
+                    Some(Block::Grass(_) | Block::Dirt(_) | Block::Sand(_) | Block::Stone(_) | Block::Glowstone(_))
+                )
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn terrain_is_the_same_for_the_same_seed() {
+        let generator = TerrainGenerator::new(WorldSeed(42), 0);
+        let offset = Vector2::new(3, -2);
+
+        assert!(blocks(&generator.generate_chunk(offset)) == blocks(&generator.generate_chunk(offset)));
+
+        let other = TerrainGenerator::new(WorldSeed(43), 0);
+        assert!(blocks(&generator.generate_chunk(offset)) != blocks(&other.generate_chunk(offset)));
+    }
+
+    #[test]
+    fn neighboring_chunks_meet_without_a_seam() {
+        let generator = TerrainGenerator::new(WorldSeed(7), 0);
+
+        for (left_offset, right_offset) in [(Vector2::new(0, 0), Vector2::new(1, 0)), (Vector2::new(-1, 4), Vector2::new(0, 4))] {
+            let left = generator.generate_chunk(left_offset);
+            let right = generator.generate_chunk(right_offset);
+            let border_x = right_offset.x * CHUNK_WIDTH as i32;
+
+            // Both sides of the border follow the same world space heightmap
+            for z in 0..CHUNK_DEPTH as i32 {
+                let world_z = left_offset.y * CHUNK_DEPTH as i32 + z;
+                assert_eq!(ground_height(&left, CHUNK_WIDTH as i32 - 1, z), generator.height_at(border_x - 1, world_z));
+                assert_eq!(ground_height(&right, 0, z), generator.height_at(border_x, world_z));
+            }
+        }
+    }
+
+    #[test]
+    fn every_generator_is_deterministic() {
+        let generators: [Arc<dyn WorldGenerator>; 4] = [
+            Arc::new(TerrainGenerator::new(WorldSeed(5), 0)),
+            Arc::new(NoiseGenerator { seed: WorldSeed(5), ..Default::default() }),
+            Arc::new(FlatGenerator::default()),
+            Arc::new(DebugGridGenerator),
+        ];
+
+        for generator in generators {
+            for offset in [Vector2::new(0, 0), Vector2::new(-3, 2)] {
+                assert!(blocks(&generator.generate_chunk(offset)) == blocks(&generator.generate_chunk(offset)));
+            }
+        }
+
+        let noise = |seed| NoiseGenerator { seed: WorldSeed(seed), ..Default::default() }.generate_chunk(Vector2::new(0, 0));
+        assert!(blocks(&noise(5)) != blocks(&noise(6)));
+    }
+
+    #[test]
+    fn generators_fill_columns_up_to_their_height() {
+        let noise = NoiseGenerator { seed: WorldSeed(9), ..Default::default() };
+        let chunk = noise.generate_chunk(Vector2::new(2, -1));
+        for (x, z) in [(0, 0), (15, 15), (7, 3)] {
+            let height = noise.height_at(2 * CHUNK_WIDTH as i32 + x, -(CHUNK_DEPTH as i32) + z);
+            assert_eq!(chunk.get_block(Vector3::new(x, height, z)), Some(&Block::new_grass(false)));
+            assert_eq!(chunk.get_block(Vector3::new(x, height + 1, z)), Some(&Block::new_air()));
+        }
+
+        let flat = FlatGenerator::default();
+        let chunk = flat.generate_chunk(Vector2::new(0, 0));
+        assert_eq!(flat.height_at(0, 0), 0);
+        assert_eq!(chunk.get_block(Vector3::new(3, 0, 3)), Some(&Block::new_grass(false)));
+        assert_eq!(chunk.get_block(Vector3::new(3, -1, 3)), Some(&Block::new_dirt()));
+        assert_eq!(chunk.get_block(Vector3::new(3, 1, 3)), Some(&Block::new_air()));
+    }
+
+    #[test]
+    fn the_debug_grid_marks_chunk_corners_and_the_origin() {
+        let chunk = DebugGridGenerator.generate_chunk(Vector2::new(1, 1));
+        assert_eq!(chunk.get_block(Vector3::new(0, 1, 0)), Some(&Block::new_cobblestone()));
+        assert_eq!(chunk.get_block(Vector3::new(0, 0, 5)), Some(&Block::new_stone()));
+        assert_eq!(chunk.get_block(Vector3::new(5, 0, 5)), Some(&Block::new_sand()));
+
+        let origin = DebugGridGenerator.generate_chunk(Vector2::new(0, 0));
+        assert_eq!(origin.get_block(Vector3::new(0, 2, 0)), Some(&Block::new_glowstone()));
+        assert_eq!(origin.get_block(Vector3::new(4, 1, 0)), Some(&Block::new_magma()));
+    }
+
+    #[test]
+    fn generators_are_picked_by_name() {
+        assert_eq!("flat".parse::<GeneratorKind>().unwrap(), GeneratorKind::Flat);
+        assert_eq!("debug".parse::<GeneratorKind>().unwrap(), GeneratorKind::DebugGrid);
+        assert!("caves".parse::<GeneratorKind>().is_err());
+    }
+
+    #[test]
+    fn a_region_comes_out_the_same_in_any_order() {
+        let generator = TerrainGenerator::new(WorldSeed(11), 0);
+        let region = (-1..=1).flat_map(|x| (-1..=1).map(move |z| Vector2::new(x, z))).collect::<Vec<_>>();
+        let chunk_of = |position: Vector3<i32>| {
+            Vector2::new(position.x.div_euclid(CHUNK_WIDTH as i32), position.z.div_euclid(CHUNK_DEPTH as i32))
+        };
+
+        let forward = region.iter().map(|offset| generator.generate_chunk(*offset)).collect::<Vec<_>>();
+        let mut backward = region.iter().rev().map(|offset| generator.generate_chunk(*offset)).collect::<Vec<_>>();
+        backward.reverse();
+
+        for (forward, backward) in forward.iter().zip(&backward) {
+            assert!(blocks(forward) == blocks(backward), "chunk {:?} depends on the order", forward.world_offset);
+        }
+
+        // The trunks and leaves of trees hanging over a border end up in the neighbor, whichever
+        // of the two was generated first
+        let mut overhanging = 0;
+        for offset in &region {
+            for feature in generator.features(*offset) {
+                let Feature::Tree { ground } = feature else { continue };
+
+                for (position, _) in oak_tree(ground).skip(1) {
+                    let neighbor = chunk_of(position);
+                    if neighbor == *offset {
+                        continue;
+                    }
+                    let Some(index) = region.iter().position(|offset| *offset == neighbor) else { continue };
+
+                    let local_position = position - Vector3::new(neighbor.x * CHUNK_WIDTH as i32, 0, neighbor.y * CHUNK_DEPTH as i32);
+                    for chunk in [&forward[index], &backward[index]] {
+                        assert!(matches!(chunk.get_block(local_position), Some(Block::OakLog(_) | Block::OakLeaves(_))));
+                    }
+                    overhanging += 1;
+                }
+            }
+        }
+        assert!(overhanging > 0, "no tree crosses a chunk border in the region");
+    }
+}
@@ -45,6 +45,24 @@ impl Texture {
         Self::from_image(device, queue, &img, Some(label), is_normal_map)
     }
 
+    /// Like `new`, but also uploads a full mip chain and samples it with linear mipmap filtering,
+    /// which stops distant surfaces from shimmering.
+    ///
+    /// The levels are box filtered across the whole image, so this isn't suitable for texture
+    /// atlases: the lower levels would blend neighboring tiles together. Block textures are stored
+    /// in a `TextureArray` instead, which filters every layer on its own.
+    pub fn new_with_mipmaps(
+        file_path: &Path,
+        is_normal_map: bool,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        let data = get_bytes(file_path).expect("Unable to load path for texture");
+        let img = image::load_from_memory(&data).expect("Unable to decode texture");
+
+        Self::create(device, queue, &img, file_path.to_str(), is_normal_map, true)
+    }
+
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -52,7 +70,22 @@ impl Texture {
         label: Option<&str>,
         is_normal_map: bool,
     ) -> Result<Self> {
-        let rgba = img.to_rgba8();
+        Ok(Self::create(device, queue, img, label, is_normal_map, false))
+    }
+
+    fn create(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+        is_normal_map: bool,
+        mipmaps: bool,
+    ) -> Self {
+        let levels = if mipmaps {
+            mip_chain(img.to_rgba8())
+        } else {
+            vec![img.to_rgba8()]
+        };
         let dimensions = img.dimensions();
 
         let size = wgpu::Extent3d {
@@ -63,7 +96,7 @@ impl Texture {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count: levels.len() as u32,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: if is_normal_map {
@@ -74,21 +107,9 @@ impl Texture {
             usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: std::num::NonZeroU32::new(4 * dimensions.0),
-                rows_per_image: std::num::NonZeroU32::new(dimensions.1),
-            },
-            size,
-        );
+        for (mip_level, level) in levels.iter().enumerate() {
+            write_level(queue, &texture, level, mip_level as u32, 0);
+        }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
@@ -96,21 +117,22 @@ impl Texture {
             address_mode_v: wgpu::AddressMode::Repeat,
             address_mode_w: wgpu::AddressMode::Repeat,
             mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+            min_filter: if mipmaps { wgpu::FilterMode::Linear } else { wgpu::FilterMode::Nearest },
+            mipmap_filter: if mipmaps { wgpu::FilterMode::Linear } else { wgpu::FilterMode::Nearest },
             ..Default::default()
         });
 
-        Ok(Self {
+        Self {
             texture,
             view,
             sampler,
-        })
+        }
     }
 
     pub fn create_depth_texture(
         device: &wgpu::Device,
         config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
         label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
@@ -122,7 +144,7 @@ impl Texture {
             label: Some(label),
             size,
             mip_level_count: 1,
-            sample_count: 1,
+            sample_count,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
@@ -149,4 +171,263 @@ impl Texture {
             sampler,
         }
     }
+
+    /// Creates the multisampled color target that's rendered into and then resolved to the
+    /// surface texture.
+    pub fn create_msaa_texture(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        sample_count: u32,
+        label: &str,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// A color target the size of `config` that can be copied from, for rendering without a
+    /// surface
+    pub fn create_render_target(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, label: &str) -> Self {
+        let size = wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+}
+
+/// A stack of equally sized textures bound as a single `D2Array` texture. Each block texture
+/// lives in its own layer, so it can be sampled with plain 0..1 coordinates and never bleeds into
+/// its neighbors.
+pub struct TextureArray {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+    pub layer_count: u32,
+}
+
+impl TextureArray {
+    /// Loads a texture atlas and splits it into layers, see `from_atlas`
+    pub fn new(
+        file_path: &Path,
+        tile_size: u32,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Self {
+        let data = get_bytes(file_path).expect("Unable to load path for texture");
+        let img = image::load_from_memory(&data).expect("Unable to decode texture atlas");
+
+        Self::from_atlas(device, queue, &img, tile_size, file_path.to_str())
+    }
+
+    /// Imports an atlas of `tile_size` square tiles. Tiles are read left to right, top to bottom,
+    /// so the tile at pixel `(x, y)` ends up in layer `atlas_layer(x, y, tile_size, atlas_width)`.
+    pub fn from_atlas(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        atlas: &image::DynamicImage,
+        tile_size: u32,
+        label: Option<&str>,
+    ) -> Self {
+        let (width, height) = atlas.dimensions();
+
+        let tiles = (0..height / tile_size)
+            .flat_map(|row| (0..width / tile_size).map(move |column| (column, row)))
+            .map(|(column, row)| atlas.crop_imm(column * tile_size, row * tile_size, tile_size, tile_size))
+            .collect::<Vec<_>>();
+
+        Self::from_images(device, queue, &tiles, label)
+            .expect("Texture atlas tiles should all be the same size")
+    }
+
+    /// Creates the array with one layer per image, in order. Every image must have the same size.
+    pub fn from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &[image::DynamicImage],
+        label: Option<&str>,
+    ) -> Result<Self> {
+        let (width, height) = images
+            .first()
+            .map(|img| img.dimensions())
+            .context("Texture array needs at least one layer")?;
+
+        if let Some(img) = images.iter().find(|img| img.dimensions() != (width, height)) {
+            bail!(
+                "Texture array layer is {:?}, expected {:?}",
+                img.dimensions(),
+                (width, height)
+            );
+        }
+
+        let layer_count = images.len() as u32;
+        let mip_level_count = mip_level_count(width, height);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: layer_count,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        });
+
+        // Each layer gets its own mip chain, so distant faces never pick up colors from
+        // other block textures
+        for (layer, img) in images.iter().enumerate() {
+            for (mip_level, level) in mip_chain(img.to_rgba8()).iter().enumerate() {
+                write_level(queue, &texture, level, mip_level as u32, layer as u32);
+            }
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            layer_count,
+        })
+    }
+}
+
+/// The layer `from_atlas` puts the tile at pixel `(x, y)` of an atlas `atlas_width` pixels wide
+pub const fn atlas_layer(x: u32, y: u32, tile_size: u32, atlas_width: u32) -> u32 {
+    (y / tile_size) * (atlas_width / tile_size) + x / tile_size
+}
+
+/// The number of levels in a full mip chain for a texture of the given size, down to 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Downsamples the image with a 2x2 box filter until it's 1x1. The first level is the image itself.
+fn mip_chain(img: image::RgbaImage) -> Vec<image::RgbaImage> {
+    let mut levels = vec![img];
+
+    while levels.len() < mip_level_count(levels[0].width(), levels[0].height()) as usize {
+        let previous = levels.last().unwrap();
+        let (width, height) = ((previous.width() / 2).max(1), (previous.height() / 2).max(1));
+
+        let level = image::RgbaImage::from_fn(width, height, |x, y| {
+            let mut sum = [0u32; 4];
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let sx = (x * 2 + dx).min(previous.width() - 1);
+                let sy = (y * 2 + dy).min(previous.height() - 1);
+                for (total, channel) in sum.iter_mut().zip(previous.get_pixel(sx, sy).0) {
+                    *total += channel as u32;
+                }
+            }
+            image::Rgba(sum.map(|total| (total / 4) as u8))
+        });
+
+        levels.push(level);
+    }
+
+    levels
+}
+
+fn write_level(queue: &wgpu::Queue, texture: &wgpu::Texture, level: &image::RgbaImage, mip_level: u32, layer: u32) {
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            aspect: wgpu::TextureAspect::All,
+            texture,
+            mip_level,
+            origin: wgpu::Origin3d {
+                x: 0,
+                y: 0,
+                z: layer,
+            },
+        },
+        level,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: std::num::NonZeroU32::new(4 * level.width()),
+            rows_per_image: std::num::NonZeroU32::new(level.height()),
+        },
+        wgpu::Extent3d {
+            width: level.width(),
+            height: level.height(),
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_mip_chain_of_a_256_pixel_texture_has_9_levels() {
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(256, 16), 9);
+        assert_eq!(mip_level_count(1, 1), 1);
+    }
+
+    #[test]
+    fn mip_chain_halves_every_level_down_to_1x1() {
+        let img = image::RgbaImage::from_pixel(16, 16, image::Rgba([200, 100, 50, 255]));
+        let levels = mip_chain(img);
+
+        assert_eq!(levels.len(), 5);
+        for (i, level) in levels.iter().enumerate() {
+            assert_eq!(level.dimensions(), (16 >> i, 16 >> i));
+        }
+        // A flat color stays the same color all the way down
+        assert_eq!(*levels.last().unwrap().get_pixel(0, 0), image::Rgba([200, 100, 50, 255]));
+    }
 }
@@ -0,0 +1,290 @@
+use encase::ShaderType;
+use wgpu::util::{align_to, DeviceExt};
+use wgpu::DynamicOffset;
+
+use crate::block::Tint;
+use crate::chunk::ChunkUniform;
+use crate::texture::TextureArray;
+
+/// The uniform buffer every chunk is drawn with, along with the block textures and the tint
+/// palette. Each loaded chunk gets its own slot, picked with a dynamic offset. Slots are handed out
+/// by a `SlotAllocator` and the buffer doubles in size when they run out.
+pub struct ChunkUniforms {
+    buffer: wgpu::Buffer,
+    /// `Tint::PALETTE`, which never changes
+    tint_palette: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    texture: TextureArray,
+
+    /// The size of `ChunkUniform` as the shader reads it
+    uniform_size: wgpu::BufferAddress,
+    slots: SlotAllocator,
+}
+
+impl ChunkUniforms {
+    pub fn new(texture: TextureArray, capacity: u32, device: &wgpu::Device) -> Self {
+        // The std140 size reported by `ShaderType` (not `mem::size_of`) is what the shader actually reads
+        let uniform_size = ChunkUniform::min_size().get().next_power_of_two() as wgpu::BufferAddress;
+        let slot_size = align_to(uniform_size, device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress);
+        let capacity = capacity.max(1);
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(uniform_size),
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: None,
+        });
+
+        let tint_palette = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tint Palette Buffer"),
+            contents: bytemuck::cast_slice(&Tint::PALETTE),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let slots = SlotAllocator::new(slot_size, capacity);
+        let buffer = ChunkUniforms::create_buffer(slot_size, capacity, device);
+        let bind_group = ChunkUniforms::create_bind_group(&layout, &buffer, &tint_palette, &texture, uniform_size, device);
+
+        Self {
+            buffer,
+            tint_palette,
+            layout,
+            bind_group,
+            texture,
+
+            uniform_size,
+            slots,
+        }
+    }
+
+    fn create_buffer(slot_size: wgpu::BufferAddress, capacity: u32, device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Uniform Buffer"),
+            size: capacity as wgpu::BufferAddress * slot_size,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+        tint_palette: &wgpu::Buffer,
+        texture: &TextureArray,
+        uniform_size: wgpu::BufferAddress,
+        device: &wgpu::Device,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(uniform_size),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: tint_palette.as_entire_binding(),
+                },
+            ],
+            label: None,
+        })
+    }
+
+    /// Takes a free slot and returns its offset, doubling the buffer if there aren't any left.
+    /// Growing replaces the buffer and bind group, so every chunk's uniforms have to be written
+    /// again before the next draw.
+    pub fn allocate(&mut self, device: &wgpu::Device) -> DynamicOffset {
+        match self.slots.allocate() {
+            Some(offset) => offset,
+            None => {
+                self.grow(self.slots.capacity() * 2, device);
+                self.slots.allocate().unwrap()
+            }
+        }
+    }
+
+    /// Hands a slot from `allocate` back
+    pub fn release(&mut self, offset: DynamicOffset) {
+        self.slots.release(offset);
+    }
+
+    fn grow(&mut self, capacity: u32, device: &wgpu::Device) {
+        log::debug!("growing the chunk uniform buffer from {} to {} slots", self.slots.capacity(), capacity);
+
+        self.buffer = ChunkUniforms::create_buffer(self.slots.slot_size(), capacity, device);
+        self.bind_group = ChunkUniforms::create_bind_group(&self.layout, &self.buffer, &self.tint_palette, &self.texture, self.uniform_size, device);
+        self.slots.grow(capacity);
+    }
+
+    pub fn write(&self, offset: DynamicOffset, uniform: &ChunkUniform, queue: &wgpu::Queue) {
+        let mut data = encase::UniformBuffer::new(Vec::new());
+        data.write(uniform).unwrap();
+
+        queue.write_buffer(&self.buffer, offset as wgpu::BufferAddress, &data.into_inner());
+    }
+
+    pub fn layout(&self) -> &wgpu::BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    /// How many slots the buffer has room for
+    pub fn capacity(&self) -> u32 {
+        self.slots.capacity()
+    }
+
+    /// How many slots are handed out
+    pub fn used(&self) -> u32 {
+        self.slots.used()
+    }
+}
+
+/// The bookkeeping behind `ChunkUniforms`' slots: a free list of offsets into a buffer of
+/// `capacity` evenly spaced slots. It never touches the buffer itself.
+pub struct SlotAllocator {
+    /// The distance between slots, at least the uniform's size and aligned to
+    /// `min_uniform_buffer_offset_alignment`
+    slot_size: wgpu::BufferAddress,
+    /// How many slots there are
+    capacity: u32,
+    /// The offsets of the slots no one is using, the next one handed out is at the end
+    free: Vec<DynamicOffset>,
+}
+
+impl SlotAllocator {
+    pub fn new(slot_size: wgpu::BufferAddress, capacity: u32) -> Self {
+        Self {
+            slot_size,
+            capacity,
+            free: SlotAllocator::slot_offsets(0..capacity, slot_size),
+        }
+    }
+
+    /// The offsets of the slots, reversed so the lowest one is handed out first
+    fn slot_offsets(slots: std::ops::Range<u32>, slot_size: wgpu::BufferAddress) -> Vec<DynamicOffset> {
+        slots.rev().map(|slot| (slot as wgpu::BufferAddress * slot_size) as DynamicOffset).collect()
+    }
+
+    /// Takes a free slot and returns its offset, or `None` if every slot is in use
+    pub fn allocate(&mut self) -> Option<DynamicOffset> {
+        self.free.pop()
+    }
+
+    /// Hands a slot from `allocate` back
+    pub fn release(&mut self, offset: DynamicOffset) {
+        debug_assert!(!self.free.contains(&offset), "chunk uniform slot {} was released twice", offset);
+        self.free.push(offset);
+    }
+
+    /// Adds slots up to `capacity`. The slots already free are still handed out before the new ones.
+    pub fn grow(&mut self, capacity: u32) {
+        let mut new_slots = SlotAllocator::slot_offsets(self.capacity..capacity, self.slot_size);
+        new_slots.append(&mut self.free);
+        self.free = new_slots;
+        self.capacity = capacity;
+    }
+
+    pub fn slot_size(&self) -> wgpu::BufferAddress {
+        self.slot_size
+    }
+
+    /// How many slots there are
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// How many slots are handed out
+    pub fn used(&self) -> u32 {
+        self.capacity - self.free.len() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_are_handed_out_lowest_first() {
+        let mut slots = SlotAllocator::new(256, 2);
+        assert_eq!(slots.allocate(), Some(0));
+        assert_eq!(slots.allocate(), Some(256));
+        assert_eq!(slots.allocate(), None);
+
+        slots.grow(4);
+        assert_eq!(slots.allocate(), Some(512));
+        slots.release(0);
+        assert_eq!(slots.allocate(), Some(0));
+        assert_eq!((slots.used(), slots.capacity()), (3, 4));
+    }
+
+    #[test]
+    fn allocating_and_releasing_does_not_leak_slots() {
+        let mut slots = SlotAllocator::new(256, 4);
+        let kept = (0..3).map(|_| slots.allocate().unwrap()).collect::<Vec<_>>();
+
+        for _ in 0..1000 {
+            let offset = slots.allocate().unwrap();
+            slots.release(offset);
+        }
+
+        assert_eq!(slots.used(), 3);
+        assert_eq!(slots.capacity(), 4);
+
+        for offset in kept {
+            slots.release(offset);
+        }
+        assert_eq!(slots.used(), 0);
+        assert_eq!(slots.capacity(), 4);
+    }
+}
@@ -0,0 +1,138 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Vector3, Vector4};
+use encase::ShaderType;
+use wgpu::util::DeviceExt;
+use wgpu::{BindGroup, RenderPass};
+
+use crate::chunk::Vertex;
+use crate::renderer;
+
+/// Every edge of a box is a separate line, two vertices each
+const BOX_VERTEX_COUNT: usize = 24;
+
+/// How far the box pokes out of the block it outlines, so the lines don't z-fight with its faces
+const BOX_INFLATE: f32 = 0.002;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LineVertex {
+    pub position: Vector3<f32>,
+}
+
+unsafe impl Pod for LineVertex {}
+
+unsafe impl Zeroable for LineVertex {}
+
+impl Vertex for LineVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        static ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![0 => Float32x3];
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<LineVertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &ATTRIBS,
+        }
+    }
+}
+
+#[derive(ShaderType, Debug, Copy, Clone, PartialEq)]
+pub struct WireframeUniform {
+    pub color: Vector4<f32>,
+}
+
+/// The outline of a box, drawn as a line list in render space. Used to highlight the block the
+/// camera is looking at.
+#[derive(Debug)]
+pub struct WireframeMesh {
+    vertex_buffer: wgpu::Buffer,
+}
+
+impl WireframeMesh {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Wireframe Vertex Buffer"),
+            contents: bytemuck::cast_slice(&WireframeMesh::box_vertices(Vector3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 1.0, 1.0))),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        });
+
+        Self { vertex_buffer }
+    }
+
+    /// Moves the outline around the block centered on `center`
+    pub fn set_block(&self, queue: &wgpu::Queue, center: Vector3<f32>) {
+        let half = 0.5 + BOX_INFLATE;
+        let vertices = WireframeMesh::box_vertices(
+            center - Vector3::new(half, half, half),
+            center + Vector3::new(half, half, half),
+        );
+
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+
+    fn box_vertices(min: Vector3<f32>, max: Vector3<f32>) -> [LineVertex; BOX_VERTEX_COUNT] {
+        let corner = |i: usize| LineVertex {
+            position: Vector3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            ),
+        };
+
+        // Each edge joins two corners whose indices differ in a single bit
+        let edges = [
+            (0, 1), (2, 3), (4, 5), (6, 7),
+            (0, 2), (1, 3), (4, 6), (5, 7),
+            (0, 4), (1, 5), (2, 6), (3, 7),
+        ];
+
+        let mut vertices = [corner(0); BOX_VERTEX_COUNT];
+        for (i, (a, b)) in edges.iter().enumerate() {
+            vertices[i * 2] = corner(*a);
+            vertices[i * 2 + 1] = corner(*b);
+        }
+
+        vertices
+    }
+}
+
+impl renderer::Draw for WireframeMesh {
+    fn draw<'a>(&'a self, render_pass: &mut RenderPass<'a>, camera_bind_group: &'a BindGroup, uniforms: &'a BindGroup) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_bind_group(0, camera_bind_group, &[]);
+        render_pass.set_bind_group(1, uniforms, &[]);
+        render_pass.draw(0..BOX_VERTEX_COUNT as u32, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_vertices_are_the_12_edges_of_the_inflated_box() {
+        let half = 0.5 + BOX_INFLATE;
+        let center = Vector3::new(3.0, -2.0, 7.0);
+        let vertices = WireframeMesh::box_vertices(center - Vector3::new(half, half, half), center + Vector3::new(half, half, half));
+        assert_eq!(vertices.len(), 24);
+
+        let mut edges = Vec::new();
+        for line in vertices.chunks(2) {
+            let (a, b) = (line[0].position - center, line[1].position - center);
+
+            // Both ends are corners of the box, and they differ along exactly one axis
+            for end in [a, b] {
+                for axis in [end.x, end.y, end.z] {
+                    assert!((axis.abs() - half).abs() < 1e-6);
+                }
+            }
+            let differing = [a.x != b.x, a.y != b.y, a.z != b.z].iter().filter(|differs| **differs).count();
+            assert_eq!(differing, 1);
+
+            let (a, b) = ([a.x > 0.0, a.y > 0.0, a.z > 0.0], [b.x > 0.0, b.y > 0.0, b.z > 0.0]);
+            edges.push(if a < b { (a, b) } else { (b, a) });
+        }
+
+        edges.sort();
+        edges.dedup();
+        assert_eq!(edges.len(), 12);
+    }
+}
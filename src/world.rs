@@ -1,193 +1,1802 @@
-use cgmath::{Vector2, ElementWise, Vector3};
-use hashbrown::HashMap;
-use crate::{chunk::{Chunk, ChunkMesh, Direction, self}, block::Block};
+use std::collections::VecDeque;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use cgmath::{Vector2, Vector3, Point3, EuclideanSpace, InnerSpace};
+use hashbrown::{HashMap, HashSet};
+use instant::Instant;
+use rand::Rng;
+use rayon::prelude::*;
+use crate::{chunk::{Chunk, ChunkMesh, ChunkMeshData, Direction, MeshStats, self}, block::Block};
+use crate::camera::CameraState;
+use crate::loader::ChunkLoader;
+use crate::registry::TextureTable;
+use crate::save::{self, SaveError, SaveHeader, SavedChunk};
+use crate::terrain::{self, WorldGenerator, WorldSeed};
+use crate::uniforms::ChunkUniforms;
+
+/// A snapshot of a chunk and its horizontal neighbors, sent to the mesher thread. The chunks are
+/// shared with the world, see `LoadedChunk::chunk`.
+struct MeshJob {
+    chunk_location: Vector2<i32>,
+    id: u64,
+    chunk: Arc<Chunk>,
+    neighbors: [Option<Arc<Chunk>>; 4],
+}
+
+struct FinishedMesh {
+    chunk_location: Vector2<i32>,
+    id: u64,
+    data: ChunkMeshData,
+}
+
+/// Why a block couldn't be set by its world space position
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetBlockError {
+    /// The chunk at this location isn't loaded
+    Unloaded(Vector2<i32>),
+    /// The position is above or below the chunks
+    OutOfBounds(Vector3<i32>),
+}
+
+impl fmt::Display for SetBlockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SetBlockError::Unloaded(location) => write!(f, "chunk {:?} isn't loaded", location),
+            SetBlockError::OutOfBounds(position) => write!(f, "block position {:?} is outside of the world's height", position),
+        }
+    }
+}
+
+impl std::error::Error for SetBlockError {}
+
+/// A chunk along with the mesh it's drawn with
+pub struct LoadedChunk {
+    /// Shared with the mesh jobs snapshotting it, so queueing a remesh doesn't copy any blocks.
+    /// Edits go through `Arc::make_mut`, which only copies the chunk while a job still has it.
+    pub chunk: Arc<Chunk>,
+    pub mesh: ChunkMesh,
+    /// When the chunk was streamed in by a `ChunkLoader`. It fades in from then over
+    /// `FADE_IN_TIME`, chunks loaded any other way show up right away.
+    pub streamed_in: Option<Instant>,
+}
+
+/// How long a streamed in chunk takes to fade in
+pub const FADE_IN_TIME: Duration = Duration::from_millis(500);
+
+impl LoadedChunk {
+    /// How opaque the chunk is drawn at `now`
+    pub fn fade(&self, now: Instant) -> f32 {
+        fade_since(self.streamed_in, now)
+    }
+}
+
+/// Ramps linearly from transparent at `start` to opaque `FADE_IN_TIME` later
+fn fade_since(start: Option<Instant>, now: Instant) -> f32 {
+    match start {
+        Some(start) => (now.saturating_duration_since(start).as_secs_f32() / FADE_IN_TIME.as_secs_f32()).min(1.0),
+        None => 1.0,
+    }
+}
+
+/// The block a ray hit, in world space, and the face it entered through
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockHit {
+    pub position: Vector3<i32>,
+    pub face: Direction,
+    /// Where the ray entered the block, in render space like the ray's start
+    pub point: Point3<f32>,
+    /// How far the ray went before it hit the block
+    pub distance: f32,
+}
+
+/// Keeps boxes that only touch from counting as overlapping
+const COLLISION_EPSILON: f32 = 1e-4;
+
+/// An axis aligned box, in render space unless it says otherwise
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// A whole block, relative to its center
+    pub fn block() -> Self {
+        Self::new(Point3::new(-0.5, -0.5, -0.5), Point3::new(0.5, 0.5, 0.5))
+    }
+
+    pub fn translated(&self, offset: Vector3<f32>) -> Self {
+        Self::new(self.min + offset, self.max + offset)
+    }
+
+    /// Whether the boxes overlap on the given axis, not counting boxes that only touch
+    fn overlaps_on(&self, other: &Aabb, axis: usize) -> bool {
+        self.min[axis] < other.max[axis] - COLLISION_EPSILON && other.min[axis] < self.max[axis] - COLLISION_EPSILON
+    }
+
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.overlaps_on(other, axis))
+    }
+
+    fn translate(&mut self, axis: usize, amount: f32) {
+        self.min[axis] += amount;
+        self.max[axis] += amount;
+    }
+}
+
+/// How far a box could actually move, and which axes it was stopped on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collision {
+    pub displacement: Vector3<f32>,
+    pub blocked: [bool; 3],
+}
+
+/// How many blocks get to react to their neighbors changing after a single edit, so blocks that
+/// keep changing each other can't go on forever
+pub const NEIGHBOR_UPDATE_BUDGET: usize = 512;
+
+/// The position of a block to update and the position of its neighbor that changed
+type BlockUpdate = (Vector3<i32>, Vector3<i32>);
+
+/// What a block can do to the world while reacting to a neighbor changing, see
+/// `BlockData::on_neighbor_changed`. The world can't be changed while a block is looking at it,
+/// so edits and updates are collected here and applied by `World::set_block` once the block is
+/// done. Positions are in world space.
+pub struct WorldAccess<'a> {
+    world: &'a World,
+    changes: BlockChanges,
+}
+
+/// What a block did through a `WorldAccess`
+#[derive(Default)]
+struct BlockChanges {
+    edits: Vec<(Vector3<i32>, Block)>,
+    scheduled: Vec<Vector3<i32>>,
+}
+
+impl<'a> WorldAccess<'a> {
+    fn new(world: &'a World) -> Self {
+        Self {
+            world,
+            changes: BlockChanges::default(),
+        }
+    }
+
+    /// The block at the position, including edits made through this that aren't applied yet
+    pub fn get_block(&self, position: Vector3<i32>) -> Option<&Block> {
+        self.changes
+            .edits
+            .iter()
+            .rev()
+            .find(|(edit_position, _)| *edit_position == position)
+            .map(|(_, block)| block)
+            .or_else(|| self.world.get_block_global(position))
+    }
+
+    /// Sets the block once the current update is done. Its neighbors get updated like for any
+    /// other edit.
+    pub fn set_block(&mut self, position: Vector3<i32>, block: Block) {
+        self.changes.edits.push((position, block));
+    }
+
+    /// Updates the block at the position again after the current update, as if one of its
+    /// neighbors changed
+    pub fn schedule_update(&mut self, position: Vector3<i32>) {
+        self.changes.scheduled.push(position);
+    }
+
+    fn into_changes(self) -> BlockChanges {
+        self.changes
+    }
+}
+
+/// How many chunks the camera can get from the render origin before it's moved, see
+/// `World::rebase_origin`. At 16 chunks positions are still accurate to about 1/30000 of a block.
+pub const REBASE_DISTANCE: i32 = 16;
+
+/// How many chunks past the render distance chunks stay loaded, so going back and forth across a
+/// chunk border doesn't keep unloading and reloading the same row of chunks
+pub const UNLOAD_MARGIN: u32 = 1;
 
-#[derive(Clone)]
 pub struct World {
-    chunk_map: HashMap<Vector2<i32>, usize>,
-    chunks: Vec<Chunk>,
-    chunk_meshes: Vec<ChunkMesh>,
+    chunks: HashMap<Vector2<i32>, LoadedChunk>,
+    /// The chunk everything is rendered relative to
+    origin: Vector2<i32>,
+
+    /// The id of the most recently queued mesh job for each chunk that's waiting on one
+    pending_meshes: HashMap<Vector2<i32>, u64>,
+    next_job_id: u64,
+    job_sender: Sender<MeshJob>,
+    finished_receiver: Receiver<FinishedMesh>,
+
+    textures: Arc<TextureTable>,
+    /// Every loaded chunk has a slot in here, which is handed back when it's unloaded
+    uniforms: ChunkUniforms,
 }
 
 impl World {
-    pub fn new() -> Self {
+    /// Creates an empty world. `textures` overrides the built-in blocks' textures when meshing,
+    /// and the chunks are drawn with `uniforms`.
+    pub fn new(textures: TextureTable, uniforms: ChunkUniforms) -> Self {
+        let textures = Arc::new(textures);
+        let (job_sender, job_receiver) = mpsc::channel::<MeshJob>();
+        let (finished_sender, finished_receiver) = mpsc::channel();
+
+        // The mesher thread exits once the world (and with it `job_sender`) is dropped
+        let mesher_textures = textures.clone();
+        thread::Builder::new()
+            .name("chunk mesher".to_string())
+            .spawn(move || {
+                for job in job_receiver {
+                    let neighbors = job.neighbors.each_ref().map(Option::as_deref);
+                    let mut data = ChunkMeshData::from_chunk(&job.chunk, neighbors, &mesher_textures);
+                    data.compact();
+
+                    let finished = FinishedMesh {
+                        chunk_location: job.chunk_location,
+                        id: job.id,
+                        data,
+                    };
+
+                    if finished_sender.send(finished).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Unable to spawn chunk mesher thread");
+
         Self {
-            chunk_map: HashMap::new(),
-            chunks: Vec::new(),
-            chunk_meshes: Vec::new(),
+            chunks: HashMap::new(),
+            origin: Vector2::new(0, 0),
+
+            pending_meshes: HashMap::new(),
+            next_job_id: 0,
+            job_sender,
+            finished_receiver,
+
+            textures,
+            uniforms,
+        }
+    }
+
+    pub fn new_chunk(&mut self, chunk_location: Vector2<i32>, device: &wgpu::Device) -> Vector2<i32> {
+        self.insert_chunk(Chunk::new(chunk_location), device)
+    }
+
+    /// Creates a chunk filled by `f` (see `Chunk::generate`) and queues its mesh, along with the
+    /// meshes of any loaded neighbors whose border faces it now hides.
+    pub fn new_chunk_with(
+        &mut self,
+        chunk_location: Vector2<i32>,
+        device: &wgpu::Device,
+        f: impl Fn(Vector3<i32>) -> Block,
+    ) -> Vector2<i32> {
+        self.add_generated_chunk(Chunk::generate(chunk_location, f), device)
+    }
+
+    /// Like `new_chunk_with`, but the chunk is filled by a world generator
+    pub fn new_chunk_generated(
+        &mut self,
+        chunk_location: Vector2<i32>,
+        device: &wgpu::Device,
+        generator: &dyn WorldGenerator,
+    ) -> Vector2<i32> {
+        self.add_generated_chunk(generator.generate_chunk(chunk_location), device)
+    }
+
+    fn add_generated_chunk(&mut self, chunk: Chunk, device: &wgpu::Device) -> Vector2<i32> {
+        let chunk_location = chunk.world_offset;
+        log::debug!("generated chunk {:?}, blocks take up {} bytes", chunk_location, chunk.block_memory());
+
+        self.insert_chunk(chunk, device);
+
+        self.queue_remesh(chunk_location);
+        self.queue_neighbor_remeshes(chunk_location);
+
+        chunk_location
+    }
+
+    /// Generates and meshes many chunks at once, spreading the work over rayon's thread pool.
+    /// `f` picks each block from the chunk's location and the block's position in it, like
+    /// `Chunk::generate`. Only the uploads happen on this thread, so the meshes are ready when this
+    /// returns. Loaded chunks next to the batch get a remesh queued, since their border faces may
+    /// now be hidden.
+    pub fn generate_chunks_parallel(
+        &mut self,
+        chunks: &[Vector2<i32>],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        f: impl Fn(Vector2<i32>, Vector3<i32>) -> Block + Sync,
+    ) {
+        self.load_chunks_parallel(chunks, device, queue, |location| {
+            Chunk::generate(location, |position| f(location, position))
+        });
+    }
+
+    /// Writes every loaded chunk to region files in the `dir` folder, along with the seed the
+    /// world was generated with and the camera, see `save::write`
+    pub fn save(&self, dir: &Path, seed: WorldSeed, camera: CameraState) -> Result<(), SaveError> {
+        // The meshes can't leave this thread, so only the blocks are handed to rayon
+        let loaded_chunks = self.chunks.iter()
+            .map(|(location, loaded)| (*location, &*loaded.chunk))
+            .collect::<Vec<_>>();
+        let saved = loaded_chunks
+            .par_iter()
+            .map(|(location, chunk)| (*location, SavedChunk::from_chunk(chunk)))
+            .collect::<Vec<_>>();
+
+        let header = SaveHeader {
+            seed: seed.0,
+            origin: self.origin.into(),
+            camera,
+        };
+
+        save::write(dir, &header, &saved)
+    }
+
+    /// Replaces every chunk with the ones in a folder written by `save` and meshes them. The
+    /// header is returned as well, to restore the camera and keep generating with the same seed.
+    /// The world is left as it was if the save can't be loaded.
+    pub fn load(&mut self, dir: &Path, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<SaveHeader, SaveError> {
+        let (header, saved) = save::read(dir)?;
+
+        let chunk_count = saved.len();
+        let chunks = saved
+            .par_iter()
+            .map(|(location, saved)| saved.to_chunk(*location).map(|chunk| (*location, chunk)))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        if chunks.len() != chunk_count {
+            return Err(SaveError::Corrupt("the same chunk is stored more than once".to_string()));
         }
+
+        // Any mesh still in flight is for a chunk that's about to be gone, so it's dropped when it
+        // finishes
+        for (_, loaded) in self.chunks.drain() {
+            self.uniforms.release(loaded.mesh.uniform_offset);
+        }
+        self.pending_meshes.clear();
+        self.origin = header.origin.into();
+
+        let locations = chunks.keys().copied().collect::<Vec<_>>();
+        self.load_chunks_parallel(&locations, device, queue, |location| chunks[&location].clone());
+
+        Ok(header)
+    }
+
+    /// Like `generate_chunks_parallel`, but the chunks are filled by a world generator
+    pub fn generate_terrain_parallel(
+        &mut self,
+        chunks: &[Vector2<i32>],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        generator: &dyn WorldGenerator,
+    ) {
+        self.load_chunks_parallel(chunks, device, queue, |location| generator.generate_chunk(location));
+    }
+
+    /// Creates every chunk with `generate` and meshes them, see `generate_chunks_parallel`
+    fn load_chunks_parallel(
+        &mut self,
+        chunks: &[Vector2<i32>],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        generate: impl Fn(Vector2<i32>) -> Chunk + Sync,
+    ) {
+        let generated = chunks
+            .par_iter()
+            .map(|location| generate(*location))
+            .collect::<Vec<_>>();
+
+        for chunk in generated {
+            log::debug!("generated chunk {:?}, blocks take up {} bytes", chunk.world_offset, chunk.block_memory());
+            self.insert_chunk(chunk, device);
+        }
+
+        let meshes = {
+            // The meshes hold GPU buffers that can't leave this thread, so the mesher only gets
+            // to see the blocks
+            let loaded_chunks = self.chunks.iter()
+                .map(|(location, loaded)| (*location, &*loaded.chunk))
+                .collect::<HashMap<_, _>>();
+            let textures = &*self.textures;
+
+            chunks
+                .par_iter()
+                .map(|location| {
+                    let neighbors = World::neighbor_locations(*location)
+                        .map(|neighbor| loaded_chunks.get(&neighbor).copied());
+
+                    let mut data = ChunkMeshData::from_chunk(loaded_chunks[location], neighbors, textures);
+                    data.compact();
+
+                    (*location, data)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for (location, data) in meshes {
+            self.pending_meshes.remove(&location);
+            self.chunks.get_mut(&location).unwrap().mesh.upload(data, device, queue);
+        }
+
+        let batch = chunks.iter().copied().collect::<HashSet<_>>();
+        let outside = batch.iter()
+            .flat_map(|location| World::neighbor_locations(*location))
+            .filter(|neighbor| !batch.contains(neighbor) && self.chunks.contains_key(neighbor))
+            .collect::<HashSet<_>>();
+
+        for location in outside {
+            self.queue_remesh(location);
+        }
+    }
+
+    /// The locations of the chunks in front, behind, left and right of a chunk, in the order
+    /// `ChunkMeshData::from_chunk` takes them
+    fn neighbor_locations(chunk_location: Vector2<i32>) -> [Vector2<i32>; 4] {
+        [Direction::FRONT, Direction::BACK, Direction::LEFT, Direction::RIGHT].map(|face| {
+            let face_vec = face.to_vec3();
+            chunk_location + Vector2::new(face_vec.x, face_vec.z)
+        })
     }
 
-    pub fn new_chunk(&mut self, chunk_location: Vector2<i32>, uniform_offset: u32, device: &wgpu::Device) -> usize {
-        let chunk = Chunk::new(chunk_location);
-        let chunk_mesh = ChunkMesh::new(uniform_offset, device);
+    /// Inserts a chunk that was meshed without its neighbors, like the ones from a `ChunkLoader`,
+    /// and uploads the mesh. It's remeshed if any neighbor is loaded, since that hides some of its
+    /// border faces, and so are the neighbors.
+    pub fn insert_meshed_chunk(
+        &mut self,
+        chunk: Chunk,
+        data: ChunkMeshData,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Vector2<i32> {
+        let chunk_location = self.insert_chunk(chunk, device);
+        let loaded = self.chunks.get_mut(&chunk_location).unwrap();
+        loaded.mesh.upload(data, device, queue);
+        loaded.streamed_in = Some(Instant::now());
+        self.pending_meshes.remove(&chunk_location);
+
+        if World::neighbor_locations(chunk_location).iter().any(|neighbor| self.chunks.contains_key(neighbor)) {
+            self.queue_remesh(chunk_location);
+            self.queue_neighbor_remeshes(chunk_location);
+        }
+
+        chunk_location
+    }
 
-        self.chunks.push(chunk);
-        self.chunk_meshes.push(chunk_mesh);
+    /// Inserts a chunk at its `world_offset` with a new uniform slot, replacing any chunk that was
+    /// already there
+    fn insert_chunk(&mut self, chunk: Chunk, device: &wgpu::Device) -> Vector2<i32> {
+        let chunk_location = chunk.world_offset;
+        let mesh = ChunkMesh::new(self.uniforms.allocate(device), device);
 
-        if self.chunks.len() != self.chunk_meshes.len() {
-            eprintln!("chunk vec and chunk mesh vec have different sizes!");
+        if let Some(replaced) = self.chunks.insert(chunk_location, LoadedChunk { chunk: Arc::new(chunk), mesh, streamed_in: None }) {
+            self.uniforms.release(replaced.mesh.uniform_offset);
         }
 
-        let index = self.chunks.len() - 1;
+        chunk_location
+    }
+
+    /// Unloads a chunk, freeing its mesh and uniform slot, and queues remeshes of its neighbors,
+    /// whose border faces are no longer hidden by it
+    pub fn remove_chunk(&mut self, chunk_location: Vector2<i32>) -> Option<Chunk> {
+        let loaded = self.chunks.remove(&chunk_location)?;
+        self.uniforms.release(loaded.mesh.uniform_offset);
 
-        self.chunk_map.insert(chunk_location, index);
+        // Any mesh still in flight for it is dropped when it finishes
+        self.pending_meshes.remove(&chunk_location);
+        self.queue_neighbor_remeshes(chunk_location);
 
-        index
+        Some(Arc::unwrap_or_clone(loaded.chunk))
     }
 
-    pub fn get_chunk_index_by_offset(&self, offset: Vector2<i32>) -> Option<usize> {
-        self.chunk_map.get(&offset).copied()
+    /// Every chunk location within `render_distance` chunks of `center` on both axes, closest
+    /// first
+    pub fn chunks_in_range(center: Vector2<i32>, render_distance: u32) -> Vec<Vector2<i32>> {
+        let distance = render_distance as i32;
+        let mut locations = (-distance..=distance)
+            .flat_map(|x| (-distance..=distance).map(move |y| center + Vector2::new(x, y)))
+            .collect::<Vec<_>>();
+        locations.sort_by_key(|location| {
+            let offset = location - center;
+            offset.x * offset.x + offset.y * offset.y
+        });
+
+        locations
     }
 
-    pub fn get_chunk_by_offset(&self, offset: Vector2<i32>) -> Option<(&Chunk, &ChunkMesh)> {
-        match self.get_chunk_index_by_offset(offset) {
-            Some(expr) => self.get_chunk(expr),
-            None => None,
+    /// The chunks in range of `center` that aren't loaded yet, closest first
+    pub fn chunks_to_load(&self, center: Vector2<i32>, render_distance: u32) -> Vec<Vector2<i32>> {
+        World::chunks_in_range(center, render_distance)
+            .into_iter()
+            .filter(|location| !self.chunks.contains_key(location))
+            .collect()
+    }
+
+    /// The loaded chunks that are out of range of `center`
+    pub fn chunks_out_of_range(&self, center: Vector2<i32>, render_distance: u32) -> Vec<Vector2<i32>> {
+        let distance = render_distance as i32;
+
+        self.chunks
+            .keys()
+            .filter(|location| {
+                let offset = *location - center;
+                offset.x.abs().max(offset.y.abs()) > distance
+            })
+            .copied()
+            .collect()
+    }
+
+    /// Loads the chunks within `render_distance` chunks of the camera and unloads the ones more
+    /// than `UNLOAD_MARGIN` further away. Missing chunks are requested from the loader, which adds
+    /// a few of the finished ones each call, so this should be called every frame.
+    /// `camera_position` is in render space. Returns how many chunks were added.
+    pub fn update_streaming(
+        &mut self,
+        camera_position: Point3<f32>,
+        render_distance: u32,
+        loader: &mut ChunkLoader,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> usize {
+        let center = self.origin + Vector2::new(
+            (camera_position.x / chunk::CHUNK_WIDTH as f32).floor() as i32,
+            (camera_position.z / chunk::CHUNK_DEPTH as f32).floor() as i32,
+        );
+
+        for location in self.chunks_out_of_range(center, render_distance + UNLOAD_MARGIN) {
+            self.remove_chunk(location);
+        }
+
+        loader.cancel_out_of_range(center, render_distance + UNLOAD_MARGIN);
+        for location in self.chunks_to_load(center, render_distance) {
+            loader.request(location);
         }
+
+        loader.upload_finished(self, device, queue)
     }
 
-    pub fn get_chunk(&self, chunk_index: usize) -> Option<(&Chunk, &ChunkMesh)> {
-        match (self.chunks.get(chunk_index), self.chunk_meshes.get(chunk_index)) {
-            (None, None) | (None, Some(_)) | (Some(_), None) => None,
-            (Some(chunk), Some(mesh)) => Some((chunk, mesh)),
+    fn queue_neighbor_remeshes(&mut self, chunk_location: Vector2<i32>) {
+        for face in [Direction::FRONT, Direction::BACK, Direction::LEFT, Direction::RIGHT] {
+            let face_vec = face.to_vec3();
+            let neighbor = chunk_location + Vector2::new(face_vec.x, face_vec.z);
+
+            if self.chunks.contains_key(&neighbor) {
+                self.queue_remesh(neighbor);
+            }
         }
     }
 
-    pub fn get_chunk_mut(&mut self, chunk_index: usize) -> Option<(&mut Chunk, &mut ChunkMesh)> {
-        match (self.chunks.get_mut(chunk_index), self.chunk_meshes.get_mut(chunk_index)) {
-            (None, None) | (None, Some(_)) | (Some(_), None) => None,
-            (Some(chunk), Some(mesh)) => Some((chunk, mesh))
+    /// The uniform buffer and bind group the chunks are drawn with
+    pub fn uniforms(&self) -> &ChunkUniforms {
+        &self.uniforms
+    }
+
+    /// The textures the world meshes with, to mesh chunks the same way elsewhere
+    pub fn textures(&self) -> Arc<TextureTable> {
+        self.textures.clone()
+    }
+
+    pub fn origin(&self) -> Vector2<i32> {
+        self.origin
+    }
+
+    /// Moves the render origin to another chunk, so that rendering far from spawn doesn't lose
+    /// float precision. Chunks keep their `world_offset`, only their model matrices (see
+    /// `Chunk::model_matrix`) change, so nothing gets remeshed. Returns how far render space
+    /// shifted, which has to be subtracted from the camera position.
+    pub fn rebase_origin(&mut self, new_origin: Vector2<i32>) -> Vector3<f32> {
+        let shift = new_origin - self.origin;
+        self.origin = new_origin;
+
+        Vector3::new(
+            (shift.x * chunk::CHUNK_WIDTH as i32) as f32,
+            0.0,
+            (shift.y * chunk::CHUNK_DEPTH as i32) as f32,
+        )
+    }
+
+    /// The world space block position render space is centered on
+    fn origin_offset(&self) -> Vector3<i32> {
+        Vector3::new(
+            self.origin.x * chunk::CHUNK_WIDTH as i32,
+            0,
+            self.origin.y * chunk::CHUNK_DEPTH as i32,
+        )
+    }
+
+    /// Converts a world space block position into render space
+    pub fn to_render_space(&self, position: Vector3<i32>) -> Vector3<f32> {
+        (position - self.origin_offset()).cast().unwrap()
+    }
+
+    pub fn get_chunk(&self, chunk_location: Vector2<i32>) -> Option<(&Chunk, &ChunkMesh)> {
+        self.chunks.get(&chunk_location).map(|loaded| (&*loaded.chunk, &loaded.mesh))
+    }
+
+    /// How opaque the chunk at `chunk_location` is drawn at `now`, see `LoadedChunk::fade`
+    pub fn fade(&self, chunk_location: Vector2<i32>, now: Instant) -> f32 {
+        self.chunks.get(&chunk_location).map_or(1.0, |loaded| loaded.fade(now))
+    }
+
+    pub fn get_chunk_mut(&mut self, chunk_location: Vector2<i32>) -> Option<(&mut Chunk, &mut ChunkMesh)> {
+        self.chunks.get_mut(&chunk_location).map(|loaded| (Arc::make_mut(&mut loaded.chunk), &mut loaded.mesh))
+    }
+
+    /// Resolves a position relative to a chunk into the index of the chunk that actually contains
+    /// it and the position local to that chunk. Positions outside the chunk's x/z range land in
+    /// the neighboring chunk (including diagonal ones), which has to be loaded.
+    fn resolve_relative(&self, chunk_location: Vector2<i32>, position: Vector3<i32>) -> Option<(Vector2<i32>, Vector3<i32>)> {
+        let (offset, local_position) = World::world_to_chunk(position);
+        let location = chunk_location + offset;
+
+        self.chunks.contains_key(&location).then_some((location, local_position))
+    }
+
+    /// Splits a world space block position into the offset of the chunk containing it and the
+    /// position local to that chunk
+    fn world_to_chunk(position: Vector3<i32>) -> (Vector2<i32>, Vector3<i32>) {
+        let offset = Vector2::new(
+            position.x.div_euclid(chunk::CHUNK_WIDTH as i32),
+            position.z.div_euclid(chunk::CHUNK_DEPTH as i32),
+        );
+        let local_position = Vector3::new(
+            position.x.rem_euclid(chunk::CHUNK_WIDTH as i32),
+            position.y,
+            position.z.rem_euclid(chunk::CHUNK_DEPTH as i32),
+        );
+
+        (offset, local_position)
+    }
+
+    /// Gets a block by its position relative to a chunk, even if the position is outside of it.
+    /// Returns `None` if the chunk the position falls in isn't loaded, or y is out of range.
+    pub fn get_block_relative(&self, chunk_location: Vector2<i32>, position: Vector3<i32>) -> Option<&Block> {
+        let (location, local_position) = self.resolve_relative(chunk_location, position)?;
+        self.chunks.get(&location)?.chunk.get_block(local_position)
+    }
+
+    /// Gets a block by its world space position. Returns `None` if its chunk isn't loaded.
+    pub fn get_block_global(&self, position: Vector3<i32>) -> Option<&Block> {
+        let (location, local_position) = World::world_to_chunk(position);
+        self.chunks.get(&location)?.chunk.get_block(local_position)
+    }
+
+    /// Walks the blocks along a ray until it hits a solid one, or has gone `max_distance`.
+    /// `start` is in render space (see `rebase_origin`), the hit position is in world space. The
+    /// block the ray starts in is skipped, so it never hits the block the camera is inside of.
+    pub fn raycast(&self, start: Point3<f32>, direction: Vector3<f32>, max_distance: f32) -> Option<BlockHit> {
+        let origin_offset = self.origin_offset();
+        let solid = |cell| self.get_block_global(cell + origin_offset).is_some_and(|block| block.is_solid());
+
+        World::raycast_cells(start, direction, max_distance, solid)
+            .map(|hit| BlockHit { position: hit.position + origin_offset, ..hit })
+    }
+
+    /// The grid traversal behind `raycast`, with `solid` telling which render space cells stop
+    /// the ray. The hit position is in render space too.
+    fn raycast_cells(
+        start: Point3<f32>,
+        direction: Vector3<f32>,
+        max_distance: f32,
+        solid: impl Fn(Vector3<i32>) -> bool,
+    ) -> Option<BlockHit> {
+        let direction = direction.normalize();
+        let ray_start = start;
+
+        // Blocks are centered on integer positions, so shift by half a block to get cells that
+        // span [n, n + 1)
+        let start = start.to_vec() + Vector3::new(0.5, 0.5, 0.5);
+        let mut cell = start.map(|v| v.floor() as i32);
+
+        let step = direction.map(|d| if d > 0.0 { 1 } else if d < 0.0 { -1 } else { 0 });
+        let t_delta = direction.map(|d| if d != 0.0 { 1.0 / d.abs() } else { f32::INFINITY });
+        let mut t_max = Vector3::new(0, 1, 2).map(|axis| match step[axis] {
+            1 => (cell[axis] as f32 + 1.0 - start[axis]) * t_delta[axis],
+            -1 => (start[axis] - cell[axis] as f32) * t_delta[axis],
+            _ => f32::INFINITY,
+        });
+
+        loop {
+            let axis = if t_max.x < t_max.y && t_max.x < t_max.z {
+                0
+            } else if t_max.y < t_max.z {
+                1
+            } else {
+                2
+            };
+
+            let distance = t_max[axis];
+            if distance > max_distance {
+                return None;
+            }
+
+            cell[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+
+            if solid(cell) {
+                // The ray enters the block through the face pointing back along it
+                let face = match (axis, step[axis]) {
+                    (0, 1) => Direction::LEFT,
+                    (0, _) => Direction::RIGHT,
+                    (1, 1) => Direction::BOTTOM,
+                    (1, _) => Direction::TOP,
+                    (_, 1) => Direction::BACK,
+                    _ => Direction::FRONT,
+                };
+
+                return Some(BlockHit {
+                    position: cell,
+                    face,
+                    point: ray_start + direction * distance,
+                    distance,
+                });
+            }
         }
     }
 
-    pub fn set_block(&mut self, chunk_index: usize, position: Vector3<i32>, block: Block) {
-        let chunk = match self.chunks.get_mut(chunk_index) {
-            Some(chunk) => chunk,
+    /// The collision boxes (see `BlockData::collision_box`) of the blocks in every cell `region`
+    /// reaches into, in render space. Blocks are looked up by world position, so regions spanning
+    /// a chunk border see both chunks. Unloaded chunks don't collide.
+    fn collision_boxes(&self, region: Aabb) -> impl Iterator<Item = Aabb> + '_ {
+        let origin_offset = self.origin_offset();
+
+        // Blocks are centered on integer positions, so a block at n spans [n - 0.5, n + 0.5]
+        let first = region.min.map(|v| (v + 0.5 + COLLISION_EPSILON).floor() as i32);
+        let last = region.max.map(|v| (v + 0.5 - COLLISION_EPSILON).floor() as i32);
+
+        (first.x..=last.x)
+            .flat_map(move |x| (first.y..=last.y).map(move |y| (x, y)))
+            .flat_map(move |(x, y)| (first.z..=last.z).map(move |z| Vector3::new(x, y, z)))
+            .filter_map(move |cell| {
+                let collision_box = self.get_block_global(cell + origin_offset)?.collision_box()?;
+                Some(collision_box.translated(cell.cast().unwrap()))
+            })
+    }
+
+    /// Whether the render space box overlaps the collision box of any block
+    pub fn collides(&self, aabb: &Aabb) -> bool {
+        self.collision_boxes(*aabb).any(|collision_box| collision_box.intersects(aabb))
+    }
+
+    /// Moves `aabb` by `velocity` (a displacement, already scaled by the frame time) until it runs
+    /// into a block's collision box. Each axis is swept separately, y first, so landing on the
+    /// ground doesn't stop sliding along it. Boxes spanning a chunk border collide with both
+    /// chunks, unloaded chunks don't collide.
+    pub fn resolve_collision(&self, aabb: Aabb, velocity: Vector3<f32>) -> Collision {
+        let mut aabb = aabb;
+        let mut collision = Collision {
+            displacement: Vector3::new(0.0, 0.0, 0.0),
+            blocked: [false; 3],
+        };
+
+        for axis in [1, 0, 2] {
+            let wanted = velocity[axis];
+            if wanted == 0.0 {
+                continue;
+            }
+
+            let mut swept = aabb;
+            if wanted > 0.0 {
+                swept.max[axis] += wanted;
+            } else {
+                swept.min[axis] += wanted;
+            }
+
+            let mut moved = wanted;
+            for block_box in self.collision_boxes(swept) {
+                // Only boxes in the way on the other two axes can stop the movement
+                if !(0..3).filter(|other| *other != axis).all(|other| block_box.overlaps_on(&aabb, other)) {
+                    continue;
+                }
+
+                if wanted > 0.0 && block_box.min[axis] >= aabb.max[axis] - COLLISION_EPSILON {
+                    moved = moved.min(block_box.min[axis] - aabb.max[axis]);
+                } else if wanted < 0.0 && block_box.max[axis] <= aabb.min[axis] + COLLISION_EPSILON {
+                    moved = moved.max(block_box.max[axis] - aabb.min[axis]);
+                }
+            }
+
+            // Don't let rounding push the box backwards into where it came from
+            let moved = if wanted > 0.0 { moved.max(0.0) } else { moved.min(0.0) };
+
+            collision.blocked[axis] = moved != wanted;
+            collision.displacement[axis] = moved;
+            aabb.translate(axis, moved);
+        }
+
+        collision
+    }
+
+    /// Gets the light level at a position relative to a chunk, like `get_block_relative`. Anything
+    /// outside of the loaded world is fully lit.
+    pub fn get_light_relative(&self, chunk_location: Vector2<i32>, position: Vector3<i32>) -> u8 {
+        self.resolve_relative(chunk_location, position)
+            .and_then(|(location, local_position)| self.chunks.get(&location)?.chunk.get_light(local_position))
+            .unwrap_or(chunk::MAX_LIGHT)
+    }
+
+    /// Sets the light level of a whole column of blocks, given in world block coordinates, and
+    /// remeshes the chunks whose faces it touches. There's no light propagation yet, so this is
+    /// the way to get light values into the world for now.
+    pub fn set_light_column(&mut self, x: i32, z: i32, value: u8) {
+        let (offset, local_position) = World::world_to_chunk(Vector3::new(x, 0, z));
+        let (local_x, local_z) = (local_position.x, local_position.z);
+
+        let chunk = match self.chunks.get_mut(&offset) {
+            Some(loaded) => Arc::make_mut(&mut loaded.chunk),
             None => return,
         };
 
-        chunk.set_block(position, block);
+        for y in chunk::MIN_Y..=chunk::MAX_Y {
+            chunk.set_light(Vector3::new(local_x, y, local_z), value)
+                .expect("world_to_chunk keeps the column inside the chunk");
+        }
+
+        self.queue_remesh(offset);
 
-        let chunks = self.chunks.clone();
+        // Faces of the neighboring chunk that face into this column
+        for face in [Direction::FRONT, Direction::BACK, Direction::LEFT, Direction::RIGHT] {
+            let face_vec = face.to_vec3();
+            if let Some((neighbor, _)) = self.resolve_relative(offset, Vector3::new(local_x + face_vec.x, 0, local_z + face_vec.z)) {
+                if neighbor != offset {
+                    self.queue_remesh(neighbor);
+                }
+            }
+        }
+    }
+
+    /// Sets a block by its position relative to a chunk and patches the meshes around it. The
+    /// blocks next to it get to react afterwards, see `BlockData::on_neighbor_changed`.
+    pub fn set_block(&mut self, chunk_location: Vector2<i32>, position: Vector3<i32>, block: Block) {
+        if self.set_block_quietly(chunk_location, position, block) {
+            let world_position = position + Vector3::new(
+                chunk_location.x * chunk::CHUNK_WIDTH as i32,
+                0,
+                chunk_location.y * chunk::CHUNK_DEPTH as i32,
+            );
 
-        let chunk = match chunks.get(chunk_index) {
-            Some(chunk) => chunk,
+            self.update_neighbors(world_position);
+        }
+    }
+
+    /// Like `set_block`, with the block given by its world space position, so it works across
+    /// chunk borders. Fails if the chunk isn't loaded or the position is out of the height range.
+    pub fn set_block_global(&mut self, position: Vector3<i32>, block: Block) -> Result<(), SetBlockError> {
+        let (location, local_position) = World::world_to_chunk(position);
+
+        if !self.chunks.contains_key(&location) {
+            return Err(SetBlockError::Unloaded(location));
+        }
+        if !Chunk::contains(local_position) {
+            return Err(SetBlockError::OutOfBounds(position));
+        }
+
+        self.set_block(location, local_position, block);
+
+        Ok(())
+    }
+
+    /// Places a block in the air next to the block a ray hit, on the side of the face it hit, so
+    /// logs run along that face's axis (see `Block::placed_against`). Blocks with collision can't
+    /// be placed where they'd overlap `body`, which is in render space. Returns whether the
+    /// block was placed.
+    pub fn place_block(&mut self, hit: &BlockHit, block: Block, body: &Aabb) -> bool {
+        let position = hit.position + hit.face.to_vec3();
+        if self.get_block_global(position) != Some(&Block::new_air()) {
+            return false;
+        }
+
+        let block = block.placed_against(&hit.face);
+        if let Some(collision_box) = block.collision_box() {
+            if collision_box.translated(self.to_render_space(position)).intersects(body) {
+                return false;
+            }
+        }
+
+        self.set_block_global(position, block).is_ok()
+    }
+
+    /// Breaks the block at the world space position, replacing it with air like `set_block`,
+    /// and returns what it dropped, see `Block::drops`. Nothing happens if the chunk isn't loaded.
+    pub fn break_and_collect(&mut self, position: Vector3<i32>) -> Vec<Block> {
+        let block = match self.get_block_global(position) {
+            Some(block) => *block,
+            None => return Vec::new(),
+        };
+
+        match self.set_block_global(position, Block::new_air()) {
+            Ok(()) => block.drops(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Lets the blocks around a changed position react to it, and the blocks around any edits
+    /// they make after that
+    fn update_neighbors(&mut self, changed_position: Vector3<i32>) {
+        self.run_updates(changed_position, VecDeque::from(World::neighbor_updates(changed_position)));
+    }
+
+    /// Updates for the six blocks around a changed position
+    fn neighbor_updates(position: Vector3<i32>) -> [BlockUpdate; 6] {
+        Direction::ALL.map(|face| (position + face.to_vec3(), position))
+    }
+
+    /// Runs block updates until nothing changes anymore or `NEIGHBOR_UPDATE_BUDGET` runs out.
+    /// `cause` is the position of the change that set them off. Returns how many blocks changed.
+    fn run_updates(&mut self, cause: Vector3<i32>, mut updates: VecDeque<BlockUpdate>) -> usize {
+        let mut budget = NEIGHBOR_UPDATE_BUDGET;
+        let mut changed_blocks = 0;
+
+        while let Some((position, changed)) = updates.pop_front() {
+            if budget == 0 {
+                log::warn!("edit at {:?} set off more than {} block updates, dropping the rest", cause, NEIGHBOR_UPDATE_BUDGET);
+                break;
+            }
+            budget -= 1;
+
+            let block = match self.get_block_global(position) {
+                Some(block) => *block,
+                None => continue,
+            };
+
+            let mut access = WorldAccess::new(self);
+            block.on_neighbor_changed(position, changed, &mut access);
+            changed_blocks += self.apply_changes(access.into_changes(), &mut updates);
+        }
+
+        changed_blocks
+    }
+
+    /// Applies the edits a block made through a `WorldAccess`, queueing updates for the
+    /// neighbors of every block that changed and for the blocks it scheduled. Returns how many
+    /// blocks changed.
+    fn apply_changes(&mut self, changes: BlockChanges, updates: &mut VecDeque<BlockUpdate>) -> usize {
+        let mut changed_blocks = 0;
+
+        for (position, block) in changes.edits {
+            let (location, local_position) = World::world_to_chunk(position);
+            if self.set_block_quietly(location, local_position, block) {
+                updates.extend(World::neighbor_updates(position));
+                changed_blocks += 1;
+            }
+        }
+
+        updates.extend(changes.scheduled.into_iter().map(|position| (position, position)));
+
+        changed_blocks
+    }
+
+    /// Gives `ticks_per_chunk` random positions in every loaded chunk a random tick, which only
+    /// does something for blocks that want one, see `BlockData::on_random_tick`. Their edits
+    /// update the neighbors like `set_block` does. Chunks are visited in order of location, so
+    /// the same seed always picks the same positions. Returns how many blocks changed.
+    pub fn random_tick(&mut self, rng: &mut impl Rng, ticks_per_chunk: usize) -> usize {
+        let mut locations = self.chunks.keys().copied().collect::<Vec<_>>();
+        locations.sort_by_key(|location| (location.x, location.y));
+
+        let mut changed_blocks = 0;
+
+        for location in locations {
+            for _ in 0..ticks_per_chunk {
+                let local_position = Vector3::new(
+                    rng.gen_range(0..chunk::CHUNK_WIDTH as i32),
+                    rng.gen_range(chunk::MIN_Y..=chunk::MAX_Y),
+                    rng.gen_range(0..chunk::CHUNK_DEPTH as i32),
+                );
+
+                let chunk = &self.chunks[&location].chunk;
+                let block = match chunk.get_block(local_position) {
+                    Some(block) if block.wants_random_tick() => *block,
+                    _ => continue,
+                };
+                let position = chunk.to_world(local_position);
+
+                let mut access = WorldAccess::new(self);
+                block.on_random_tick(position, &mut access);
+
+                let mut updates = VecDeque::new();
+                changed_blocks += self.apply_changes(access.into_changes(), &mut updates);
+                changed_blocks += self.run_updates(position, updates);
+            }
+        }
+
+        changed_blocks
+    }
+
+    /// Like `set_block`, without updating the neighbors. Returns whether the block changed.
+    fn set_block_quietly(&mut self, chunk_location: Vector2<i32>, position: Vector3<i32>, block: Block) -> bool {
+        let previous = match self.chunks.get_mut(&chunk_location).map(|loaded| Arc::make_mut(&mut loaded.chunk).set_block(position, block)) {
+            Some(Ok(previous)) => previous,
+            Some(Err(e)) => {
+                log::warn!("Unable to set block in chunk {:?}: {}", chunk_location, e);
+                return false;
+            }
+            None => return false,
+        };
+
+        // Nothing changed, so neither did the meshes
+        if previous == block {
+            return false;
+        }
+
+        // Placing a block hides its neighbors' faces and removing one brings them back
+        for (location, position, face) in World::faces_touching(chunk_location, position) {
+            self.refresh_face(location, position, &face);
+        }
+
+        self.requeue_pending(chunk_location);
+
+        true
+    }
+
+    /// Every face touching the block at `position` in the chunk at `chunk_location`, from both
+    /// sides: the block's own six, then for each of them the face of the neighbor pointing back at
+    /// it. Neighbors across a chunk border come with that chunk's location and their position in
+    /// it, whether or not it's loaded.
+    fn faces_touching(chunk_location: Vector2<i32>, position: Vector3<i32>) -> Vec<(Vector2<i32>, Vector3<i32>, Direction)> {
+        Direction::ALL
+            .into_iter()
+            .flat_map(|face| {
+                let (offset, neighbor) = World::world_to_chunk(position + face.to_vec3());
+                let opposite = face.get_opposite();
+
+                [(chunk_location, position, face), (chunk_location + offset, neighbor, opposite)]
+            })
+            .collect()
+    }
+
+    /// Adds or removes a block's face in its chunk's mesh depending on the blocks around it right
+    /// now, so it doesn't matter what the face looked like before. Faces of unloaded chunks or out
+    /// of the height range are skipped. Blocks without geometry, like air, just get their slots
+    /// cleared.
+    fn refresh_face(&mut self, location: Vector2<i32>, position: Vector3<i32>, face: &Direction) {
+        let block = match self.chunks.get(&location).and_then(|loaded| loaded.chunk.get_block(position)) {
+            Some(block) => *block,
             None => return,
         };
 
-        let _flattened = ChunkMesh::flatten_3d(position.into());
+        let v = face.to_vec3() + position;
+        let visible = block.face_visible(face, self.get_block_relative(location, v));
+        let light = self.get_light_relative(location, v);
 
-        let faces = [
-            Direction::FRONT,
-            Direction::BACK,
-            Direction::TOP,
-            Direction::BOTTOM,
-            Direction::LEFT,
-            Direction::RIGHT,
-        ];
+        let mesh = &mut self.chunks.get_mut(&location).unwrap().mesh;
+        if visible {
+            mesh.add_face(position, face, &block, light, &self.textures);
+        } else {
+            mesh.remove_face(position, face);
+        }
+    }
 
-        let is_air = if let Block::Air(_) = block { true } else { false };
+    /// Changes many blocks at once, given in world space coordinates. Unlike `set_block` this
+    /// doesn't patch the meshes face by face, instead every touched chunk (and every neighbor
+    /// with an edit on the border facing it) gets a single remesh on the mesher thread, so the
+    /// changes show up once those finish. Edits in unloaded chunks or outside the height range are
+    /// skipped. Returns how many chunks had blocks changed.
+    pub fn set_blocks(&mut self, edits: impl IntoIterator<Item = (Vector3<i32>, Block)>) -> usize {
+        let mut modified = HashSet::new();
+        let mut remesh = HashSet::new();
 
-        for face in faces {
-            let face_vec = face.to_vec3();
-            let v = face_vec.add_element_wise(position);
-
-            let neighbor = chunk.get_block(v);
-            match neighbor {
-                Some(neighbor) => {
-                    let mesh = match self.chunk_meshes.get_mut(chunk_index) {
-                        Some(mesh) => mesh,
-                        None => continue, // The current chunk's mesh is unavailable
-                    };
+        for (position, block) in edits {
+            let (offset, local_position) = World::world_to_chunk(position);
 
-                    match neighbor {
-                        Block::Air(..) => if !is_air {
-                            mesh.add_face(position, &face, &block);
-                        },
-                        _ => if is_air {
-                            mesh.add_face(position, &face.get_opposite(), neighbor);
-                        } else {
-                            mesh.remove_face(position, &face);
-                            mesh.remove_face(v, &face.get_opposite());
-                        }
-                    }
-                },
-                None => {
-                    let (neighbor_chunk, neighbor_mesh) = match self.chunk_map.get(&Vector2::new(face_vec.x, face_vec.z).add_element_wise(chunk.world_offset)) {
-                        Some(index) => match (self.chunks.get(*index), self.chunk_meshes.get_mut(*index)) {
-                            (Some(chunk), Some(mesh)) => (chunk, mesh),
-                            // Either the neighbor chunk or the chunk's mesh couldn't be found, but
-                            // the chunk has an index in the map.
-                            (None, None) | (None, Some(_)) | (Some(_), None) => continue,
-                        },
-                        None => {
-                            match self.chunk_meshes.get_mut(chunk_index) {
-                                Some(mesh) => {
-                                    mesh.add_face(position, &face, &block);
-                                    continue
-                                },
-                                None => continue,
-                            }
-                        },
-                    };
+            let chunk = match self.chunks.get_mut(&offset) {
+                Some(loaded) => Arc::make_mut(&mut loaded.chunk),
+                None => continue,
+            };
 
-                    let mut neighbor_chunk_block = None;
-                    let neighbor_chunk_block_position = Vector3::new(v.x.rem_euclid(chunk::CHUNK_WIDTH as i32), v.y, v.z.rem_euclid(chunk::CHUNK_DEPTH as i32));
-                    if !(0..16).contains(&v.x) || !(0..16).contains(&v.z) {
-                        neighbor_chunk_block = neighbor_chunk.get_block(neighbor_chunk_block_position);
-                    }
+            match chunk.set_block(local_position, block) {
+                Ok(previous) if previous != block => {}
+                _ => continue,
+            }
+
+            modified.insert(offset);
+            remesh.insert(offset);
+
+            // Edits on the border can hide or reveal faces of the neighboring chunk
+            for face in [Direction::FRONT, Direction::BACK, Direction::LEFT, Direction::RIGHT] {
+                let (neighbor_offset, _) = World::world_to_chunk(position + face.to_vec3());
+
+                if neighbor_offset != offset && self.chunks.contains_key(&neighbor_offset) {
+                    remesh.insert(neighbor_offset);
+                }
+            }
+        }
+
+        for location in remesh {
+            self.queue_remesh(location);
+        }
+
+        modified.len()
+    }
 
-                    if !is_air {
-                        if let Some(b) = neighbor_chunk_block {
-                            match b {
-                                Block::Air(..) => { 
-                                    match self.chunk_meshes.get_mut(chunk_index) {
-                                        Some(mesh) => mesh.add_face(position, &face, &block),
-                                        None => continue,
-                                    }
-                                },
-                                _ => neighbor_mesh.remove_face(neighbor_chunk_block_position, &face.get_opposite()),
-                            }
-                        } else {
-                            match self.chunk_meshes.get_mut(chunk_index) {
-                                Some(mesh) => mesh.add_face(position, &face, &block),
-                                None => continue,
-                            }
-                        }
+    /// Grows an oak tree on top of the world space position `ground`, which has to be soil (see
+    /// `BlockTags`) with air above it for the trunk. The soil turns into dirt, and the leaves
+    /// only fill air. Returns whether the tree was grown.
+    pub fn grow_tree(&mut self, ground: Vector3<i32>) -> bool {
+        if !self.get_block_global(ground).is_some_and(|block| block.has_tag("soil")) {
+            return false;
+        }
+
+        let tree = terrain::oak_tree(ground).collect::<Vec<_>>();
+        let is_air = |position: &Vector3<i32>| self.get_block_global(*position) == Some(&Block::new_air());
+        if !tree.iter().filter(|(_, block)| matches!(block, Block::OakLog(_))).all(|(position, _)| is_air(position)) {
+            return false;
+        }
+
+        let blocks = tree
+            .into_iter()
+            .filter(|(position, block)| *block != Block::new_oak_leaves() || is_air(position))
+            .collect::<Vec<_>>();
+        self.set_blocks(blocks);
+
+        true
+    }
+
+    /// Like `set_blocks`, with positions relative to a chunk like in `set_block`
+    pub fn set_blocks_batch(&mut self, edits: &[(Vector2<i32>, Vector3<i32>, Block)]) -> usize {
+        self.set_blocks(edits.iter().map(|(chunk_location, position, block)| {
+            let chunk_origin = Vector3::new(
+                chunk_location.x * chunk::CHUNK_WIDTH as i32,
+                0,
+                chunk_location.y * chunk::CHUNK_DEPTH as i32,
+            );
+
+            (chunk_origin + position, *block)
+        }))
+    }
+
+    /// Sets every block in the box between the world space positions `min` and `max`, inclusive,
+    /// with a single remesh of each chunk it changed, plus the neighbors whose border it reaches.
+    /// Faces inside the box are culled by the remesh like any others. Unloaded chunks are skipped.
+    /// Returns how many blocks changed.
+    pub fn fill_region(&mut self, min: Vector3<i32>, max: Vector3<i32>, block: Block) -> usize {
+        let (min_chunk, _) = World::world_to_chunk(min);
+        let (max_chunk, _) = World::world_to_chunk(max);
+        let mut remesh = HashSet::new();
+        let mut changed = 0;
+
+        for chunk_x in min_chunk.x..=max_chunk.x {
+            for chunk_z in min_chunk.y..=max_chunk.y {
+                let location = Vector2::new(chunk_x, chunk_z);
+                let chunk = match self.chunks.get_mut(&location) {
+                    Some(loaded) => Arc::make_mut(&mut loaded.chunk),
+                    None => continue,
+                };
+
+                let chunk_origin = Vector3::new(
+                    chunk_x * chunk::CHUNK_WIDTH as i32,
+                    0,
+                    chunk_z * chunk::CHUNK_DEPTH as i32,
+                );
+                let (local_min, local_max) = (min - chunk_origin, max - chunk_origin);
+
+                let chunk_changed = chunk.fill_region(local_min, local_max, block);
+                if chunk_changed == 0 {
+                    continue;
+                }
+
+                changed += chunk_changed;
+                remesh.insert(location);
+
+                // Only neighbors the box touches the border of can have faces hidden or revealed
+                let touches = [
+                    (Direction::FRONT, local_max.z >= chunk::CHUNK_DEPTH as i32 - 1),
+                    (Direction::BACK, local_min.z <= 0),
+                    (Direction::LEFT, local_min.x <= 0),
+                    (Direction::RIGHT, local_max.x >= chunk::CHUNK_WIDTH as i32 - 1),
+                ];
+                for (face, touches) in touches {
+                    let face_vec = face.to_vec3();
+                    let neighbor = location + Vector2::new(face_vec.x, face_vec.z);
+
+                    if touches && self.chunks.contains_key(&neighbor) {
+                        remesh.insert(neighbor);
                     }
                 }
             }
         }
+
+        for location in remesh {
+            self.queue_remesh(location);
+        }
+
+        changed
+    }
+
+    /// Rebuilds the chunk's mesh right away on this thread, see `ChunkMesh::rebuild`. Any remesh
+    /// still in flight for it is dropped when it finishes, since this one is newer.
+    pub fn rebuild_mesh(&mut self, chunk_location: Vector2<i32>, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let neighbors = [Direction::FRONT, Direction::BACK, Direction::LEFT, Direction::RIGHT].map(|face| {
+            let face_vec = face.to_vec3();
+            chunk_location + Vector2::new(face_vec.x, face_vec.z)
+        });
+
+        let mut loaded = match self.chunks.remove(&chunk_location) {
+            Some(loaded) => loaded,
+            None => return,
+        };
+
+        let neighbor_chunks = neighbors.map(|location| self.chunks.get(&location).map(|loaded| &*loaded.chunk));
+        loaded.mesh.rebuild(&loaded.chunk, neighbor_chunks, &self.textures, device, queue);
+
+        self.chunks.insert(chunk_location, loaded);
+        self.pending_meshes.remove(&chunk_location);
+    }
+
+    /// Queues a full rebuild of the chunk's mesh on the mesher thread. The result is picked up by
+    /// `drain_finished_meshes`, and any older job for the same chunk is discarded when it finishes.
+    pub fn queue_remesh(&mut self, chunk_location: Vector2<i32>) {
+        let chunk = match self.chunks.get(&chunk_location) {
+            Some(loaded) => Arc::clone(&loaded.chunk),
+            None => return,
+        };
+
+        let neighbors = [Direction::FRONT, Direction::BACK, Direction::LEFT, Direction::RIGHT].map(|face| {
+            let face_vec = face.to_vec3();
+            self.chunks.get(&(chunk_location + Vector2::new(face_vec.x, face_vec.z)))
+                .map(|loaded| Arc::clone(&loaded.chunk))
+        });
+
+        let id = self.next_job_id;
+        self.next_job_id += 1;
+        self.pending_meshes.insert(chunk_location, id);
+
+        let job = MeshJob {
+            chunk_location,
+            id,
+            chunk,
+            neighbors,
+        };
+
+        if self.job_sender.send(job).is_err() {
+            log::warn!("chunk mesher thread has stopped, chunk {:?} won't be remeshed", chunk_location);
+        }
+    }
+
+    /// Uploads every mesh the mesher thread has finished since the last call. Should be called
+    /// once per frame, returns how many meshes were uploaded.
+    pub fn drain_finished_meshes(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> usize {
+        let mut uploaded = 0;
+
+        while let Ok(finished) = self.finished_receiver.try_recv() {
+            // A newer job was queued for this chunk after this one, so this mesh is already stale
+            if self.pending_meshes.get(&finished.chunk_location) != Some(&finished.id) {
+                continue;
+            }
+
+            self.pending_meshes.remove(&finished.chunk_location);
+
+            if let Some(loaded) = self.chunks.get_mut(&finished.chunk_location) {
+                loaded.mesh.upload(finished.data, device, queue);
+                uploaded += 1;
+
+                log::debug!(
+                    "remeshed chunk {:?}: {:?}, world total: {:?}",
+                    finished.chunk_location,
+                    loaded.mesh.stats(),
+                    self.mesh_stats(),
+                );
+            }
+        }
+
+        uploaded
+    }
+
+    /// Compacts every mesh where more than half of the memory isn't used by any faces, which
+    /// happens after removing a lot of blocks. Returns how many bytes were freed.
+    pub fn compact_meshes(&mut self, device: &wgpu::Device) -> usize {
+        self.chunk_mesh_iter_mut()
+            .filter(|mesh| mesh.reclaimable_bytes() > mesh.stats().gpu_bytes / 2)
+            .map(|mesh| mesh.compact(device))
+            .sum()
     }
 
-    pub fn update_buffers(&self, queue: &wgpu::Queue) {
-        for chunk_mesh in self.chunk_meshes.iter() {
-            chunk_mesh.buffer_write(queue);
+    /// The combined stats of every chunk mesh in the world
+    pub fn mesh_stats(&self) -> MeshStats {
+        self.chunk_mesh_iter().map(ChunkMesh::stats).sum()
+    }
+
+    /// Chunks with a remesh in flight were snapshotted before the latest edit, so they need
+    /// another one to pick it up.
+    fn requeue_pending(&mut self, chunk_location: Vector2<i32>) {
+        let locations = [Vector2::new(0, 0), Vector2::new(1, 0), Vector2::new(-1, 0), Vector2::new(0, 1), Vector2::new(0, -1)]
+            .map(|v| chunk_location + v);
+
+        for location in locations {
+            if self.pending_meshes.contains_key(&location) {
+                self.queue_remesh(location);
+            }
         }
     }
 
-    pub fn chunks_iter(&self) -> std::slice::Iter<Chunk> {
-        self.chunks.iter()
+    /// Uploads the meshes `set_block` patched since the last call, see `ChunkMesh::sync`. Returns
+    /// how many were uploaded, nothing is if the world didn't change.
+    pub fn update_buffers(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> usize {
+        let mut uploaded = 0;
+
+        for chunk_mesh in self.chunk_mesh_iter_mut().filter(|chunk_mesh| chunk_mesh.is_dirty()) {
+            chunk_mesh.sync(device, queue);
+            uploaded += 1;
+        }
+
+        uploaded
     }
 
-    pub fn chunks_iter_mut(&mut self) -> std::slice::IterMut<Chunk> {
-        self.chunks.iter_mut()
+    /// How many chunks have mesh changes waiting for `update_buffers`
+    pub fn dirty_chunk_count(&self) -> usize {
+        self.chunk_mesh_iter().filter(|chunk_mesh| chunk_mesh.is_dirty()).count()
     }
 
-    pub fn chunk_mesh_iter(&self) -> std::slice::Iter<ChunkMesh> {
-        self.chunk_meshes.iter()
+    /// Iterates over every non-air block in every chunk, with world space positions
+    pub fn iter_blocks(&self) -> impl Iterator<Item = (Vector3<i32>, &Block)> {
+        self.chunks_iter().flat_map(|chunk| {
+            chunk.iter_blocks().map(|(position, block)| (chunk.to_world(position), block))
+        })
     }
 
-    pub fn chunk_mesh_iter_mut(&mut self) -> std::slice::IterMut<ChunkMesh> {
-        self.chunk_meshes.iter_mut()
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
     }
 
-    pub fn chunk_map_iter(&mut self) -> hashbrown::hash_map::Iter<Vector2<i32>, usize> {
-        self.chunk_map.iter()
+    /// Iterates over every loaded chunk along with its mesh
+    pub fn iter(&self) -> impl Iterator<Item = (&Chunk, &ChunkMesh)> {
+        self.chunks.values().map(|loaded| (&*loaded.chunk, &loaded.mesh))
     }
 
-    pub fn chunk_map_iter_mut(&mut self) -> hashbrown::hash_map::IterMut<Vector2<i32>, usize> {
-        self.chunk_map.iter_mut()
+    pub fn chunks_iter(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.values().map(|loaded| &*loaded.chunk)
+    }
+
+    pub fn chunks_iter_mut(&mut self) -> impl Iterator<Item = &mut Chunk> {
+        self.chunks.values_mut().map(|loaded| Arc::make_mut(&mut loaded.chunk))
+    }
+
+    pub fn chunk_mesh_iter(&self) -> impl Iterator<Item = &ChunkMesh> {
+        self.chunks.values().map(|loaded| &loaded.mesh)
+    }
+
+    pub fn chunk_mesh_iter_mut(&mut self) -> impl Iterator<Item = &mut ChunkMesh> {
+        self.chunks.values_mut().map(|loaded| &mut loaded.mesh)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::renderer::{self, Renderer};
+    use crate::texture::TextureArray;
+
+    /// An empty world drawn with the real atlas, for tests with a headless renderer
+    pub(crate) fn test_world(renderer: &Renderer) -> World {
+        let texture = TextureArray::new(Path::new("sprite_atlas.png"), chunk::TEXTURE_SIZE as u32, &renderer.device, &renderer.queue);
+        World::new(TextureTable::default(), ChunkUniforms::new(texture, 4, &renderer.device))
+    }
+
+    /// A world with the chunks at `locations` loaded and meshed, all air
+    pub(crate) fn air_world(renderer: &Renderer, locations: &[Vector2<i32>]) -> World {
+        let mut world = test_world(renderer);
+        world.generate_chunks_parallel(locations, &renderer.device, &renderer.queue, |_, _| Block::new_air());
+        world
+    }
+
+    fn face_count(world: &World, location: Vector2<i32>) -> usize {
+        world.get_chunk(location).unwrap().1.data.face_count()
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn placing_against_the_next_chunk_hides_both_border_faces() {
+        let renderer = renderer::tests::headless();
+        let (left, right) = (Vector2::new(0, 0), Vector2::new(1, 0));
+        let mut world = air_world(&renderer, &[left, right]);
+
+        world.set_block(right, Vector3::new(0, 0, 0), Block::new_stone());
+        assert_eq!(face_count(&world, right), 6);
+
+        world.set_block(left, Vector3::new(15, 0, 0), Block::new_stone());
+        assert_eq!(face_count(&world, left), 5);
+        assert_eq!(face_count(&world, right), 5);
+
+        world.set_block(left, Vector3::new(15, 0, 0), Block::new_air());
+        assert_eq!(face_count(&world, left), 0);
+        assert_eq!(face_count(&world, right), 6);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn set_block_inside_a_chunk_hides_the_shared_face() {
+        let renderer = renderer::tests::headless();
+        let location = Vector2::new(0, 0);
+        let mut world = air_world(&renderer, &[location]);
+
+        world.set_block(location, Vector3::new(4, 0, 4), Block::new_stone());
+        assert_eq!(face_count(&world, location), 6);
+
+        world.set_block(location, Vector3::new(5, 0, 4), Block::new_stone());
+        assert_eq!(face_count(&world, location), 10);
+        assert_eq!(world.get_block_global(Vector3::new(5, 0, 4)), Some(&Block::new_stone()));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn set_block_on_a_border_leaves_an_empty_neighbor_alone() {
+        let renderer = renderer::tests::headless();
+        let (left, right) = (Vector2::new(0, 0), Vector2::new(1, 0));
+        let mut world = air_world(&renderer, &[left, right]);
+
+        world.set_block(left, Vector3::new(15, 0, 0), Block::new_stone());
+        assert_eq!(face_count(&world, left), 6);
+        assert_eq!(face_count(&world, right), 0);
+        assert_eq!(world.get_block_global(Vector3::new(15, 0, 0)), Some(&Block::new_stone()));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn set_block_on_a_border_without_a_neighbor_keeps_the_border_face() {
+        let renderer = renderer::tests::headless();
+        let location = Vector2::new(0, 0);
+        let mut world = air_world(&renderer, &[location]);
+
+        world.set_block(location, Vector3::new(15, 0, 0), Block::new_stone());
+        assert_eq!(face_count(&world, location), 6);
+
+        assert_eq!(
+            world.set_block_global(Vector3::new(16, 0, 0), Block::new_stone()),
+            Err(SetBlockError::Unloaded(Vector2::new(1, 0))),
+        );
+    }
+
+    #[test]
+    fn positions_on_chunk_borders_land_in_the_right_chunk() {
+        let split = |x, z| World::world_to_chunk(Vector3::new(x, 7, z));
+
+        assert_eq!(split(0, 0), (Vector2::new(0, 0), Vector3::new(0, 7, 0)));
+        assert_eq!(split(15, 15), (Vector2::new(0, 0), Vector3::new(15, 7, 15)));
+        assert_eq!(split(16, 0), (Vector2::new(1, 0), Vector3::new(0, 7, 0)));
+        assert_eq!(split(0, 16), (Vector2::new(0, 1), Vector3::new(0, 7, 0)));
+        assert_eq!(split(31, 32), (Vector2::new(1, 2), Vector3::new(15, 7, 0)));
+    }
+
+    #[test]
+    fn negative_positions_round_down_into_the_previous_chunk() {
+        let split = |x, z| World::world_to_chunk(Vector3::new(x, -3, z));
+
+        assert_eq!(split(-1, -1), (Vector2::new(-1, -1), Vector3::new(15, -3, 15)));
+        assert_eq!(split(-16, 0), (Vector2::new(-1, 0), Vector3::new(0, -3, 0)));
+        assert_eq!(split(-17, -16), (Vector2::new(-2, -1), Vector3::new(15, -3, 0)));
+        assert_eq!(split(-33, 5), (Vector2::new(-3, 0), Vector3::new(15, -3, 5)));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn world_space_edits_work_at_negative_positions() {
+        let renderer = renderer::tests::headless();
+        let mut world = air_world(&renderer, &[Vector2::new(-1, -1), Vector2::new(0, 0)]);
+
+        world.set_block_global(Vector3::new(-1, -3, -1), Block::new_stone()).unwrap();
+        assert_eq!(world.get_block_global(Vector3::new(-1, -3, -1)), Some(&Block::new_stone()));
+        assert_eq!(
+            world.get_block_relative(Vector2::new(-1, -1), Vector3::new(15, -3, 15)),
+            Some(&Block::new_stone()),
+        );
+
+        assert_eq!(world.get_block_global(Vector3::new(-17, 0, 0)), None);
+        assert_eq!(
+            world.set_block_global(Vector3::new(-17, 0, 0), Block::new_stone()),
+            Err(SetBlockError::Unloaded(Vector2::new(-2, 0))),
+        );
+        assert_eq!(
+            world.set_block_global(Vector3::new(-1, chunk::MAX_Y + 1, -1), Block::new_stone()),
+            Err(SetBlockError::OutOfBounds(Vector3::new(-1, chunk::MAX_Y + 1, -1))),
+        );
+    }
+
+    #[test]
+    fn a_ray_straight_down_hits_the_top_of_the_ground() {
+        let hit = World::raycast_cells(Point3::new(0.2, 5.0, 0.3), -Vector3::unit_y(), 10.0, |cell| cell.y <= 0).unwrap();
+
+        assert_eq!(hit.position, Vector3::new(0, 0, 0));
+        assert_eq!(hit.face, Direction::TOP);
+        assert_eq!(hit.distance, 4.5);
+        assert_eq!(hit.point, Point3::new(0.2, 0.5, 0.3));
+    }
+
+    #[test]
+    fn a_diagonal_ray_through_a_corner_doesnt_slip_between_blocks() {
+        // Both blocks touch the corner at (0.5, 0, 0.5) that the ray passes through
+        let solid = |cell: Vector3<i32>| cell == Vector3::new(1, 0, 0) || cell == Vector3::new(0, 0, 1);
+        let hit = World::raycast_cells(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 1.0), 10.0, solid).unwrap();
+
+        assert!(solid(hit.position), "{:?}", hit.position);
+        assert!((hit.distance - 0.5f32.hypot(0.5)).abs() < 1e-5);
+
+        // A block diagonally across the corner is hit after stepping through one of the others
+        let hit = World::raycast_cells(Point3::new(0.0, 0.0, 0.0), Vector3::new(1.0, 0.0, 1.0), 10.0, |cell| {
+            cell == Vector3::new(1, 0, 1)
+        });
+        assert_eq!(hit.map(|hit| hit.position), Some(Vector3::new(1, 0, 1)));
+    }
+
+    #[test]
+    fn a_ray_that_leaves_everything_solid_behind_hits_nothing() {
+        // Like running out of loaded chunks, where nothing is solid
+        assert!(World::raycast_cells(Point3::new(0.0, 0.0, 0.0), Vector3::unit_x(), 64.0, |_| false).is_none());
+        assert!(World::raycast_cells(Point3::new(0.0, 0.0, 0.0), Vector3::unit_x(), 5.0, |cell| cell.x == 10).is_none());
+
+        // The cell the ray starts in is skipped
+        let hit = World::raycast_cells(Point3::new(0.0, 0.0, 0.0), Vector3::unit_x(), 5.0, |cell| cell.x >= 0);
+        assert_eq!(hit.map(|hit| (hit.position, hit.face)), Some((Vector3::new(1, 0, 0), Direction::LEFT)));
+    }
+
+    #[test]
+    fn streamed_in_chunks_fade_in_over_fade_in_time() {
+        let start = Instant::now();
+        assert_eq!(fade_since(None, start), 1.0);
+        assert_eq!(fade_since(Some(start), start), 0.0);
+        assert!((fade_since(Some(start), start + FADE_IN_TIME / 2) - 0.5).abs() < 1e-4);
+        assert_eq!(fade_since(Some(start), start + FADE_IN_TIME * 3), 1.0);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn set_blocks_remeshes_every_chunk_it_touches_once() {
+        let renderer = renderer::tests::headless();
+        let locations = [Vector2::new(0, 0), Vector2::new(1, 0), Vector2::new(0, 1), Vector2::new(1, 1)];
+        let mut world = air_world(&renderer, &locations);
+        let first_job = world.next_job_id;
+
+        // A 20 block cube around the corner the four chunks share
+        let cube = (6..26).flat_map(|x| (0..20).flat_map(move |y| (6..26).map(move |z| Vector3::new(x, y, z))));
+        assert_eq!(world.set_blocks(cube.map(|position| (position, Block::new_stone()))), 4);
+
+        assert_eq!(world.next_job_id - first_job, 4);
+        let mut pending = world.pending_meshes.keys().copied().collect::<Vec<_>>();
+        pending.sort_by_key(|location| (location.y, location.x));
+        assert_eq!(pending, locations);
+        assert_eq!(world.get_block_global(Vector3::new(25, 19, 25)), Some(&Block::new_stone()));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn removing_a_chunk_leaves_the_others_reachable() {
+        let renderer = renderer::tests::headless();
+        let locations = (-1..=1).flat_map(|x| (-1..=1).map(move |z| Vector2::new(x, z))).collect::<Vec<_>>();
+        let mut world = air_world(&renderer, &locations);
+
+        // A different block in the corner of each chunk, so a mixed up lookup shows
+        let blocks = [Block::new_stone(), Block::new_dirt(), Block::new_cobblestone()];
+        for (i, location) in locations.iter().enumerate() {
+            world.set_block(*location, Vector3::new(0, 0, 0), blocks[i % blocks.len()]);
+        }
+
+        for removed in [Vector2::new(0, 0), Vector2::new(-1, -1), Vector2::new(1, 0)] {
+            assert!(world.remove_chunk(removed).is_some());
+            assert!(world.get_chunk(removed).is_none());
+
+            for (i, location) in locations.iter().enumerate() {
+                let corner = Vector3::new(location.x * chunk::CHUNK_WIDTH as i32, 0, location.y * chunk::CHUNK_DEPTH as i32);
+                let expected = world.get_chunk(*location).map(|_| &blocks[i % blocks.len()]);
+                assert_eq!(world.get_block_global(corner), expected);
+            }
+        }
+        assert_eq!(world.chunk_count(), 6);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn rebasing_the_origin_keeps_blocks_where_they_are_relative_to_the_camera() {
+        let renderer = renderer::tests::headless();
+        let mut world = test_world(&renderer);
+        let mut camera = Vector3::new(3.5, 70.0, -12.25);
+
+        for new_origin in [Vector2::new(1, 0), Vector2::new(-40, 17), Vector2::new(100_000, -100_000)] {
+            let block = Vector3::new(new_origin.x * chunk::CHUNK_WIDTH as i32 + 5, 64, new_origin.y * chunk::CHUNK_DEPTH as i32 - 3);
+            let before = world.to_render_space(block) - camera;
+
+            camera -= world.rebase_origin(new_origin);
+
+            let after = world.to_render_space(block) - camera;
+            assert!((after - before).magnitude() < 1e-3, "{:?} moved to {:?}", before, after);
+        }
+    }
+
+    /// The body of a player standing on the floor at y = 0.5, centered on x and z
+    fn body_at(x: f32, z: f32) -> Aabb {
+        Aabb::new(Point3::new(x - 0.3, 0.5, z - 0.3), Point3::new(x + 0.3, 2.3, z + 0.3))
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn a_falling_body_lands_on_the_floor() {
+        let renderer = renderer::tests::headless();
+        let location = Vector2::new(0, 0);
+        let mut world = air_world(&renderer, &[location]);
+        world.set_block(location, Vector3::new(5, 0, 5), Block::new_stone());
+
+        let mut body = body_at(5.0, 5.0);
+        body.translate(1, 1.0);
+        let collision = world.resolve_collision(body, Vector3::new(0.0, -3.0, 0.0));
+
+        assert!((collision.displacement.y + 1.0).abs() < 1e-5, "{:?}", collision.displacement);
+        assert_eq!(collision.blocked, [false, true, false]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn walking_into_a_wall_stops_only_the_blocked_axis() {
+        let renderer = renderer::tests::headless();
+        let location = Vector2::new(0, 0);
+        let mut world = air_world(&renderer, &[location]);
+        for y in 1..=2 {
+            world.set_block(location, Vector3::new(7, y, 5), Block::new_stone());
+        }
+
+        let collision = world.resolve_collision(body_at(5.0, 5.0), Vector3::new(3.0, 0.0, 1.0));
+
+        // The wall's face is at x = 6.5, so the body stops 1.2 blocks in, and slides along it
+        assert!((collision.displacement.x - 1.2).abs() < 1e-5, "{:?}", collision.displacement);
+        assert_eq!(collision.displacement.z, 1.0);
+        assert_eq!(collision.blocked, [true, false, false]);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn parallel_generation_matches_serial_generation() {
+        let renderer = renderer::tests::headless();
+        let mut world = test_world(&renderer);
+        let terrain = terrain::TerrainGenerator::new(WorldSeed(7), 0);
+        let pick = |location: Vector2<i32>, position: Vector3<i32>| {
+            let height = terrain.height_at(
+                location.x * chunk::CHUNK_WIDTH as i32 + position.x,
+                location.y * chunk::CHUNK_DEPTH as i32 + position.z,
+            );
+            match position.y {
+                y if y < height - 3 => Block::new_stone(),
+                y if y < height => Block::new_dirt(),
+                y if y == height => Block::new_grass(false),
+                _ => Block::new_air(),
+            }
+        };
+
+        let locations = (-1..=1).flat_map(|x| (-1..=1).map(move |z| Vector2::new(x, z))).collect::<Vec<_>>();
+        world.generate_chunks_parallel(&locations, &renderer.device, &renderer.queue, pick);
+
+        for location in locations {
+            let serial = Chunk::generate(location, |position| pick(location, position));
+            let (parallel, _) = world.get_chunk(location).unwrap();
+            assert!(parallel.iter_all_blocks().eq(serial.iter_all_blocks()), "chunk {:?} differs", location);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn a_sand_column_settles_when_its_support_is_removed() {
+        let renderer = renderer::tests::headless();
+        let location = Vector2::new(0, 0);
+        let mut world = air_world(&renderer, &[location]);
+        world.set_block(location, Vector3::new(5, 0, 5), Block::new_stone());
+        world.set_block(location, Vector3::new(5, 1, 5), Block::new_stone());
+        for y in 2..6 {
+            world.set_block(location, Vector3::new(5, y, 5), Block::new_sand());
+        }
+        assert_eq!(world.get_block_global(Vector3::new(5, 5, 5)), Some(&Block::new_sand()));
+
+        world.set_block(location, Vector3::new(5, 1, 5), Block::new_air());
+
+        for y in 1..5 {
+            assert_eq!(world.get_block_global(Vector3::new(5, y, 5)), Some(&Block::new_sand()), "y = {}", y);
+        }
+        assert_eq!(world.get_block_global(Vector3::new(5, 5, 5)), Some(&Block::new_air()));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn block_updates_stop_when_the_budget_runs_out() {
+        let renderer = renderer::tests::headless();
+        let location = Vector2::new(0, 0);
+        let mut world = air_world(&renderer, &[location]);
+
+        // A floating column that would take far more updates than the budget to fall 20 blocks.
+        // `set_blocks` doesn't update neighbors, so it stays up until something below changes.
+        world.set_blocks((0..100).map(|y| (Vector3::new(5, y, 5), Block::new_sand())));
+        world.set_block(location, Vector3::new(5, -21, 5), Block::new_stone());
+
+        let changed = world.run_updates(Vector3::new(5, -1, 5), VecDeque::from(World::neighbor_updates(Vector3::new(5, -1, 5))));
+
+        // Every update moves at most one sand block, which is two edits
+        assert!(changed > 0);
+        assert!(changed <= 2 * NEIGHBOR_UPDATE_BUDGET, "{} blocks changed", changed);
+        assert_eq!(world.get_block_global(Vector3::new(5, 99, 5)), Some(&Block::new_sand()));
+    }
+
+    #[test]
+    fn raising_the_render_distance_adds_the_ring_around_the_old_range() {
+        let center = Vector2::new(3, -7);
+        let near = World::chunks_in_range(center, 2);
+        let far = World::chunks_in_range(center, 4);
+        assert_eq!((near.len(), far.len()), (25, 81));
+
+        let added = far.iter().filter(|location| !near.contains(location)).collect::<Vec<_>>();
+        assert_eq!(added.len(), 81 - 25);
+        assert!(added.iter().all(|location| {
+            let offset = *location - center;
+            offset.x.abs().max(offset.y.abs()) > 2
+        }));
+
+        // Closest first, so the chunks around the camera load before the far corners
+        assert_eq!(far[0], center);
+        let distances = far.iter().map(|location| (location - center).magnitude2()).collect::<Vec<_>>();
+        assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    /// The faces `faces_touching` lists outside of `chunk_location`
+    fn faces_across_borders(chunk_location: Vector2<i32>, position: Vector3<i32>) -> Vec<(Vector2<i32>, Vector3<i32>, Direction)> {
+        World::faces_touching(chunk_location, position)
+            .into_iter()
+            .filter(|(location, _, _)| *location != chunk_location)
+            .collect()
+    }
+
+    #[test]
+    fn faces_touching_a_block_come_from_both_sides() {
+        let location = Vector2::new(2, -3);
+        let faces = World::faces_touching(location, Vector3::new(4, 10, 7));
+
+        assert_eq!(faces.len(), 12);
+        for face in Direction::ALL {
+            assert!(faces.contains(&(location, Vector3::new(4, 10, 7), face.clone())));
+            assert!(faces.contains(&(location, Vector3::new(4, 10, 7) + face.to_vec3(), face.get_opposite())));
+        }
+        assert!(faces_across_borders(location, Vector3::new(4, 10, 7)).is_empty());
+    }
+
+    #[test]
+    fn faces_touching_a_border_block_reach_into_the_neighbor() {
+        let location = Vector2::new(0, 0);
+
+        assert_eq!(
+            faces_across_borders(location, Vector3::new(15, 0, 3)),
+            vec![(Vector2::new(1, 0), Vector3::new(0, 0, 3), Direction::LEFT)],
+        );
+        assert_eq!(
+            faces_across_borders(location, Vector3::new(0, 0, 3)),
+            vec![(Vector2::new(-1, 0), Vector3::new(15, 0, 3), Direction::RIGHT)],
+        );
+
+        // A corner block touches two neighbors, but never the diagonal one
+        assert_eq!(
+            faces_across_borders(Vector2::new(-1, -1), Vector3::new(0, 5, 15)),
+            vec![
+                (Vector2::new(-1, 0), Vector3::new(0, 5, 0), Direction::BACK),
+                (Vector2::new(-2, -1), Vector3::new(15, 5, 15), Direction::RIGHT),
+            ],
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn a_world_that_did_not_change_uploads_nothing() {
+        let renderer = renderer::tests::headless();
+        let location = Vector2::new(0, 0);
+        let mut world = air_world(&renderer, &[location, Vector2::new(1, 0)]);
+        assert_eq!(world.update_buffers(&renderer.device, &renderer.queue), 0);
+
+        // Only the edited chunk is uploaded, and only once
+        world.set_block(location, Vector3::new(4, 0, 4), Block::new_stone());
+        assert_eq!(world.dirty_chunk_count(), 1);
+        assert_eq!(world.update_buffers(&renderer.device, &renderer.queue), 1);
+        assert_eq!(world.update_buffers(&renderer.device, &renderer.queue), 0);
+        assert_eq!(world.dirty_chunk_count(), 0);
     }
 }